@@ -0,0 +1,197 @@
+use crate::error::RGitError;
+use crate::hash::ObjectId;
+use crate::objects::{Blob, EntryType, ObjectDatabase, Tree};
+use crate::utils::quote_path;
+use anyhow::Result;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::ops::Bound;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+
+/// The files of a tree, subtrees excluded, by path.
+type Files = BTreeMap<Vec<u8>, (EntryType, ObjectId)>;
+
+fn tree_files(tree: Option<&Tree>) -> Result<Files> {
+    let mut files = BTreeMap::new();
+    if let Some(tree) = tree {
+        tree.walk(&[], &mut |entry| {
+            if entry.entry_type() != EntryType::Tree {
+                files.insert(entry.path().to_vec(), (entry.entry_type(), *entry.hash()));
+            }
+            Ok(true)
+        })?;
+    }
+    Ok(files)
+}
+
+/// Returns true when `path` is `dir` or below it.
+fn overwrite_error(local: &BTreeSet<&[u8]>, untracked: &BTreeSet<&[u8]>) -> anyhow::Error {
+    let list = |paths: &BTreeSet<&[u8]>| {
+        paths
+            .iter()
+            .map(|path| format!("\t{}\n", quote_path(path)))
+            .collect::<String>()
+    };
+    let mut message = String::new();
+    if !local.is_empty() {
+        message.push_str(&format!(
+            "error: Your local changes to the following files would be overwritten by checkout:\n{}\
+             Please commit your changes or stash them before you switch branches.\n",
+            list(local)
+        ));
+    }
+    if !untracked.is_empty() {
+        message.push_str(&format!(
+            "error: The following untracked working tree files would be overwritten by checkout:\n{}\
+             Please move or remove them before you switch branches.\n",
+            list(untracked)
+        ));
+    }
+    message.push_str("Aborting");
+    RGitError::new(message, 1)
+}
+
+/// Removes `path`, whatever it is, when it exists.
+fn remove_path(path: &Path) -> Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(path)?,
+        Ok(_) => fs::remove_file(path)?,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err.into()),
+    }
+    Ok(())
+}
+
+/// Removes the empty directories from the parent of `path` up to, but excluding, `work_dir`.
+fn remove_empty_parents(work_dir: &Path, path: &Path) -> Result<()> {
+    let mut dir = path.parent();
+    while let Some(parent) = dir.filter(|dir| *dir != work_dir) {
+        if fs::read_dir(parent)?.next().is_some() {
+            break;
+        }
+        fs::remove_dir(parent)?;
+        dir = parent.parent();
+    }
+    Ok(())
+}
+
+fn write_file(
+    db: &ObjectDatabase,
+    work_dir: &Path,
+    path: &Path,
+    entry_type: EntryType,
+    hash: &ObjectId,
+) -> Result<()> {
+    // an untracked file may stand where a directory is needed when forced
+    let mut parent = path.parent();
+    while let Some(dir) = parent.filter(|dir| *dir != work_dir) {
+        if fs::symlink_metadata(dir).is_ok_and(|metadata| !metadata.is_dir()) {
+            fs::remove_file(dir)?;
+        }
+        parent = dir.parent();
+    }
+    fs::create_dir_all(path.parent().unwrap())?;
+    remove_path(path)?;
+
+    match entry_type {
+        EntryType::Regular => Blob::from_rgit_objects(db, hash)?.write_to_file(path),
+        EntryType::Executable => Blob::from_rgit_objects(db, hash)?.write_to_executable(path),
+        EntryType::Symlink => Blob::from_rgit_objects(db, hash)?.write_to_symlink(path),
+        // the nested repository is not cloned, only its directory is created
+        EntryType::Gitlink => Ok(fs::create_dir_all(path)?),
+        EntryType::Tree => unreachable!("subtrees are not files"),
+    }
+}
+
+/// Updates `work_dir`, whose current content is `work`, from the tree `from` to the tree `to`.
+///
+/// Only the files that differ between `from` and `to` are touched, local changes to the other
+/// ones are kept. Unless `force` is set, nothing is changed when a file to update has local
+/// changes, or when an untracked file is in the way; with `force` every tracked file is reset to
+/// its content in `to`.
+pub fn checkout_tree(
+    db: &ObjectDatabase,
+    work_dir: &Path,
+    work: &Tree,
+    from: Option<&Tree>,
+    to: &Tree,
+    force: bool,
+) -> Result<()> {
+    let work = tree_files(Some(work))?;
+    let from = tree_files(from)?;
+    let to = tree_files(Some(to))?;
+
+    let paths = from.keys().chain(to.keys()).collect::<BTreeSet<_>>();
+    let changed = paths
+        .into_iter()
+        .filter(|path| {
+            if force {
+                work.get(*path) != to.get(*path)
+            } else {
+                from.get(*path) != to.get(*path) && work.get(*path) != to.get(*path)
+            }
+        })
+        .collect::<Vec<_>>();
+
+    if !force {
+        let work_only = work
+            .keys()
+            .filter(|path| !from.contains_key(*path))
+            .map(Vec::as_slice)
+            .collect::<BTreeSet<_>>();
+        let mut local = BTreeSet::new();
+        let mut untracked = BTreeSet::new();
+        for path in changed.iter() {
+            if work.get(*path) != from.get(*path) {
+                if from.contains_key(*path) {
+                    local.insert(path.as_slice());
+                } else {
+                    untracked.insert(path.as_slice());
+                }
+            }
+            if to.contains_key(*path) {
+                // untracked files in a directory that becomes a file
+                let dir = [path.as_slice(), b"/"].concat();
+                untracked.extend(
+                    work_only
+                        .range::<[u8], _>((Bound::Included(dir.as_slice()), Bound::Unbounded))
+                        .take_while(|work_path| work_path.starts_with(&dir)),
+                );
+                // and untracked files that become directories
+                for (i, _) in path.iter().enumerate().filter(|(_, &byte)| byte == b'/') {
+                    if let Some(work_path) = work_only.get(&path[..i]) {
+                        untracked.insert(work_path);
+                    }
+                }
+            }
+        }
+        if !local.is_empty() || !untracked.is_empty() {
+            return Err(overwrite_error(&local, &untracked));
+        }
+    }
+
+    // removals first so that directories that become files are empty by the time they are written
+    for path in changed.iter().filter(|path| !to.contains_key(**path)) {
+        let file_path = work_dir.join(OsStr::from_bytes(path));
+        match from.get(*path) {
+            Some((EntryType::Gitlink, _)) => {
+                // the files of a nested repository are not ours to delete
+                if fs::read_dir(&file_path).is_ok_and(|mut entries| entries.next().is_none()) {
+                    fs::remove_dir(&file_path)?;
+                }
+            }
+            _ => remove_path(&file_path)?,
+        }
+        remove_empty_parents(work_dir, &file_path)?;
+    }
+    for path in changed {
+        if let Some((entry_type, hash)) = to.get(path) {
+            let file_path = work_dir.join(OsStr::from_bytes(path));
+            write_file(db, work_dir, &file_path, *entry_type, hash)?;
+        }
+    }
+    Ok(())
+}
@@ -1,5 +1,9 @@
-use crate::commands::{CatFileArgs, CheckIgnoreArgs, CommitArgs, HashObjectArgs, ReadTreeArgs};
 use clap::{Parser, Subcommand};
+use rgit::commands::{
+    BranchArgs, CatFileArgs, CheckIgnoreArgs, CheckoutArgs, CloneArgs, CommitArgs, FsckArgs,
+    GcArgs, HashObjectArgs, InitArgs, PackRefsArgs, PruneArgs, ReadTreeArgs, ReflogArgs,
+    RepackArgs, RevParseArgs, SwitchArgs, SymbolicRefArgs, TagArgs, UpdateRefArgs,
+};
 
 #[derive(Parser, Debug)]
 #[clap(version, author, about)]
@@ -11,7 +15,10 @@ pub struct RustGitArgs {
 #[derive(Subcommand, Debug)]
 pub enum RustGitSubCommands {
     #[clap(name = "init")]
-    Init,
+    Init(InitArgs),
+
+    #[clap(name = "clone")]
+    Clone(CloneArgs),
 
     #[clap(name = "hash-object")]
     HashObject(HashObjectArgs),
@@ -30,4 +37,43 @@ pub enum RustGitSubCommands {
 
     #[clap(name = "commit")]
     Commit(CommitArgs),
+
+    #[clap(name = "tag")]
+    Tag(TagArgs),
+
+    #[clap(name = "branch")]
+    Branch(BranchArgs),
+
+    #[clap(name = "switch")]
+    Switch(SwitchArgs),
+
+    #[clap(name = "checkout")]
+    Checkout(CheckoutArgs),
+
+    #[clap(name = "reflog")]
+    Reflog(ReflogArgs),
+
+    #[clap(name = "update-ref")]
+    UpdateRef(UpdateRefArgs),
+
+    #[clap(name = "symbolic-ref")]
+    SymbolicRef(SymbolicRefArgs),
+
+    #[clap(name = "pack-refs")]
+    PackRefs(PackRefsArgs),
+
+    #[clap(name = "rev-parse")]
+    RevParse(RevParseArgs),
+
+    #[clap(name = "repack")]
+    Repack(RepackArgs),
+
+    #[clap(name = "gc")]
+    Gc(GcArgs),
+
+    #[clap(name = "fsck")]
+    Fsck(FsckArgs),
+
+    #[clap(name = "prune")]
+    Prune(PruneArgs),
 }
@@ -0,0 +1,391 @@
+use crate::error::RGitError;
+use crate::objects::{is_ancestor, RGitObjectType};
+use crate::refs::{
+    check_ref_name, list_refs, read_ref, rename_ref, update_ref, write_symbolic_ref, RefTransaction,
+};
+use crate::revision::{abbreviate, peel};
+use crate::Repository;
+use anyhow::Result;
+use clap::Parser;
+use std::env;
+use std::io;
+use std::path::Path;
+
+/// List, create, delete or rename branches
+#[derive(Parser, Debug)]
+pub struct BranchArgs {
+    /// Delete branches, which must be merged into HEAD
+    #[arg(short, long, conflicts_with = "rename")]
+    pub delete: bool,
+
+    /// Delete branches even when they are not merged
+    #[arg(short = 'D', conflicts_with_all = ["delete", "rename"])]
+    pub force_delete: bool,
+
+    /// Rename a branch, the current one when only the new name is given
+    #[arg(short = 'm', long = "move")]
+    pub rename: bool,
+
+    /// The branch to create and its start point (HEAD by default), the branches to delete, or
+    /// the old and new names of the branch to rename. The branches are listed when omitted.
+    pub names: Vec<String>,
+}
+
+/// Returns the full ref name of the branch `name`, checking that it is a valid branch name.
+pub(super) fn branch_ref(name: &str) -> Result<String> {
+    let ref_name = format!("refs/heads/{}", name);
+    if name == "HEAD" || name.starts_with('-') || !check_ref_name(&ref_name) {
+        return Err(RGitError::new(
+            format!("fatal: '{}' is not a valid branch name", name),
+            128,
+        ));
+    }
+    Ok(ref_name)
+}
+
+fn list_branches(dir: &Path, writer: &mut dyn io::Write) -> Result<u8> {
+    let repo = Repository::discover(dir)?;
+    let head_branch = repo.head_branch()?;
+    if head_branch.is_none() {
+        if let Some(head) = repo.head()? {
+            writeln!(
+                writer,
+                "* (HEAD detached at {})",
+                abbreviate(repo.db(), &head)?
+            )?;
+        }
+    }
+
+    let mut branches = list_refs(repo.rgit_dir())?
+        .into_iter()
+        .filter(|(name, _)| name.starts_with("refs/heads/"))
+        .map(|(name, _)| name)
+        .collect::<Vec<_>>();
+    branches.sort();
+    for name in branches {
+        let marker = if head_branch.as_ref() == Some(&name) {
+            '*'
+        } else {
+            ' '
+        };
+        writeln!(writer, "{} {}", marker, &name["refs/heads/".len()..])?;
+    }
+    Ok(0)
+}
+
+/// Creates the branch `name` pointing to the commit `start_point` resolves to.
+fn create_branch(dir: &Path, name: &str, start_point: &str) -> Result<u8> {
+    let repo = Repository::discover(dir)?;
+    let ref_name = branch_ref(name)?;
+    if read_ref(repo.rgit_dir(), &ref_name)?.is_some() {
+        return Err(RGitError::new(
+            format!("fatal: a branch named '{}' already exists", name),
+            128,
+        ));
+    }
+
+    let hash = repo.resolve(start_point)?;
+    let hash = peel(repo.db(), start_point, hash, RGitObjectType::Commit)?;
+    let message = format!("branch: Created from {}", start_point);
+    update_ref(repo.rgit_dir(), &ref_name, &hash, &message)?;
+    Ok(0)
+}
+
+/// Deletes the branches `names`. Unless `force` is set, only branches whose commit is reachable
+/// from `HEAD` are deleted. The branches that cannot be deleted are reported to `error_writer`.
+fn delete_branches(
+    dir: &Path,
+    names: &[String],
+    force: bool,
+    writer: &mut dyn io::Write,
+    error_writer: &mut dyn io::Write,
+) -> Result<u8> {
+    let repo = Repository::discover(dir)?;
+    let head_branch = repo.head_branch()?;
+    let head = repo.head()?;
+
+    let mut exit_code = 0;
+    for name in names {
+        let ref_name = format!("refs/heads/{}", name);
+        let Some(hash) = read_ref(repo.rgit_dir(), &ref_name)? else {
+            writeln!(error_writer, "error: branch '{}' not found.", name)?;
+            exit_code = 1;
+            continue;
+        };
+        if head_branch.as_ref() == Some(&ref_name) {
+            writeln!(
+                error_writer,
+                "error: Cannot delete branch '{}' checked out at '{}'",
+                name,
+                repo.work_dir().display()
+            )?;
+            exit_code = 1;
+            continue;
+        }
+        let merged = match head {
+            Some(head) => is_ancestor(repo.db(), &hash, &head)?,
+            None => false,
+        };
+        if !force && !merged {
+            writeln!(
+                error_writer,
+                "error: The branch '{}' is not fully merged.\n\
+                 If you are sure you want to delete it, run 'rgit branch -D {}'.",
+                name, name
+            )?;
+            exit_code = 1;
+            continue;
+        }
+
+        // another process moving the branch since the merge check makes the delete fail
+        let mut transaction = RefTransaction::new(repo.rgit_dir());
+        transaction.no_deref().delete(&ref_name, Some(&hash));
+        transaction.commit()?;
+        writeln!(
+            writer,
+            "Deleted branch {} (was {}).",
+            name,
+            abbreviate(repo.db(), &hash)?
+        )?;
+    }
+    Ok(exit_code)
+}
+
+/// Renames the branch `old`, the current branch when `None`, to `new`. `HEAD` follows the branch
+/// when it is the current one.
+fn rename_branch(dir: &Path, old: Option<&str>, new: &str) -> Result<u8> {
+    let repo = Repository::discover(dir)?;
+    let head_branch = repo.head_branch()?;
+    let old_ref = match old {
+        Some(old) => format!("refs/heads/{}", old),
+        None => head_branch.clone().ok_or_else(|| {
+            RGitError::new(
+                "fatal: cannot rename the current branch while not on any branch".to_string(),
+                128,
+            )
+        })?,
+    };
+    let is_head = head_branch.as_ref() == Some(&old_ref);
+    let hash = read_ref(repo.rgit_dir(), &old_ref)?;
+    // the current branch can be renamed before its first commit
+    if hash.is_none() && !is_head {
+        return Err(RGitError::new(
+            format!(
+                "fatal: no branch named '{}'",
+                &old_ref["refs/heads/".len()..]
+            ),
+            128,
+        ));
+    }
+
+    let new_ref = branch_ref(new)?;
+    if new_ref != old_ref && read_ref(repo.rgit_dir(), &new_ref)?.is_some() {
+        return Err(RGitError::new(
+            format!("fatal: a branch named '{}' already exists", new),
+            128,
+        ));
+    }
+
+    if hash.is_some() && new_ref != old_ref {
+        let message = format!("Branch: renamed {} to {}", old_ref, new_ref);
+        rename_ref(repo.rgit_dir(), &old_ref, &new_ref, &message)?;
+    }
+    if is_head {
+        write_symbolic_ref(repo.rgit_dir(), "HEAD", &new_ref)?;
+    }
+    Ok(0)
+}
+
+pub fn rgit_branch(args: &BranchArgs) -> Result<u8> {
+    let dir = env::current_dir()?;
+    let names = args.names.iter().map(String::as_str).collect::<Vec<_>>();
+
+    if args.delete || args.force_delete {
+        if names.is_empty() {
+            return Err(RGitError::new(
+                "fatal: branch name required".to_string(),
+                128,
+            ));
+        }
+        return delete_branches(
+            &dir,
+            &args.names,
+            args.force_delete,
+            &mut io::stdout(),
+            &mut io::stderr(),
+        );
+    }
+    if args.rename {
+        return match names.as_slice() {
+            [new] => rename_branch(&dir, None, new),
+            [old, new] => rename_branch(&dir, Some(old), new),
+            [] => Err(RGitError::new(
+                "fatal: branch name required".to_string(),
+                128,
+            )),
+            _ => Err(RGitError::new(
+                "fatal: too many arguments for a rename operation".to_string(),
+                128,
+            )),
+        };
+    }
+    match names.as_slice() {
+        [] => list_branches(&dir, &mut io::stdout()),
+        [name] => create_branch(&dir, name, "HEAD"),
+        [name, start_point] => create_branch(&dir, name, start_point),
+        _ => Err(RGitError::new(
+            "fatal: too many arguments to create a branch".to_string(),
+            128,
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::refs::{read_ref_target, RefTarget};
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn list(dir: &Path) -> String {
+        let mut buffer = Vec::new();
+        list_branches(dir, &mut buffer).unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    #[test]
+    fn test_branch() {
+        let dir = tempdir().unwrap();
+        let repo = Repository::init(dir.path(), None).unwrap();
+        assert_eq!(
+            read_ref_target(repo.rgit_dir(), "HEAD").unwrap(),
+            Some(RefTarget::Symbolic("refs/heads/main".to_string()))
+        );
+        // an unborn branch is not listed
+        assert_eq!(list(dir.path()), "");
+        assert!(create_branch(dir.path(), "topic", "HEAD").is_err());
+
+        fs::write(dir.path().join("file"), "first").unwrap();
+        let first = repo.commit("first").unwrap();
+        assert_eq!(
+            read_ref(repo.rgit_dir(), "refs/heads/main").unwrap(),
+            Some(first)
+        );
+        create_branch(dir.path(), "topic", "HEAD").unwrap();
+        create_branch(dir.path(), "feature/x", &first.to_string()).unwrap();
+        assert_eq!(list(dir.path()), "  feature/x\n* main\n  topic\n");
+
+        let result = create_branch(dir.path(), "topic", "HEAD");
+        assert!(result.unwrap_err().to_string().contains("already exists"));
+        for name in ["HEAD", "-x", "a..b", "a b"] {
+            let result = create_branch(dir.path(), name, "HEAD");
+            assert!(result
+                .unwrap_err()
+                .to_string()
+                .contains("not a valid branch name"));
+        }
+
+        // commits advance the current branch only
+        fs::write(dir.path().join("file"), "second").unwrap();
+        let second = repo.commit("second").unwrap();
+        assert_eq!(repo.head().unwrap(), Some(second));
+        assert_eq!(
+            read_ref(repo.rgit_dir(), "refs/heads/main").unwrap(),
+            Some(second)
+        );
+        assert_eq!(
+            read_ref(repo.rgit_dir(), "refs/heads/topic").unwrap(),
+            Some(first)
+        );
+
+        // a detached HEAD holds a commit ID and commits move it
+        fs::write(repo.rgit_dir().join("HEAD"), format!("{}\n", first)).unwrap();
+        assert_eq!(repo.head_branch().unwrap(), None);
+        assert!(list(dir.path()).starts_with(&format!(
+            "* (HEAD detached at {})\n",
+            abbreviate(repo.db(), &first).unwrap()
+        )));
+        fs::write(dir.path().join("file"), "detached").unwrap();
+        let detached = repo.commit("detached").unwrap();
+        assert_eq!(repo.head().unwrap(), Some(detached));
+        assert_eq!(
+            read_ref(repo.rgit_dir(), "refs/heads/main").unwrap(),
+            Some(second)
+        );
+        write_symbolic_ref(repo.rgit_dir(), "HEAD", "refs/heads/topic").unwrap();
+
+        // deleting requires the branch to be merged into HEAD
+        let (mut buffer, mut errors) = (Vec::new(), Vec::new());
+        let names = ["main".to_string(), "nope".to_string(), "topic".to_string()];
+        assert_eq!(
+            delete_branches(dir.path(), &names, false, &mut buffer, &mut errors).unwrap(),
+            1
+        );
+        assert!(buffer.is_empty());
+        let output = String::from_utf8(errors).unwrap();
+        assert!(output.contains("error: The branch 'main' is not fully merged."));
+        assert!(output.contains("error: branch 'nope' not found."));
+        assert!(output.contains("error: Cannot delete branch 'topic' checked out at"));
+        assert_eq!(
+            read_ref(repo.rgit_dir(), "refs/heads/main").unwrap(),
+            Some(second)
+        );
+
+        let mut buffer = Vec::new();
+        let names = ["feature/x".to_string()];
+        assert_eq!(
+            delete_branches(dir.path(), &names, false, &mut buffer, &mut Vec::new()).unwrap(),
+            0
+        );
+        assert!(String::from_utf8(buffer)
+            .unwrap()
+            .starts_with("Deleted branch feature/x (was "));
+        assert!(!repo.rgit_dir().join("refs/heads/feature").exists());
+        let names = ["main".to_string()];
+        assert_eq!(
+            delete_branches(dir.path(), &names, true, &mut Vec::new(), &mut Vec::new()).unwrap(),
+            0
+        );
+        assert_eq!(list(dir.path()), "* topic\n");
+
+        // renaming the current branch moves HEAD along
+        rename_branch(dir.path(), None, "renamed").unwrap();
+        assert_eq!(
+            repo.head_branch().unwrap(),
+            Some("refs/heads/renamed".to_string())
+        );
+        assert_eq!(repo.head().unwrap(), Some(first));
+        create_branch(dir.path(), "other", "HEAD").unwrap();
+        let result = rename_branch(dir.path(), Some("other"), "renamed");
+        assert!(result.unwrap_err().to_string().contains("already exists"));
+        let result = rename_branch(dir.path(), Some("nope"), "x");
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("no branch named 'nope'"));
+        rename_branch(dir.path(), Some("other"), "moved").unwrap();
+        assert_eq!(list(dir.path()), "  moved\n* renamed\n");
+
+        // a branch can move below its own name, and stays when the new name is taken
+        rename_branch(dir.path(), Some("moved"), "moved/x").unwrap();
+        assert_eq!(list(dir.path()), "  moved/x\n* renamed\n");
+        assert!(repo.rgit_dir().join("logs/refs/heads/moved/x").is_file());
+        assert!(rename_branch(dir.path(), None, "moved/x/y").is_err());
+        assert_eq!(list(dir.path()), "  moved/x\n* renamed\n");
+        assert!(repo.rgit_dir().join("logs/refs/heads/renamed").is_file());
+    }
+
+    #[test]
+    fn test_rename_unborn_branch() {
+        let dir = tempdir().unwrap();
+        let repo = Repository::init(dir.path(), None).unwrap();
+        rename_branch(dir.path(), None, "trunk").unwrap();
+        fs::write(dir.path().join("file"), "content").unwrap();
+        let commit = repo.commit("first").unwrap();
+        assert_eq!(
+            read_ref(repo.rgit_dir(), "refs/heads/trunk").unwrap(),
+            Some(commit)
+        );
+        assert_eq!(read_ref(repo.rgit_dir(), "refs/heads/main").unwrap(), None);
+    }
+}
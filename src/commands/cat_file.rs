@@ -1,6 +1,5 @@
-use crate::hash::hash_array_from_str;
 use crate::objects::from_rgit_objects;
-use crate::utils::get_rgit_dir;
+use crate::Repository;
 use anyhow::Result;
 use clap::{ArgGroup, Parser};
 use std::env;
@@ -35,10 +34,10 @@ fn cat_file(
     p: bool,
     writer: &mut dyn io::Write,
 ) -> Result<u8> {
-    let rgit_dir = get_rgit_dir(dir)?;
-    let hash_array = hash_array_from_str(&object)?;
+    let repo = Repository::discover(dir)?;
+    let hash = repo.resolve(&object)?;
 
-    let rgit_object = from_rgit_objects(rgit_dir.as_path(), &hash_array)?;
+    let rgit_object = from_rgit_objects(repo.db(), &hash)?;
     if t {
         writeln!(writer, "{}", rgit_object.object_type())?;
     } else if s {
@@ -64,7 +63,8 @@ pub fn rgit_cat_file(args: &CatFileArgs) -> Result<u8> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::objects::Blob;
+    use crate::hash::ObjectFormat;
+    use crate::objects::{Blob, ObjectDatabase};
     use crate::utils::init_rgit_dir;
     use std::fs;
     use tempfile::tempdir;
@@ -89,14 +89,14 @@ mod tests {
             .to_string()
             .contains("fatal: not a rgit repository"));
 
-        let rgit_dir = init_rgit_dir(dir.path()).unwrap();
+        let db = ObjectDatabase::open(&init_rgit_dir(dir.path()).unwrap()).unwrap();
 
         let file_path = dir.path().join("test.txt");
         let content = "Hello, World!";
         fs::write(&file_path, content).unwrap();
-        let blob = Blob::from_file(&file_path).unwrap();
+        let blob = Blob::from_file(&file_path, ObjectFormat::Sha1).unwrap();
         let hash = blob.hash();
-        blob.write_to_rgit_objects(rgit_dir.as_path()).unwrap();
+        blob.write_to_rgit_objects(&db).unwrap();
 
         let mut buffer = Vec::new();
         let result = cat_file(
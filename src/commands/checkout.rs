@@ -0,0 +1,111 @@
+use crate::commands::switch::{expand_previous, head_name, switch_branch};
+use crate::objects::{Commit, RGitObjectType};
+use crate::refs::{read_ref, RefTarget};
+use crate::revision::{abbreviate, peel};
+use crate::Repository;
+use anyhow::Result;
+use clap::Parser;
+use std::env;
+use std::io;
+use std::path::Path;
+
+/// Switch branches, or check out a commit on a detached HEAD
+#[derive(Parser, Debug)]
+pub struct CheckoutArgs {
+    /// Check out even if files with local changes have to be overwritten, discarding the changes
+    #[arg(short, long)]
+    pub force: bool,
+
+    /// The branch to switch to, or the commit to check out
+    pub target: String,
+}
+
+/// Switches to the branch `target`, or detaches `HEAD` at the commit `target` names.
+fn checkout(dir: &Path, target: &str, force: bool, writer: &mut dyn io::Write) -> Result<u8> {
+    let repo = Repository::discover(dir)?;
+    let target = &expand_previous(&repo, target)?;
+    if read_ref(repo.rgit_dir(), &format!("refs/heads/{}", target))?.is_some() {
+        return switch_branch(&repo, target, force, writer);
+    }
+
+    let hash = peel(
+        repo.db(),
+        target,
+        repo.resolve(target)?,
+        RGitObjectType::Commit,
+    )?;
+    repo.checkout(&hash, force)?;
+    let message = format!("checkout: moving from {} to {}", head_name(&repo)?, target);
+    repo.set_head(&RefTarget::Direct(hash), &message)?;
+    let commit = Commit::from_rgit_objects(repo.db(), &hash)?;
+    writeln!(
+        writer,
+        "HEAD is now at {} {}",
+        abbreviate(repo.db(), &hash)?,
+        commit.commit_message.lines().next().unwrap_or_default()
+    )?;
+    Ok(0)
+}
+
+pub fn rgit_checkout(args: &CheckoutArgs) -> Result<u8> {
+    checkout(
+        &env::current_dir()?,
+        &args.target,
+        args.force,
+        &mut io::stdout(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_checkout() {
+        let dir = tempdir().unwrap();
+        let path = dir.path();
+        let repo = Repository::init(path, None).unwrap();
+        fs::write(path.join("file"), "first").unwrap();
+        let first = repo.commit("first\n\nbody").unwrap();
+        fs::write(path.join("file"), "second").unwrap();
+        let second = repo.commit("second").unwrap();
+
+        let mut buffer = Vec::new();
+        checkout(path, &first.to_string()[..7], false, &mut buffer).unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            format!("HEAD is now at {} first\n", &first.to_string()[..7])
+        );
+        assert_eq!(repo.head_branch().unwrap(), None);
+        assert_eq!(repo.head().unwrap(), Some(first));
+        assert_eq!(fs::read_to_string(path.join("file")).unwrap(), "first");
+
+        fs::write(path.join("file"), "local change").unwrap();
+        assert!(checkout(path, "main", false, &mut Vec::new()).is_err());
+        assert_eq!(repo.head().unwrap(), Some(first));
+
+        let mut buffer = Vec::new();
+        checkout(path, "main", true, &mut buffer).unwrap();
+        assert_eq!(buffer, b"Switched to branch 'main'\n");
+        assert_eq!(repo.head().unwrap(), Some(second));
+        assert_eq!(fs::read_to_string(path.join("file")).unwrap(), "second");
+
+        // `-` goes back to the detached commit
+        let mut buffer = Vec::new();
+        checkout(path, "-", false, &mut buffer).unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            format!("HEAD is now at {} first\n", &first.to_string()[..7])
+        );
+        checkout(path, "-", false, &mut Vec::new()).unwrap();
+        assert_eq!(
+            repo.head_branch().unwrap(),
+            Some("refs/heads/main".to_string())
+        );
+
+        assert!(checkout(path, "nope", false, &mut Vec::new()).is_err());
+        assert!(checkout(path, "HEAD^{tree}", false, &mut Vec::new()).is_err());
+    }
+}
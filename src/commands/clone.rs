@@ -0,0 +1,316 @@
+use crate::commands::init::init;
+use crate::config::Config;
+use crate::error::RGitError;
+use crate::objects::{read_alternates, Commit, LooseStore, ObjectDatabase, PackStore, Tree};
+use crate::refs::{
+    list_refs, read_ref, read_ref_target, replace_ref, update_ref, write_ref, write_symbolic_ref,
+    RefTarget,
+};
+use anyhow::Result;
+use clap::Parser;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Clone a local repository into a new directory
+#[derive(Parser, Debug)]
+pub struct CloneArgs {
+    /// Borrow the objects of a local repository through `objects/info/alternates` instead of
+    /// copying them
+    #[arg(long, value_name = "repository")]
+    pub reference: Option<String>,
+
+    /// The repository to clone from, either its work tree or its `.rgit` directory
+    pub repository: String,
+
+    /// The directory to clone into, the name of the repository by default
+    pub directory: Option<String>,
+}
+
+/// Returns the `.rgit` directory of the repository at `path`.
+fn find_rgit_dir(path: &Path) -> Result<PathBuf> {
+    let rgit_dir = if path.file_name().is_some_and(|name| name == ".rgit") {
+        path.to_path_buf()
+    } else {
+        path.join(".rgit")
+    };
+    if !rgit_dir.join("objects").is_dir() {
+        return Err(RGitError::new(
+            format!("fatal: repository '{}' does not exist", path.display()),
+            128,
+        ));
+    }
+    Ok(rgit_dir.canonicalize()?)
+}
+
+/// Clones the repository at `source` into `dir`.
+///
+/// Every alternate of the source is kept, and `reference` is added as another one, so that only
+/// the objects that can't be borrowed are copied. The borrowing repository breaks if objects
+/// disappear from an alternate, e.g. by pruning it.
+fn clone(
+    source: &Path,
+    dir: &Path,
+    reference: Option<&Path>,
+    writer: &mut dyn io::Write,
+) -> Result<u8> {
+    let source_rgit_dir = find_rgit_dir(source)?;
+    let source_db = ObjectDatabase::open(&source_rgit_dir)?;
+    let format = source_db.format();
+
+    let mut alternates = Vec::new();
+    if let Some(reference) = reference {
+        let reference_rgit_dir = find_rgit_dir(reference)?;
+        let reference_format = Config::load(&reference_rgit_dir)?.object_format()?;
+        if reference_format != format {
+            return Err(RGitError::new(
+                format!(
+                    "fatal: reference repository '{}' uses {}, but the repository uses {}",
+                    reference.display(),
+                    reference_format,
+                    format
+                ),
+                128,
+            ));
+        }
+        alternates.push(reference_rgit_dir.join("objects"));
+    }
+    alternates.extend(read_alternates(&source_rgit_dir.join("objects"))?);
+
+    if dir.exists() && dir.read_dir()?.next().is_some() {
+        return Err(RGitError::new(
+            format!(
+                "fatal: destination path '{}' already exists and is not an empty directory.",
+                dir.display()
+            ),
+            128,
+        ));
+    }
+    writeln!(writer, "Cloning into '{}'...", dir.display())?;
+    fs::create_dir_all(dir)?;
+    init(dir, Some(format), &mut io::sink())?;
+    let rgit_dir = dir.join(".rgit");
+
+    let objects_dir = rgit_dir.join("objects");
+    if !alternates.is_empty() {
+        fs::create_dir_all(objects_dir.join("info"))?;
+        let content = alternates
+            .iter()
+            .map(|alternate| format!("{}\n", alternate.display()))
+            .collect::<String>();
+        fs::write(objects_dir.join("info").join("alternates"), content)?;
+    }
+
+    // packs are copied whole unless every one of their objects can be borrowed
+    let db = ObjectDatabase::open(&rgit_dir)?;
+    let source_objects_dir = source_rgit_dir.join("objects");
+    let pack_store = PackStore::new(&objects_dir, format);
+    for pack in PackStore::new(&source_objects_dir, format).packs()? {
+        let mut borrowed = true;
+        for hash in pack.index().hashes() {
+            if !db.contains(hash)? {
+                borrowed = false;
+                break;
+            }
+        }
+        if borrowed {
+            continue;
+        }
+        fs::create_dir_all(pack_store.pack_dir())?;
+        for path in [pack.path().with_extension("idx"), pack.path().to_path_buf()] {
+            fs::copy(&path, pack_store.pack_dir().join(path.file_name().unwrap()))?;
+        }
+    }
+    let loose_store = LooseStore::new(&objects_dir);
+    for (hash, path) in LooseStore::new(&source_objects_dir).objects()? {
+        if db.contains(&hash)? {
+            continue;
+        }
+        let object_path = loose_store.object_path(&hash);
+        fs::create_dir_all(object_path.parent().unwrap())?;
+        fs::copy(&path, &object_path)?;
+    }
+
+    // the clone is on the branch the source is on, or detached at the same commit
+    let refs = list_refs(&source_rgit_dir)?;
+    let head = read_ref_target(&source_rgit_dir, "HEAD")?;
+    let head_branch = match &head {
+        Some(RefTarget::Symbolic(branch)) => Some(branch.as_str()),
+        _ => None,
+    };
+    for (name, hash) in refs.iter() {
+        if name != "HEAD" && Some(name.as_str()) != head_branch {
+            write_ref(&rgit_dir, name, hash)?;
+        }
+    }
+    let message = format!("clone: from {}", source.display());
+    match head {
+        Some(RefTarget::Symbolic(branch)) => {
+            write_symbolic_ref(&rgit_dir, "HEAD", &branch)?;
+            if let Some(hash) = read_ref(&source_rgit_dir, &branch)? {
+                update_ref(&rgit_dir, "HEAD", &hash, &message)?;
+            }
+        }
+        Some(target) => replace_ref(&rgit_dir, "HEAD", &target, &message)?,
+        None => {}
+    }
+
+    if let Some((_, head)) = refs.iter().find(|(name, _)| name == "HEAD") {
+        let commit = Commit::from_rgit_objects(&db, head)?;
+        Tree::from_rgit_objects(&db, commit.tree())?.write_to_directory(dir)?;
+    }
+
+    Ok(0)
+}
+
+pub fn rgit_clone(args: &CloneArgs) -> Result<u8> {
+    let current_dir = env::current_dir()?;
+    let source = current_dir.join(&args.repository);
+    let dir = match &args.directory {
+        Some(directory) => current_dir.join(directory),
+        None => {
+            let source = source.canonicalize()?;
+            let source = match source.file_name() {
+                Some(name) if name == ".rgit" => source.parent().unwrap().to_path_buf(),
+                _ => source,
+            };
+            let name = source.file_name().ok_or_else(|| {
+                RGitError::new(
+                    "fatal: No directory name could be guessed.\nPlease specify a directory on the command line"
+                        .to_string(),
+                    128,
+                )
+            })?;
+            current_dir.join(name)
+        }
+    };
+    let reference = args
+        .reference
+        .as_ref()
+        .map(|reference| current_dir.join(reference));
+
+    clone(&source, &dir, reference.as_deref(), &mut io::stdout())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::repack::repack;
+    use crate::hash::ObjectFormat;
+    use crate::objects::{Blob, ObjectStore};
+    use crate::utils::init_rgit_dir;
+    use tempfile::tempdir;
+
+    /// Commits the files of `dir` and points `HEAD` to the new commit.
+    fn commit_dir(dir: &Path, message: &str) -> Commit {
+        let db = ObjectDatabase::open(&dir.join(".rgit")).unwrap();
+        let tree = Tree::from_directory(dir, ObjectFormat::Sha1).unwrap();
+        tree.write_to_rgit_objects(&db).unwrap();
+        let commit = Commit::new(*tree.hash(), Vec::new(), message.to_string()).unwrap();
+        let commit_hash = commit.write_to_rgit_objects(&db).unwrap();
+        fs::write(dir.join(".rgit/HEAD"), hex::encode(commit_hash)).unwrap();
+        commit
+    }
+
+    #[test]
+    fn test_clone() {
+        let dir = tempdir().unwrap();
+        let source = dir.path().join("source");
+        fs::create_dir(&source).unwrap();
+        init_rgit_dir(&source).unwrap();
+        fs::write(source.join("file"), "file content").unwrap();
+        fs::create_dir(source.join("dir")).unwrap();
+        fs::write(source.join("dir/subfile"), "subfile content").unwrap();
+        commit_dir(&source, "commit");
+
+        let target = dir.path().join("target");
+        let mut buffer = Vec::new();
+        clone(&source, &target, None, &mut buffer).unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            format!("Cloning into '{}'...\n", target.display())
+        );
+        assert_eq!(
+            fs::read_to_string(target.join("dir/subfile")).unwrap(),
+            "subfile content"
+        );
+        assert_eq!(
+            list_refs(&target.join(".rgit")).unwrap(),
+            list_refs(&source.join(".rgit")).unwrap()
+        );
+        assert!(!target.join(".rgit/objects/info/alternates").exists());
+        assert_eq!(
+            LooseStore::new(&target.join(".rgit/objects"))
+                .objects()
+                .unwrap()
+                .len(),
+            5
+        );
+
+        let result = clone(&source, &target, None, &mut Vec::new());
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("already exists and is not an empty directory"));
+        let result = clone(&dir.path().join("missing"), &target, None, &mut Vec::new());
+        assert!(result.unwrap_err().to_string().contains("does not exist"));
+    }
+
+    #[test]
+    fn test_clone_reference() {
+        let dir = tempdir().unwrap();
+        let source = dir.path().join("source");
+        fs::create_dir(&source).unwrap();
+        init_rgit_dir(&source).unwrap();
+        fs::write(source.join("file"), "file content").unwrap();
+        commit_dir(&source, "commit");
+
+        // the reference only lacks the second commit and its tree and blob
+        let reference = dir.path().join("reference");
+        clone(&source, &reference, None, &mut Vec::new()).unwrap();
+        fs::write(source.join("other"), "other content").unwrap();
+        let commit = commit_dir(&source, "second commit");
+
+        let target = dir.path().join("target");
+        clone(&source, &target, Some(&reference), &mut Vec::new()).unwrap();
+        assert_eq!(
+            fs::read_to_string(target.join(".rgit/objects/info/alternates")).unwrap(),
+            format!(
+                "{}\n",
+                reference
+                    .join(".rgit/objects")
+                    .canonicalize()
+                    .unwrap()
+                    .display()
+            )
+        );
+        let store = LooseStore::new(&target.join(".rgit/objects"));
+        assert_eq!(store.objects().unwrap().len(), 3);
+        let db = ObjectDatabase::open(&target.join(".rgit")).unwrap();
+        assert_eq!(db.hashes().unwrap().len(), 6);
+        assert_eq!(db.without_alternates().hashes().unwrap().len(), 3);
+        assert_eq!(
+            fs::read_to_string(target.join("other")).unwrap(),
+            "other content"
+        );
+
+        // borrowed objects are not packed into the clone
+        let mut buffer = Vec::new();
+        repack(&target, 10, 50, &mut buffer).unwrap();
+        assert!(String::from_utf8(buffer)
+            .unwrap()
+            .contains("of 3 objects deltified"));
+
+        // a clone of the clone borrows from the reference as well
+        let nested = dir.path().join("nested");
+        clone(&target, &nested, None, &mut Vec::new()).unwrap();
+        let db = ObjectDatabase::open(&nested.join(".rgit")).unwrap();
+        let blob = Blob::from_file(&source.join("file"), ObjectFormat::Sha1).unwrap();
+        assert!(db.contains(blob.hash()).unwrap());
+        assert!(!db.without_alternates().contains(blob.hash()).unwrap());
+        assert!(!store.contains(blob.hash()).unwrap());
+        assert_eq!(db.without_alternates().hashes().unwrap().len(), 3);
+        Tree::from_rgit_objects(&db, commit.tree()).unwrap();
+    }
+}
@@ -0,0 +1,364 @@
+use crate::hash::ObjectId;
+use crate::objects::{
+    parse_tree_entries, Commit, EntryType, ObjectDatabase, RGitObjectHeader, RGitObjectType,
+    RawObject, Tag,
+};
+use crate::refs::{list_reflog_ids, list_refs};
+use crate::utils::get_rgit_dir;
+use anyhow::Result;
+use clap::Parser;
+use std::collections::{BTreeMap, HashSet};
+use std::env;
+use std::io;
+use std::path::Path;
+
+/// Verifies the connectivity and validity of the objects in the database
+#[derive(Parser, Debug)]
+pub struct FsckArgs {
+    /// Print objects that exist but that aren't reachable from any of the reference nodes,
+    /// instead of only the dangling ones
+    #[arg(long)]
+    pub unreachable: bool,
+}
+
+/// The objects a valid object refers to, with the type each of them must have.
+type Links = Vec<(RGitObjectType, ObjectId)>;
+
+/// Rehashes an object and parses it, returning its type and the objects it refers to.
+fn check_object(db: &ObjectDatabase, hash: &ObjectId) -> Result<(RGitObjectType, Links)> {
+    let raw = RawObject::from_rgit_objects(db, hash)?;
+    let computed = RGitObjectHeader::new(raw.object_type, raw.content.len())
+        .hash(raw.content.as_slice(), db.format())?;
+    if computed != *hash {
+        return Err(anyhow::anyhow!(
+            "hash mismatch, content hashes to {}",
+            hex::encode(computed)
+        ));
+    }
+
+    let links = match raw.object_type {
+        RGitObjectType::Blob => Vec::new(),
+        RGitObjectType::Tree => parse_tree_entries(&raw.content, db.format())?
+            .into_iter()
+            .filter_map(|(entry_type, _, entry_hash)| match entry_type {
+                EntryType::Tree => Some((RGitObjectType::Tree, entry_hash)),
+                // gitlinks point into another repository
+                EntryType::Gitlink => None,
+                _ => Some((RGitObjectType::Blob, entry_hash)),
+            })
+            .collect(),
+        RGitObjectType::Commit => {
+            let commit = Commit::deserialize(&raw.content, db.format())?;
+            let mut links = vec![(RGitObjectType::Tree, *commit.tree())];
+            links.extend(
+                commit
+                    .parents()
+                    .iter()
+                    .map(|parent| (RGitObjectType::Commit, *parent)),
+            );
+            links
+        }
+        RGitObjectType::Tag => {
+            let tag = Tag::deserialize(&raw.content, db.format())?;
+            vec![(tag.target_type(), *tag.object())]
+        }
+    };
+    Ok((raw.object_type, links))
+}
+
+/// Reports one problem per line as `<kind> <type> <id>`, or `error in <type> <id>: <reason>`
+/// for objects that fail validation.
+fn fsck(dir: &Path, unreachable: bool, writer: &mut dyn io::Write) -> Result<u8> {
+    let rgit_dir = get_rgit_dir(dir)?;
+    let db = ObjectDatabase::open(&rgit_dir)?;
+    let mut exit_code = 0;
+
+    // objects that failed validation have no links, they are still considered present
+    let mut objects = BTreeMap::new();
+    for hash in db.hashes()? {
+        match check_object(&db, &hash) {
+            Ok((object_type, links)) => {
+                objects.insert(hash, (Some(object_type), links));
+            }
+            Err(err) => {
+                let object_type = db
+                    .read_header(&hash)
+                    .map(|header| header.object_type.to_string())
+                    .unwrap_or_else(|_| "object".to_string());
+                writeln!(
+                    writer,
+                    "error in {} {}: {}",
+                    object_type,
+                    hex::encode(hash),
+                    err
+                )?;
+                objects.insert(hash, (None, Vec::new()));
+                exit_code = 1;
+            }
+        }
+    }
+
+    // links to objects of another type than the one they must have, such as a commit in a tree
+    for (hash, (object_type, links)) in objects.iter() {
+        for (link_type, link) in links.iter() {
+            match objects.get(link) {
+                Some((Some(actual_type), _)) if actual_type != link_type => {
+                    writeln!(
+                        writer,
+                        "error in {} {}: {} is a {}, not a {}",
+                        object_type.unwrap(),
+                        hex::encode(hash),
+                        hex::encode(link),
+                        actual_type,
+                        link_type
+                    )?;
+                    exit_code = 1;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let mut reachable = HashSet::new();
+    let mut missing = BTreeMap::new();
+    let mut stack = Vec::new();
+    for (name, hash) in list_refs(&rgit_dir)? {
+        if !objects.contains_key(&hash) {
+            writeln!(
+                writer,
+                "error: {}: invalid pointer {}",
+                name,
+                hex::encode(hash)
+            )?;
+            exit_code = 1;
+            continue;
+        }
+        stack.push(hash);
+    }
+    stack.extend(
+        list_reflog_ids(&rgit_dir)?
+            .into_iter()
+            .filter(|hash| objects.contains_key(hash)),
+    );
+    while let Some(hash) = stack.pop() {
+        if !reachable.insert(hash) {
+            continue;
+        }
+        for (link_type, link) in objects[&hash].1.iter() {
+            if objects.contains_key(link) {
+                stack.push(*link);
+            } else {
+                missing.insert(*link, *link_type);
+            }
+        }
+    }
+    for (hash, object_type) in missing.iter() {
+        writeln!(writer, "missing {} {}", object_type, hex::encode(hash))?;
+        exit_code = 1;
+    }
+
+    // dangling objects are the unreachable ones no other unreachable object refers to, objects
+    // of alternates are only checked for validity since other repositories may refer to them
+    let local = db
+        .without_alternates()
+        .hashes()?
+        .into_iter()
+        .collect::<HashSet<_>>();
+    let referenced = objects
+        .iter()
+        .filter(|(hash, _)| !reachable.contains(*hash))
+        .flat_map(|(_, (_, links))| links.iter().map(|(_, link)| *link))
+        .collect::<HashSet<_>>();
+    for (hash, (object_type, _)) in objects.iter() {
+        if reachable.contains(hash) || !local.contains(hash) {
+            continue;
+        }
+        let object_type = object_type.map_or("object".to_string(), |t| t.to_string());
+        if unreachable {
+            writeln!(writer, "unreachable {} {}", object_type, hex::encode(hash))?;
+        } else if !referenced.contains(hash) {
+            writeln!(writer, "dangling {} {}", object_type, hex::encode(hash))?;
+        }
+    }
+
+    Ok(exit_code)
+}
+
+pub fn rgit_fsck(args: &FsckArgs) -> Result<u8> {
+    fsck(&env::current_dir()?, args.unreachable, &mut io::stdout())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::ObjectFormat;
+    use crate::objects::{Blob, LooseStore, Tree};
+    use crate::utils::init_rgit_dir;
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use std::fs;
+    use std::io::Write;
+    use tempfile::tempdir;
+
+    fn run_fsck(dir: &Path, unreachable: bool) -> (u8, String) {
+        let mut buffer = Vec::new();
+        let exit_code = fsck(dir, unreachable, &mut buffer).unwrap();
+        (exit_code, String::from_utf8(buffer).unwrap())
+    }
+
+    /// Replaces a loose object with the given raw (uncompressed) object bytes.
+    fn overwrite_object(store: &LooseStore, hash: &ObjectId, object: &[u8]) {
+        let path = store.object_path(hash);
+        fs::remove_file(&path).unwrap();
+        let mut encoder =
+            ZlibEncoder::new(fs::File::create(&path).unwrap(), Compression::default());
+        encoder.write_all(object).unwrap();
+        encoder.finish().unwrap();
+    }
+
+    #[test]
+    fn test_fsck() {
+        let dir = tempdir().unwrap();
+        let rgit_dir = init_rgit_dir(dir.path()).unwrap();
+        let db = ObjectDatabase::open(&rgit_dir).unwrap();
+
+        fs::write(dir.path().join("file"), "file content").unwrap();
+        fs::create_dir(dir.path().join("dir")).unwrap();
+        fs::write(dir.path().join("dir/subfile"), "subfile content").unwrap();
+        let tree = Tree::from_directory(dir.path(), ObjectFormat::Sha1).unwrap();
+        tree.write_to_rgit_objects(&db).unwrap();
+        let commit = Commit::new(*tree.hash(), Vec::new(), "commit".to_string()).unwrap();
+        let commit_hash = commit.write_to_rgit_objects(&db).unwrap();
+        fs::write(rgit_dir.join("HEAD"), hex::encode(commit_hash)).unwrap();
+        assert_eq!(run_fsck(dir.path(), false), (0, String::new()));
+
+        // an orphan blob and a tree only referenced by an orphan commit
+        fs::remove_dir_all(dir.path().join("dir")).unwrap();
+        fs::write(dir.path().join("file"), "orphan content").unwrap();
+        let orphan = Blob::from_file(&dir.path().join("file"), ObjectFormat::Sha1).unwrap();
+        orphan.write_to_rgit_objects(&db).unwrap();
+        let orphan_tree = Tree::from_directory(dir.path(), ObjectFormat::Sha1).unwrap();
+        orphan_tree.write_to_rgit_objects(&db).unwrap();
+        let orphan_commit =
+            Commit::new(*orphan_tree.hash(), Vec::new(), "orphan".to_string()).unwrap();
+        let orphan_commit = orphan_commit.write_to_rgit_objects(&db).unwrap();
+
+        let (exit_code, output) = run_fsck(dir.path(), false);
+        assert_eq!(exit_code, 0);
+        assert_eq!(
+            output,
+            format!("dangling commit {}\n", hex::encode(orphan_commit))
+        );
+        let (exit_code, output) = run_fsck(dir.path(), true);
+        assert_eq!(exit_code, 0);
+        assert_eq!(output.lines().count(), 3);
+        assert!(output.contains(&format!("unreachable blob {}", hex::encode(orphan.hash()))));
+        assert!(output.contains(&format!(
+            "unreachable tree {}",
+            hex::encode(orphan_tree.hash())
+        )));
+    }
+
+    #[test]
+    fn test_fsck_wrong_link_types() {
+        let dir = tempdir().unwrap();
+        let rgit_dir = init_rgit_dir(dir.path()).unwrap();
+        let db = ObjectDatabase::open(&rgit_dir).unwrap();
+
+        fs::write(dir.path().join("file"), "file content").unwrap();
+        let blob = Blob::from_file(&dir.path().join("file"), ObjectFormat::Sha1).unwrap();
+        blob.write_to_rgit_objects(&db).unwrap();
+        // a commit whose tree is a blob, and a tree whose file is that commit
+        let commit = Commit::new(*blob.hash(), Vec::new(), "commit".to_string()).unwrap();
+        let commit_hash = commit.write_to_rgit_objects(&db).unwrap();
+        let mut content = b"100644 file\0".to_vec();
+        content.extend(commit_hash.as_bytes());
+        let tree = RGitObjectHeader::new(RGitObjectType::Tree, content.len())
+            .hash(content.as_slice(), ObjectFormat::Sha1)
+            .unwrap();
+        let object = RawObject {
+            object_type: RGitObjectType::Tree,
+            content,
+        };
+        db.write(&tree, &object).unwrap();
+        fs::write(rgit_dir.join("HEAD"), hex::encode(commit_hash)).unwrap();
+
+        let (exit_code, output) = run_fsck(dir.path(), false);
+        assert_eq!(exit_code, 1);
+        assert!(output.contains(&format!(
+            "error in commit {}: {} is a blob, not a tree",
+            hex::encode(commit_hash),
+            hex::encode(blob.hash())
+        )));
+        assert!(output.contains(&format!(
+            "error in tree {}: {} is a commit, not a blob",
+            hex::encode(tree),
+            hex::encode(commit_hash)
+        )));
+    }
+
+    #[test]
+    fn test_fsck_corrupt_and_missing_objects() {
+        let dir = tempdir().unwrap();
+        let rgit_dir = init_rgit_dir(dir.path()).unwrap();
+        let db = ObjectDatabase::open(&rgit_dir).unwrap();
+        let store = LooseStore::new(&rgit_dir.join("objects"));
+
+        fs::write(dir.path().join("file"), "file content").unwrap();
+        fs::create_dir(dir.path().join("dir")).unwrap();
+        fs::write(dir.path().join("dir/subfile"), "subfile content").unwrap();
+        let tree = Tree::from_directory(dir.path(), ObjectFormat::Sha1).unwrap();
+        tree.write_to_rgit_objects(&db).unwrap();
+        let commit = Commit::new(*tree.hash(), Vec::new(), "commit".to_string()).unwrap();
+        let commit_hash = commit.write_to_rgit_objects(&db).unwrap();
+        fs::write(rgit_dir.join("HEAD"), hex::encode(commit_hash)).unwrap();
+
+        // a blob whose content no longer matches its name, and one that is gone
+        let file = Blob::from_file(&dir.path().join("file"), ObjectFormat::Sha1).unwrap();
+        overwrite_object(&store, file.hash(), b"blob 12\0file CONTENT");
+        let subfile = Blob::from_file(&dir.path().join("dir/subfile"), ObjectFormat::Sha1).unwrap();
+        fs::remove_file(store.object_path(subfile.hash())).unwrap();
+
+        let (exit_code, output) = run_fsck(dir.path(), false);
+        assert_eq!(exit_code, 1);
+        assert!(output.contains(&format!(
+            "error in blob {}: hash mismatch",
+            hex::encode(file.hash())
+        )));
+        assert!(output.contains(&format!("missing blob {}", hex::encode(subfile.hash()))));
+
+        // a header that lies about the content size
+        overwrite_object(&store, file.hash(), b"blob 100\0file content");
+        let (_, output) = run_fsck(dir.path(), false);
+        assert!(output.contains("header declares 100 bytes but content has 12"));
+
+        // a tree with an invalid entry mode
+        let mut content = b"100640 file\0".to_vec();
+        content.extend(file.hash().as_bytes());
+        let mut object = format!("tree {}\0", content.len()).into_bytes();
+        object.extend(&content);
+        overwrite_object(&store, tree.hash(), &object);
+        // a commit without a time header
+        let content = format!("tree {}\n\ncommit", hex::encode(tree.hash()));
+        overwrite_object(
+            &store,
+            &commit_hash,
+            format!("commit {}\0{}", content.len(), content).as_bytes(),
+        );
+
+        let (exit_code, output) = run_fsck(dir.path(), false);
+        assert_eq!(exit_code, 1);
+        assert!(output.contains(&format!("error in tree {}", hex::encode(tree.hash()))));
+        assert!(output.contains(&format!("error in commit {}", hex::encode(commit_hash))));
+
+        // HEAD pointing to an object that does not exist
+        let invalid = ObjectId::from_bytes(&[1u8; 20]).unwrap();
+        fs::write(rgit_dir.join("HEAD"), hex::encode(invalid)).unwrap();
+        let (exit_code, output) = run_fsck(dir.path(), false);
+        assert_eq!(exit_code, 1);
+        assert!(output.contains(&format!(
+            "error: HEAD: invalid pointer {}",
+            hex::encode(invalid)
+        )));
+    }
+}
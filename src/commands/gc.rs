@@ -0,0 +1,95 @@
+use crate::commands::prune::{parse_expire, prune, DEFAULT_PRUNE_EXPIRE};
+use crate::commands::repack::repack;
+use crate::refs::pack_refs;
+use crate::Repository;
+use anyhow::Result;
+use clap::Parser;
+use std::env;
+use std::io;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// Cleanup unnecessary files and optimize the local repository
+#[derive(Parser, Debug)]
+pub struct GcArgs {
+    /// More aggressively optimize the repository at the expense of taking much more time
+    #[arg(long)]
+    pub aggressive: bool,
+
+    /// Prune loose objects older than <date>, `never` keeps every unreachable object
+    #[arg(long, default_value = DEFAULT_PRUNE_EXPIRE)]
+    pub prune: String,
+}
+
+fn gc(
+    dir: &Path,
+    aggressive: bool,
+    prune_expire: Option<SystemTime>,
+    writer: &mut dyn io::Write,
+) -> Result<u8> {
+    let repo = Repository::discover(dir)?;
+    pack_refs(repo.rgit_dir(), repo.db(), true, true)?;
+
+    let window = if aggressive { 250 } else { 10 };
+    let exit_code = repack(dir, window, 50, writer)?;
+    if exit_code != 0 {
+        return Ok(exit_code);
+    }
+    prune(dir, prune_expire, false, false, writer)
+}
+
+pub fn rgit_gc(args: &GcArgs) -> Result<u8> {
+    gc(
+        &env::current_dir()?,
+        args.aggressive,
+        parse_expire(&args.prune, SystemTime::now())?,
+        &mut io::stdout(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::ObjectFormat;
+    use crate::objects::{Blob, Commit, LooseStore, ObjectDatabase, Tree};
+    use crate::refs::{read_ref, write_ref};
+    use crate::utils::init_rgit_dir;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_gc() {
+        let dir = tempdir().unwrap();
+        let rgit_dir = init_rgit_dir(dir.path()).unwrap();
+        let db = ObjectDatabase::open(&rgit_dir).unwrap();
+
+        fs::write(dir.path().join("file"), "file content").unwrap();
+        let tree = Tree::from_directory(dir.path(), ObjectFormat::Sha1).unwrap();
+        tree.write_to_rgit_objects(&db).unwrap();
+        let commit = Commit::new(*tree.hash(), Vec::new(), "commit".to_string()).unwrap();
+        let hash = commit.write_to_rgit_objects(&db).unwrap();
+        fs::write(rgit_dir.join("HEAD"), hex::encode(hash)).unwrap();
+        write_ref(&rgit_dir, "refs/heads/main", &hash).unwrap();
+
+        // unreachable objects are pruned once packing is done
+        fs::write(dir.path().join("file"), "orphan").unwrap();
+        let orphan = Blob::from_file(&dir.path().join("file"), ObjectFormat::Sha1).unwrap();
+        orphan.write_to_rgit_objects(&db).unwrap();
+
+        let mut buffer = Vec::new();
+        let result = gc(dir.path(), true, Some(SystemTime::now()), &mut buffer).unwrap();
+        assert_eq!(result, 0);
+        let output = String::from_utf8(buffer).unwrap();
+        assert!(output.contains("Counting objects: 3, done."));
+        assert!(output.contains("Object storage: "));
+        assert!(LooseStore::new(&rgit_dir.join("objects"))
+            .objects()
+            .unwrap()
+            .is_empty());
+        assert!(!db.contains(orphan.hash()).unwrap());
+
+        // refs are packed too
+        assert!(!rgit_dir.join("refs/heads").exists());
+        assert_eq!(read_ref(&rgit_dir, "refs/heads/main").unwrap(), Some(hash));
+    }
+}
@@ -1,9 +1,9 @@
-use crate::objects::{Blob, RGitObject};
-use crate::utils::{get_rgit_dir, get_rgit_object_path};
+use crate::hash::ObjectFormat;
+use crate::objects::Blob;
+use crate::Repository;
 use anyhow::Result;
 use clap::Parser;
 use std::env;
-use std::fs;
 use std::io;
 use std::path;
 
@@ -23,14 +23,17 @@ fn hash_object(
     write: bool,
     writer: &mut dyn io::Write,
 ) -> Result<u8> {
-    let blob = Blob::from_file(&file)?;
+    // outside of a repository objects are hashed with SHA-1, like git does
+    let repo = match Repository::discover(dir) {
+        Ok(repo) => Some(repo),
+        Err(_) if !write => None,
+        Err(err) => return Err(err),
+    };
+    let format = repo.as_ref().map_or(ObjectFormat::Sha1, Repository::format);
+    let blob = Blob::from_file(file, format)?;
     let hash = blob.hash();
-    if write {
-        let rgit_object_path = get_rgit_object_path(get_rgit_dir(dir)?.as_path(), hash, false)?;
-        fs::create_dir_all(rgit_object_path.parent().unwrap())?;
-        blob.serialize(&mut io::BufWriter::new(fs::File::create(
-            &rgit_object_path,
-        )?))?;
+    if let Some(repo) = repo.filter(|_| write) {
+        blob.write_to_rgit_objects(repo.db())?;
     }
     writeln!(writer, "{}", hex::encode(hash))?;
     Ok(0)
@@ -61,7 +64,7 @@ mod tests {
         let mut buffer = Vec::new();
         let result = hash_object(
             dir.path(),
-            &path::Path::new("non-existing-file"),
+            path::Path::new("non-existing-file"),
             false,
             &mut buffer,
         );
@@ -71,7 +74,7 @@ mod tests {
             .unwrap()
             .downcast_ref::<RGitError>()
             .unwrap()
-            .message
+            .to_string()
             .contains("fatal: could not open"));
 
         // test existing file
@@ -90,7 +93,7 @@ mod tests {
             .unwrap()
             .downcast_ref::<RGitError>()
             .unwrap()
-            .message
+            .to_string()
             .contains("fatal: not a rgit repository"));
 
         init_rgit_dir(dir.path()).unwrap();
@@ -100,5 +103,11 @@ mod tests {
 
         let objects_dir = dir.path().join(".rgit/objects");
         assert!(objects_dir.exists());
+
+        // the object ID must match `echo -n "Hello, World!" | git hash-object --stdin`
+        assert_eq!(
+            String::from_utf8(buffer).unwrap().trim(),
+            "b45ef6fec89518d314f546fd6c3025367b721684"
+        );
     }
 }
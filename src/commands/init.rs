@@ -1,54 +1,96 @@
-use crate::utils::init_rgit_dir;
+use crate::hash::ObjectFormat;
+use crate::Repository;
 use anyhow::Result;
+use clap::Parser;
 use std::env;
-use std::fs;
 use std::io;
 use std::path;
 
-fn init(dir: &path::Path, writer: &mut dyn io::Write) -> Result<u8> {
-    let rgit_dir_exist = fs::metadata(&dir.join(".rgit")).is_ok();
-    let rgit_dir = init_rgit_dir(&dir)?;
+/// Create an empty RGit repository or reinitialize an existing one
+#[derive(Parser, Debug)]
+pub struct InitArgs {
+    /// Specify the hash algorithm to use, `sha1` (the default) or `sha256`
+    #[arg(long = "object-format", value_name = "format")]
+    pub object_format: Option<String>,
+}
+
+pub fn init(
+    dir: &path::Path,
+    object_format: Option<ObjectFormat>,
+    writer: &mut dyn io::Write,
+) -> Result<u8> {
+    let rgit_dir_exist = dir.join(".rgit").is_dir();
+    let repo = Repository::init(dir, object_format)?;
+
     if !rgit_dir_exist {
         writeln!(
             writer,
             "Initialized empty RGit repository in {}",
-            rgit_dir.display()
+            repo.rgit_dir().display()
         )?;
     } else {
         writeln!(
             writer,
             "Reinitialized existing RGit repository in {}",
-            rgit_dir.display()
+            repo.rgit_dir().display()
         )?;
     }
 
     Ok(0)
 }
 
-pub fn rgit_init() -> Result<u8> {
-    init(&env::current_dir()?, &mut io::stdout())
+pub fn rgit_init(args: &InitArgs) -> Result<u8> {
+    let object_format = args
+        .object_format
+        .as_deref()
+        .map(str::parse::<ObjectFormat>)
+        .transpose()?;
+    init(&env::current_dir()?, object_format, &mut io::stdout())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::Config;
     use tempfile::tempdir;
 
     #[test]
     fn test_rgit_init() {
         let dir = tempdir().unwrap();
         let mut buffer = Vec::new();
-        let result = init(dir.path(), &mut buffer).unwrap();
+        let result = init(dir.path(), None, &mut buffer).unwrap();
         assert_eq!(result, 0);
         assert!(String::from_utf8(buffer)
             .unwrap()
             .contains("Initialized empty RGit repository"));
 
         let mut buffer = Vec::new();
-        let result = init(dir.path(), &mut buffer).unwrap();
+        let result = init(dir.path(), None, &mut buffer).unwrap();
         assert_eq!(result, 0);
         assert!(String::from_utf8(buffer)
             .unwrap()
             .contains("Reinitialized existing RGit repository"));
+        let config = Config::load(&dir.path().join(".rgit")).unwrap();
+        assert_eq!(config.get("core.repositoryformatversion"), Some("0"));
+        assert_eq!(config.object_format().unwrap(), ObjectFormat::Sha1);
+    }
+
+    #[test]
+    fn test_rgit_init_sha256() {
+        let dir = tempdir().unwrap();
+        let mut buffer = Vec::new();
+        init(dir.path(), Some(ObjectFormat::Sha256), &mut buffer).unwrap();
+        let config = Config::load(&dir.path().join(".rgit")).unwrap();
+        assert_eq!(config.get("core.repositoryformatversion"), Some("1"));
+        assert_eq!(config.object_format().unwrap(), ObjectFormat::Sha256);
+
+        let mut buffer = Vec::new();
+        init(dir.path(), None, &mut buffer).unwrap();
+        init(dir.path(), Some(ObjectFormat::Sha256), &mut buffer).unwrap();
+        let result = init(dir.path(), Some(ObjectFormat::Sha1), &mut buffer);
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("attempt to reinitialize repository with different hash"));
     }
 }
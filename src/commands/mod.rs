@@ -1,15 +1,43 @@
+mod branch;
 mod cat_file;
 mod check_ignore;
+mod checkout;
+mod clone;
 mod commit;
+mod fsck;
+mod gc;
 mod hash_object;
 mod init;
+mod pack_refs;
+mod prune;
 mod read_tree;
+mod reflog;
+mod repack;
+mod rev_parse;
+mod switch;
+mod symbolic_ref;
+mod tag;
+mod update_ref;
 mod write_tree;
 
+pub use branch::{rgit_branch, BranchArgs};
 pub use cat_file::{rgit_cat_file, CatFileArgs};
 pub use check_ignore::{rgit_check_ignore, CheckIgnoreArgs};
+pub use checkout::{rgit_checkout, CheckoutArgs};
+pub use clone::{rgit_clone, CloneArgs};
 pub use commit::{rgit_commit, CommitArgs};
+pub use fsck::{rgit_fsck, FsckArgs};
+pub use gc::{rgit_gc, GcArgs};
 pub use hash_object::{rgit_hash_object, HashObjectArgs};
-pub use init::rgit_init;
+pub use init::{rgit_init, InitArgs};
+pub use pack_refs::{rgit_pack_refs, PackRefsArgs};
+pub use prune::{rgit_prune, PruneArgs};
 pub use read_tree::{rgit_read_tree, ReadTreeArgs};
+pub use reflog::{rgit_reflog, ReflogArgs};
+pub use repack::{rgit_repack, RepackArgs};
+pub use rev_parse::{rgit_rev_parse, RevParseArgs};
+pub use switch::{rgit_switch, SwitchArgs};
+pub use symbolic_ref::{rgit_symbolic_ref, SymbolicRefArgs};
+pub use tag::{rgit_tag, TagArgs};
+pub use update_ref::{rgit_update_ref, UpdateRefArgs};
 pub use write_tree::rgit_write_tree;
@@ -0,0 +1,61 @@
+use crate::refs::pack_refs;
+use crate::Repository;
+use anyhow::Result;
+use clap::Parser;
+use std::env;
+use std::path::Path;
+
+/// Pack refs into .rgit/packed-refs
+#[derive(Parser, Debug)]
+pub struct PackRefsArgs {
+    /// Pack every ref under refs/, not only tags
+    #[arg(long)]
+    pub all: bool,
+
+    /// Keep the loose files of the refs that are packed
+    #[arg(long)]
+    pub no_prune: bool,
+}
+
+fn pack(dir: &Path, all: bool, prune: bool) -> Result<u8> {
+    let repo = Repository::discover(dir)?;
+    pack_refs(repo.rgit_dir(), repo.db(), all, prune)?;
+    Ok(0)
+}
+
+pub fn rgit_pack_refs(args: &PackRefsArgs) -> Result<u8> {
+    pack(&env::current_dir()?, args.all, !args.no_prune)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::refs::{list_refs, read_packed_refs, write_ref};
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_pack_refs() {
+        let dir = tempdir().unwrap();
+        let path = dir.path();
+        let repo = Repository::init(path, None).unwrap();
+        fs::write(path.join("file"), "file").unwrap();
+        let commit = repo.commit("first").unwrap();
+        write_ref(repo.rgit_dir(), "refs/tags/v1", &commit).unwrap();
+        let refs = list_refs(repo.rgit_dir()).unwrap();
+
+        pack(path, true, false).unwrap();
+        assert_eq!(read_packed_refs(repo.rgit_dir()).unwrap().len(), 2);
+        assert!(repo.rgit_dir().join("refs/tags/v1").is_file());
+        pack(path, true, true).unwrap();
+        assert!(!repo.rgit_dir().join("refs/tags").exists());
+        assert!(!repo.rgit_dir().join("refs/heads").exists());
+        assert_eq!(list_refs(repo.rgit_dir()).unwrap(), refs);
+
+        // refs keep moving once packed
+        fs::write(path.join("file"), "changed").unwrap();
+        let second = repo.commit("second").unwrap();
+        assert_eq!(repo.head().unwrap(), Some(second));
+        assert_eq!(repo.resolve("v1").unwrap(), commit);
+    }
+}
@@ -0,0 +1,289 @@
+use crate::objects::{find_reachable_objects, LooseStore, ObjectDatabase};
+use crate::refs::{list_reflog_ids, list_refs};
+use crate::utils::get_rgit_dir;
+use anyhow::Result;
+use chrono::NaiveDate;
+use clap::Parser;
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+pub const DEFAULT_PRUNE_EXPIRE: &str = "2.weeks.ago";
+
+/// Prune all unreachable objects from the object database
+#[derive(Parser, Debug)]
+pub struct PruneArgs {
+    /// Do not remove anything; just report what it would remove
+    #[arg(short = 'n', long)]
+    pub dry_run: bool,
+
+    /// Report all removed objects
+    #[arg(short, long)]
+    pub verbose: bool,
+
+    /// Only expire loose objects older than <time>, e.g. `now`, `never`, `2.weeks.ago` or
+    /// `2024-01-31`
+    #[arg(long, default_value = DEFAULT_PRUNE_EXPIRE)]
+    pub expire: String,
+}
+
+/// Parses an expiry time the way git does for `--expire`. Returns `None` for `never`.
+pub fn parse_expire(expire: &str, now: SystemTime) -> Result<Option<SystemTime>> {
+    let invalid = || anyhow::anyhow!("fatal: malformed expiration date '{}'", expire);
+
+    match expire {
+        "never" => return Ok(None),
+        "now" => return Ok(Some(now)),
+        _ => {}
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(expire, "%Y-%m-%d") {
+        let timestamp = date.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp();
+        let timestamp = u64::try_from(timestamp).map_err(|_| invalid())?;
+        return Ok(Some(
+            SystemTime::UNIX_EPOCH + Duration::from_secs(timestamp),
+        ));
+    }
+
+    // `<n>.<unit>.ago`, `<n> <unit> ago` is accepted as well
+    let parts = expire.split(['.', ' ']).collect::<Vec<_>>();
+    if parts.len() != 3 || parts[2] != "ago" {
+        return Err(invalid());
+    }
+    let count = parts[0].parse::<u64>().map_err(|_| invalid())?;
+    let unit = match parts[1].trim_end_matches('s') {
+        "second" => 1,
+        "minute" => 60,
+        "hour" => 60 * 60,
+        "day" => 24 * 60 * 60,
+        "week" => 7 * 24 * 60 * 60,
+        "month" => 30 * 24 * 60 * 60,
+        "year" => 365 * 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+    // anything further back than the epoch expires everything
+    Ok(Some(
+        count
+            .checked_mul(unit)
+            .and_then(|secs| now.checked_sub(Duration::from_secs(secs)))
+            .unwrap_or(SystemTime::UNIX_EPOCH),
+    ))
+}
+
+/// Removes the loose objects that are not reachable from `HEAD`, any ref or any reflog entry and
+/// that were last modified at or before `expire`.
+///
+/// rgit has no index, so the refs and their reflogs are the only roots.
+pub fn prune(
+    dir: &Path,
+    expire: Option<SystemTime>,
+    dry_run: bool,
+    verbose: bool,
+    writer: &mut dyn io::Write,
+) -> Result<u8> {
+    let rgit_dir = get_rgit_dir(dir)?;
+    let db = ObjectDatabase::open(&rgit_dir)?;
+    let loose_store = LooseStore::new(&rgit_dir.join("objects"));
+    let Some(expire) = expire else {
+        return Ok(0);
+    };
+
+    // the previous positions of refs stay recoverable, as long as they were not already lost
+    let mut roots = list_refs(&rgit_dir)?
+        .into_iter()
+        .map(|(_, hash)| hash)
+        .collect::<Vec<_>>();
+    for hash in list_reflog_ids(&rgit_dir)? {
+        if db.contains(&hash)? {
+            roots.push(hash);
+        }
+    }
+    let reachable = find_reachable_objects(&db, &roots)?
+        .into_iter()
+        .map(|object| object.hash)
+        .collect::<HashSet<_>>();
+
+    for (hash, path) in loose_store.objects()? {
+        if reachable.contains(&hash) || fs::metadata(&path)?.modified()? > expire {
+            continue;
+        }
+        if dry_run || verbose {
+            let header = db.read_header(&hash)?;
+            writeln!(writer, "{} {}", hex::encode(hash), header.object_type)?;
+        }
+        if !dry_run {
+            loose_store.remove(&hash)?;
+        }
+    }
+
+    Ok(0)
+}
+
+pub fn rgit_prune(args: &PruneArgs) -> Result<u8> {
+    prune(
+        &env::current_dir()?,
+        parse_expire(&args.expire, SystemTime::now())?,
+        args.dry_run,
+        args.verbose,
+        &mut io::stdout(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::ObjectFormat;
+    use crate::objects::{Blob, Commit, ObjectStore, Tree};
+    use crate::refs::update_ref;
+    use crate::utils::init_rgit_dir;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_parse_expire() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(100 * 24 * 60 * 60);
+        assert_eq!(parse_expire("now", now).unwrap(), Some(now));
+        assert_eq!(parse_expire("never", now).unwrap(), None);
+        assert_eq!(
+            parse_expire("2.weeks.ago", now).unwrap(),
+            Some(SystemTime::UNIX_EPOCH + Duration::from_secs(86 * 24 * 60 * 60))
+        );
+        assert_eq!(
+            parse_expire("1 day ago", now).unwrap(),
+            Some(SystemTime::UNIX_EPOCH + Duration::from_secs(99 * 24 * 60 * 60))
+        );
+        assert_eq!(
+            parse_expire("1970-01-02", now).unwrap(),
+            Some(SystemTime::UNIX_EPOCH + Duration::from_secs(24 * 60 * 60))
+        );
+        assert_eq!(
+            parse_expire("99999999999999999.years.ago", now).unwrap(),
+            Some(SystemTime::UNIX_EPOCH)
+        );
+        assert!(parse_expire("2.fortnights.ago", now).is_err());
+        assert!(parse_expire("yesterday", now).is_err());
+    }
+
+    #[test]
+    fn test_prune() {
+        let dir = tempdir().unwrap();
+        let rgit_dir = init_rgit_dir(dir.path()).unwrap();
+        let db = ObjectDatabase::open(&rgit_dir).unwrap();
+        let store = LooseStore::new(&rgit_dir.join("objects"));
+
+        fs::write(dir.path().join("file"), "file content").unwrap();
+        let tree = Tree::from_directory(dir.path(), ObjectFormat::Sha1).unwrap();
+        tree.write_to_rgit_objects(&db).unwrap();
+        let commit = Commit::new(*tree.hash(), Vec::new(), "commit".to_string()).unwrap();
+        let commit_hash = commit.write_to_rgit_objects(&db).unwrap();
+        fs::write(rgit_dir.join("HEAD"), hex::encode(commit_hash)).unwrap();
+
+        // an old orphan and a recent one
+        fs::write(dir.path().join("file"), "old orphan").unwrap();
+        let old = Blob::from_file(&dir.path().join("file"), ObjectFormat::Sha1).unwrap();
+        old.write_to_rgit_objects(&db).unwrap();
+        let month_ago = SystemTime::now() - Duration::from_secs(30 * 24 * 60 * 60);
+        fs::File::open(store.object_path(old.hash()))
+            .unwrap()
+            .set_modified(month_ago)
+            .unwrap();
+        fs::write(dir.path().join("file"), "new orphan").unwrap();
+        let new = Blob::from_file(&dir.path().join("file"), ObjectFormat::Sha1).unwrap();
+        new.write_to_rgit_objects(&db).unwrap();
+        fs::remove_file(dir.path().join("file")).unwrap();
+
+        let expire = parse_expire(DEFAULT_PRUNE_EXPIRE, SystemTime::now()).unwrap();
+        let mut buffer = Vec::new();
+        prune(dir.path(), expire, true, false, &mut buffer).unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            format!("{} blob\n", hex::encode(old.hash()))
+        );
+        assert!(store.contains(old.hash()).unwrap());
+
+        let mut buffer = Vec::new();
+        prune(dir.path(), expire, false, false, &mut buffer).unwrap();
+        assert!(buffer.is_empty());
+        assert!(!store.contains(old.hash()).unwrap());
+        assert!(store.contains(new.hash()).unwrap());
+
+        let mut buffer = Vec::new();
+        prune(dir.path(), None, false, true, &mut buffer).unwrap();
+        assert!(buffer.is_empty());
+        assert!(store.contains(new.hash()).unwrap());
+
+        let mut buffer = Vec::new();
+        prune(
+            dir.path(),
+            Some(SystemTime::now()),
+            false,
+            true,
+            &mut buffer,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            format!("{} blob\n", hex::encode(new.hash()))
+        );
+        assert!(!rgit_dir
+            .join("objects")
+            .join(&hex::encode(new.hash())[..2])
+            .exists());
+
+        // everything reachable is kept
+        assert_eq!(store.objects().unwrap().len(), 3);
+        Commit::from_rgit_objects(&db, &commit_hash).unwrap();
+
+        // so are the commits only the reflog still refers to
+        let orphan = Commit::new(*tree.hash(), Vec::new(), "orphan".to_string()).unwrap();
+        let orphan_hash = orphan.write_to_rgit_objects(&db).unwrap();
+        update_ref(
+            &rgit_dir,
+            "refs/heads/topic",
+            &orphan_hash,
+            "branch: Created",
+        )
+        .unwrap();
+        update_ref(&rgit_dir, "refs/heads/topic", &commit_hash, "reset: moving").unwrap();
+        prune(
+            dir.path(),
+            Some(SystemTime::now()),
+            false,
+            false,
+            &mut Vec::new(),
+        )
+        .unwrap();
+        assert!(store.contains(&orphan_hash).unwrap());
+    }
+
+    #[test]
+    fn test_prune_rewritten_orphan() {
+        let dir = tempdir().unwrap();
+        let rgit_dir = init_rgit_dir(dir.path()).unwrap();
+        let db = ObjectDatabase::open(&rgit_dir).unwrap();
+        let store = LooseStore::new(&rgit_dir.join("objects"));
+
+        fs::write(dir.path().join("file"), "old orphan").unwrap();
+        let blob = Blob::from_file(&dir.path().join("file"), ObjectFormat::Sha1).unwrap();
+        blob.write_to_rgit_objects(&db).unwrap();
+        let month_ago = SystemTime::now() - Duration::from_secs(30 * 24 * 60 * 60);
+        fs::File::open(store.object_path(blob.hash()))
+            .unwrap()
+            .set_modified(month_ago)
+            .unwrap();
+
+        // writing the object again, for a new tree say, restarts its grace period
+        let before_write = SystemTime::now() - Duration::from_secs(60);
+        blob.write_to_rgit_objects(&db).unwrap();
+        prune(
+            dir.path(),
+            Some(before_write),
+            false,
+            false,
+            &mut Vec::new(),
+        )
+        .unwrap();
+        assert!(store.contains(blob.hash()).unwrap());
+    }
+}
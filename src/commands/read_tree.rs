@@ -1,6 +1,6 @@
-use crate::hash::hash_array_from_str;
 use crate::ignore::is_ignored;
-use crate::objects::Tree;
+use crate::objects::{ObjectDatabase, RGitObjectType, Tree};
+use crate::revision::{peel, resolve_revision};
 use crate::utils::get_rgit_dir;
 use anyhow::Result;
 use clap::Parser;
@@ -11,7 +11,7 @@ use std::path;
 /// Reads tree information into the index
 #[derive(Parser, Debug)]
 pub struct ReadTreeArgs {
-    /// The tree object to be read.
+    /// The tree object to be read, commits are read as their tree
     pub tree_ish: String,
 }
 
@@ -38,8 +38,10 @@ fn empty_dir(dir: &path::Path) -> Result<()> {
 
 fn read_tree(dir: &path::Path, tree_ish: String) -> Result<u8> {
     let rgit_dir = get_rgit_dir(dir)?;
-    let tree_hash_array = hash_array_from_str(&tree_ish)?;
-    let tree = Tree::from_rgit_objects(rgit_dir.as_path(), &tree_hash_array)?;
+    let db = ObjectDatabase::open(&rgit_dir)?;
+    let hash = resolve_revision(&db, &rgit_dir, &tree_ish)?;
+    let tree_hash_array = peel(&db, &tree_ish, hash, RGitObjectType::Tree)?;
+    let tree = Tree::from_rgit_objects(&db, &tree_hash_array)?;
     empty_dir(dir)?;
     tree.write_to_directory(dir)?;
     Ok(0)
@@ -52,6 +54,7 @@ pub fn rgit_read_tree(args: &ReadTreeArgs) -> Result<u8> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::hash::ObjectFormat;
     use crate::utils::init_rgit_dir;
     use tempfile::tempdir;
 
@@ -76,7 +79,7 @@ mod tests {
     fn test_read_tree() {
         let dir = tempdir().unwrap();
         let path = dir.path();
-        let rgit_dir = init_rgit_dir(path).unwrap();
+        let db = ObjectDatabase::open(&init_rgit_dir(path).unwrap()).unwrap();
 
         let file_path = path.join("file");
         fs::write(&file_path, "file content").unwrap();
@@ -86,8 +89,8 @@ mod tests {
         let subfile_path = subdir_path.join("subfile");
         fs::write(&subfile_path, "subfile content").unwrap();
 
-        let tree = Tree::from_directory(path).unwrap();
-        tree.write_to_rgit_objects(rgit_dir.as_path()).unwrap();
+        let tree = Tree::from_directory(path, ObjectFormat::Sha1).unwrap();
+        tree.write_to_rgit_objects(&db).unwrap();
 
         let result = read_tree(path, hex::encode(tree.hash()));
         assert!(result.is_ok());
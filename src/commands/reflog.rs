@@ -0,0 +1,148 @@
+use crate::error::RGitError;
+use crate::refs::read_reflog;
+use crate::revision::{abbreviate, resolve_ref};
+use crate::Repository;
+use anyhow::Result;
+use clap::Parser;
+use std::env;
+use std::io;
+use std::path::Path;
+
+/// Show the previous positions of HEAD or of a branch
+#[derive(Parser, Debug)]
+pub struct ReflogArgs {
+    /// The ref whose reflog to show, HEAD by default
+    #[arg(default_value = "HEAD")]
+    pub reference: String,
+}
+
+/// Prints the reflog of the ref `name`, most recent change first, numbered the way `<name>@{<n>}`
+/// names them.
+fn reflog(dir: &Path, name: &str, writer: &mut dyn io::Write) -> Result<u8> {
+    let repo = Repository::discover(dir)?;
+    let ref_name = match resolve_ref(repo.rgit_dir(), name)? {
+        Some((ref_name, _)) => ref_name,
+        // before the first commit
+        None if name == "HEAD" => name.to_string(),
+        None => {
+            return Err(RGitError::InvalidRef {
+                name: name.to_string(),
+            }
+            .into())
+        }
+    };
+
+    for (n, entry) in read_reflog(repo.rgit_dir(), &ref_name)?
+        .iter()
+        .rev()
+        .enumerate()
+    {
+        writeln!(
+            writer,
+            "{} {}@{{{}}}: {}",
+            abbreviate(repo.db(), &entry.new)?,
+            name,
+            n,
+            entry.message
+        )?;
+    }
+    Ok(0)
+}
+
+pub fn rgit_reflog(args: &ReflogArgs) -> Result<u8> {
+    reflog(&env::current_dir()?, &args.reference, &mut io::stdout())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::refs::{list_reflog_ids, RefTarget};
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn show(dir: &Path, name: &str) -> String {
+        let mut buffer = Vec::new();
+        reflog(dir, name, &mut buffer).unwrap();
+        String::from_utf8(buffer).unwrap()
+    }
+
+    #[test]
+    fn test_reflog() {
+        let dir = tempdir().unwrap();
+        let path = dir.path();
+        let repo = Repository::init(path, None).unwrap();
+        fs::write(
+            repo.rgit_dir().join("config"),
+            "[user]\n\tname = A U Thor\n\temail = author@example.com\n",
+        )
+        .unwrap();
+        assert_eq!(show(path, "HEAD"), "");
+
+        fs::write(path.join("file"), "first").unwrap();
+        let first = repo.commit("first\n\nbody").unwrap();
+        fs::write(path.join("file"), "second").unwrap();
+        let second = repo.commit("second").unwrap();
+        let abbrev = |hash| abbreviate(repo.db(), &hash).unwrap();
+        assert_eq!(
+            show(path, "main"),
+            format!(
+                "{} main@{{0}}: commit: second\n{} main@{{1}}: commit (initial): first\n",
+                abbrev(second),
+                abbrev(first)
+            )
+        );
+
+        let entries = read_reflog(repo.rgit_dir(), "HEAD").unwrap();
+        assert_eq!(entries.len(), 2);
+        assert!(entries[0].old.is_null());
+        assert_eq!(entries[0].new, first);
+        assert_eq!((entries[1].old, entries[1].new), (first, second));
+        // RGIT_COMMITTER_NAME and RGIT_COMMITTER_EMAIL take precedence over the config
+        let (name, email) = repo.config().unwrap().identity().unwrap();
+        assert_eq!(entries[1].signature.name, name);
+        assert_eq!(entries[1].signature.email, email);
+        let content = fs::read_to_string(repo.rgit_dir().join("logs/HEAD")).unwrap();
+        assert!(content.starts_with(&format!(
+            "{} {} {} <{}> ",
+            "0".repeat(40),
+            first,
+            name,
+            email
+        )));
+
+        // moving HEAD is recorded in its reflog only
+        let message = format!("checkout: moving from main to {}", first);
+        repo.set_head(&RefTarget::Direct(first), &message).unwrap();
+        assert_eq!(
+            read_reflog(repo.rgit_dir(), "refs/heads/main")
+                .unwrap()
+                .len(),
+            2
+        );
+        assert!(show(path, "HEAD").starts_with(&format!(
+            "{} HEAD@{{0}}: checkout: moving from main to {}\n",
+            abbrev(first),
+            first
+        )));
+
+        assert_eq!(repo.resolve("HEAD@{0}").unwrap(), first);
+        assert_eq!(repo.resolve("HEAD@{1}").unwrap(), second);
+        assert_eq!(repo.resolve("main@{1}").unwrap(), first);
+        assert_eq!(repo.resolve("refs/heads/main@{0}").unwrap(), second);
+        assert_eq!(repo.resolve("@{2}").unwrap(), first);
+        assert_eq!(
+            repo.resolve("main@{1}^{tree}").unwrap(),
+            repo.resolve("HEAD^{tree}").unwrap()
+        );
+        let result = repo.resolve("main@{2}");
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "fatal: log for 'main' only has 2 entries"
+        );
+        assert!(repo.resolve("nope@{0}").is_err());
+        assert!(reflog(path, "nope", &mut Vec::new()).is_err());
+
+        // the objects of the reflog count as reachable
+        assert_eq!(list_reflog_ids(repo.rgit_dir()).unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,261 @@
+use crate::objects::{
+    find_reachable_objects, write_pack, LooseStore, ObjectDatabase, ObjectStore, PackObject,
+    PackStore, RawObject,
+};
+use crate::refs::{list_reflog_ids, list_refs};
+use crate::utils::get_rgit_dir;
+use anyhow::Result;
+use clap::Parser;
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Pack unpacked objects in a repository
+#[derive(Parser, Debug)]
+pub struct RepackArgs {
+    /// The number of objects considered as delta bases for each object
+    #[arg(long, default_value_t = 10)]
+    pub window: usize,
+
+    /// The maximum length of a delta chain
+    #[arg(long, default_value_t = 50)]
+    pub depth: usize,
+}
+
+fn storage_size(loose_store: &LooseStore, pack_store: &PackStore) -> Result<u64> {
+    let mut size = 0;
+    for (_, path) in loose_store.objects()? {
+        size += fs::metadata(path)?.len();
+    }
+    for pack in pack_store.packs()? {
+        size += fs::metadata(pack.path())?.len();
+        size += fs::metadata(pack.path().with_extension("idx"))?.len();
+    }
+    Ok(size)
+}
+
+pub fn repack(dir: &Path, window: usize, depth: usize, writer: &mut dyn io::Write) -> Result<u8> {
+    let rgit_dir = get_rgit_dir(dir)?;
+    let db = ObjectDatabase::open(&rgit_dir)?;
+    // deleting files depends on the on-disk layout, so the backends are also used directly
+    let loose_store = LooseStore::new(&rgit_dir.join("objects"));
+    let pack_store = PackStore::new(&rgit_dir.join("objects"), db.format());
+    let size_before = storage_size(&loose_store, &pack_store)?;
+
+    // the previous positions of refs stay recoverable, as long as they were not already lost
+    let mut roots = list_refs(&rgit_dir)?
+        .into_iter()
+        .map(|(_, hash)| hash)
+        .collect::<Vec<_>>();
+    for hash in list_reflog_ids(&rgit_dir)? {
+        if db.contains(&hash)? {
+            roots.push(hash);
+        }
+    }
+    let reachable = find_reachable_objects(&db, &roots)?;
+    writeln!(writer, "Counting objects: {}, done.", reachable.len())?;
+
+    // objects borrowed from alternates stay there, like `git repack -l`
+    let local_db = db.without_alternates();
+    let mut objects = Vec::with_capacity(reachable.len());
+    for object in reachable {
+        if !local_db.contains(&object.hash)? {
+            continue;
+        }
+        let raw = RawObject::from_rgit_objects(&db, &object.hash)?;
+        objects.push(PackObject {
+            hash: object.hash,
+            object_type: raw.object_type,
+            content: raw.content,
+            path: object.path,
+        });
+    }
+    if objects.is_empty() {
+        writeln!(writer, "Nothing new to pack.")?;
+        return Ok(0);
+    }
+    let packed = objects
+        .iter()
+        .map(|object| object.hash)
+        .collect::<HashSet<_>>();
+
+    let old_packs = pack_store.packs()?;
+    let result = write_pack(pack_store.pack_dir(), &objects, window, depth, db.format())?;
+    writeln!(
+        writer,
+        "Delta compression: {} of {} objects deltified.",
+        result.delta_count, result.object_count
+    )?;
+
+    // unreachable objects that only live in the old packs are kept as loose objects, so that
+    // they are not lost before they expire. They keep the age of their pack, so that exploding
+    // them does not restart their grace period.
+    let old_packs = old_packs
+        .into_iter()
+        .filter(|pack| pack.path() != result.pack_path)
+        .collect::<Vec<_>>();
+    for pack in old_packs.iter() {
+        let mtime = fs::metadata(pack.path())?.modified()?;
+        for hash in pack.index().hashes() {
+            if !packed.contains(hash) && !loose_store.contains(hash)? {
+                let raw = RawObject::from_rgit_objects(&db, hash)?;
+                loose_store.write(hash, &raw)?;
+                fs::File::open(loose_store.object_path(hash))?.set_modified(mtime)?;
+            }
+        }
+    }
+    for pack in old_packs.iter() {
+        fs::remove_file(pack.path().with_extension("idx"))?;
+        fs::remove_file(pack.path())?;
+    }
+
+    let mut removed = 0;
+    for (hash, _) in loose_store.objects()? {
+        if packed.contains(&hash) {
+            loose_store.remove(&hash)?;
+            removed += 1;
+        }
+    }
+
+    writeln!(
+        writer,
+        "Wrote {} ({} bytes).",
+        result.pack_path.file_name().unwrap().to_string_lossy(),
+        result.size
+    )?;
+    writeln!(
+        writer,
+        "Removed {} loose objects and {} old packs.",
+        removed,
+        old_packs.len()
+    )?;
+    writeln!(
+        writer,
+        "Object storage: {} bytes -> {} bytes.",
+        size_before,
+        storage_size(&loose_store, &pack_store)?
+    )?;
+
+    Ok(0)
+}
+
+pub fn rgit_repack(args: &RepackArgs) -> Result<u8> {
+    repack(
+        &env::current_dir()?,
+        args.window,
+        args.depth,
+        &mut io::stdout(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::{ObjectFormat, ObjectId};
+    use crate::objects::{Blob, Commit, Tree};
+    use crate::utils::init_rgit_dir;
+    use std::time::{Duration, SystemTime};
+    use tempfile::tempdir;
+
+    fn write_commit(dir: &Path, rgit_dir: &Path, parents: Vec<ObjectId>) -> ObjectId {
+        let db = ObjectDatabase::open(rgit_dir).unwrap();
+        let tree = Tree::from_directory(dir, ObjectFormat::Sha1).unwrap();
+        tree.write_to_rgit_objects(&db).unwrap();
+        let commit = Commit::new(*tree.hash(), parents, "commit".to_string()).unwrap();
+        let hash = commit.write_to_rgit_objects(&db).unwrap();
+        fs::write(rgit_dir.join("HEAD"), hex::encode(hash)).unwrap();
+        hash
+    }
+
+    #[test]
+    fn test_repack() {
+        let dir = tempdir().unwrap();
+        let rgit_dir = init_rgit_dir(dir.path()).unwrap();
+        let db = ObjectDatabase::open(&rgit_dir).unwrap();
+        let loose_store = LooseStore::new(&rgit_dir.join("objects"));
+        let pack_store = PackStore::new(&rgit_dir.join("objects"), ObjectFormat::Sha1);
+
+        let mut buffer = Vec::new();
+        repack(dir.path(), 10, 50, &mut buffer).unwrap();
+        assert!(String::from_utf8(buffer)
+            .unwrap()
+            .contains("Nothing new to pack."));
+
+        let content = (0..200)
+            .map(|i| format!("line {}\n", i))
+            .collect::<String>();
+        fs::write(dir.path().join("file"), &content).unwrap();
+        let first = write_commit(dir.path(), &rgit_dir, Vec::new());
+        fs::write(dir.path().join("file"), content.replace("line 1\n", "")).unwrap();
+        let second = write_commit(dir.path(), &rgit_dir, vec![first]);
+
+        fs::write(dir.path().join("orphan"), "orphan").unwrap();
+        let orphan = Blob::from_file(&dir.path().join("orphan"), ObjectFormat::Sha1).unwrap();
+        orphan.write_to_rgit_objects(&db).unwrap();
+        fs::remove_file(dir.path().join("orphan")).unwrap();
+
+        let mut buffer = Vec::new();
+        repack(dir.path(), 10, 50, &mut buffer).unwrap();
+        let output = String::from_utf8(buffer).unwrap();
+        assert!(output.contains("Counting objects: 6, done."));
+        assert!(output.contains("Delta compression: 1 of 6 objects deltified."));
+        assert!(output.contains("Removed 6 loose objects and 0 old packs."));
+
+        // only the unreachable blob stays loose
+        let loose = loose_store.objects().unwrap();
+        assert_eq!(loose.len(), 1);
+        assert_eq!(loose[0].0, *orphan.hash());
+        assert_eq!(pack_store.packs().unwrap().len(), 1);
+
+        let commit = Commit::from_rgit_objects(&db, &second).unwrap();
+        assert_eq!(commit.parents(), &[first]);
+        Tree::from_rgit_objects(&db, commit.tree()).unwrap();
+
+        // repacking a packed repository replaces the old pack
+        fs::write(dir.path().join("file"), "new content").unwrap();
+        write_commit(dir.path(), &rgit_dir, vec![second]);
+        let mut buffer = Vec::new();
+        repack(dir.path(), 10, 50, &mut buffer).unwrap();
+        let output = String::from_utf8(buffer).unwrap();
+        assert!(output.contains("Counting objects: 9, done."));
+        assert!(output.contains("Removed 3 loose objects and 1 old packs."));
+        assert_eq!(pack_store.packs().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_repack_keeps_age_of_unreachable_objects() {
+        let dir = tempdir().unwrap();
+        let rgit_dir = init_rgit_dir(dir.path()).unwrap();
+        let loose_store = LooseStore::new(&rgit_dir.join("objects"));
+        let pack_store = PackStore::new(&rgit_dir.join("objects"), ObjectFormat::Sha1);
+
+        fs::write(dir.path().join("file"), "first").unwrap();
+        let first = write_commit(dir.path(), &rgit_dir, Vec::new());
+        repack(dir.path(), 10, 50, &mut Vec::new()).unwrap();
+        let mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        let old_pack = pack_store.packs().unwrap()[0].path().to_path_buf();
+        fs::File::open(&old_pack)
+            .unwrap()
+            .set_modified(mtime)
+            .unwrap();
+
+        // the first commit is no longer reachable once HEAD moves to an unrelated one
+        fs::write(dir.path().join("file"), "second").unwrap();
+        write_commit(dir.path(), &rgit_dir, Vec::new());
+        let mut buffer = Vec::new();
+        repack(dir.path(), 10, 50, &mut buffer).unwrap();
+        assert!(String::from_utf8(buffer)
+            .unwrap()
+            .contains("Removed 3 loose objects and 1 old packs."));
+        assert!(!old_pack.exists());
+
+        let loose = loose_store.objects().unwrap();
+        assert_eq!(loose.len(), 3);
+        assert!(loose.iter().any(|(hash, _)| *hash == first));
+        for (_, path) in loose {
+            assert_eq!(fs::metadata(path).unwrap().modified().unwrap(), mtime);
+        }
+    }
+}
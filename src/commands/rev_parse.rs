@@ -0,0 +1,221 @@
+use crate::error::RGitError;
+use crate::refs::{read_ref_target, RefTarget};
+use crate::revision::{
+    abbreviate_to, previous_checkout, resolve_ref, resolve_revision_arg, RevisionArg,
+};
+use crate::Repository;
+use anyhow::Result;
+use clap::Parser;
+use std::env;
+use std::io;
+use std::path::Path;
+
+/// Print the object names that revisions stand for
+#[derive(Parser, Debug)]
+pub struct RevParseArgs {
+    /// Require exactly one revision that names an object, and print it
+    #[arg(long)]
+    pub verify: bool,
+
+    /// With --verify, exit with status 1 without an error message when the revision is invalid
+    #[arg(short, long, requires = "verify")]
+    pub quiet: bool,
+
+    /// Print the shortest unambiguous object names of at least <n> hex digits
+    #[arg(
+        long,
+        value_name = "n",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "7",
+    )]
+    pub short: Option<usize>,
+
+    /// Print the short names of the refs the revisions name, such as main for HEAD
+    #[arg(long)]
+    pub abbrev_ref: bool,
+
+    /// Revisions, `^<rev>` to exclude commits, and ranges `A..B` or `A...B`
+    pub revisions: Vec<String>,
+}
+
+/// Returns the short name of the ref `name` stands for: the branch `HEAD` is on, `HEAD` when
+/// detached, or the name of the ref without `refs/heads/` and the like. Other revisions print
+/// their object ID.
+fn abbrev_ref(repo: &Repository, name: &str) -> Result<String> {
+    let full_name = if name == "HEAD" || name == "@" {
+        match read_ref_target(repo.rgit_dir(), "HEAD")? {
+            Some(RefTarget::Symbolic(branch)) => Some(branch),
+            _ => Some("HEAD".to_string()),
+        }
+    } else if let Some(n) = name
+        .strip_prefix("@{-")
+        .and_then(|n| n.strip_suffix('}'))
+        .and_then(|n| n.parse::<usize>().ok())
+    {
+        match previous_checkout(repo.rgit_dir(), n)? {
+            Some(previous) => return abbrev_ref(repo, &previous),
+            None => {
+                return Err(RGitError::InvalidRef {
+                    name: name.to_string(),
+                }
+                .into())
+            }
+        }
+    } else {
+        resolve_ref(repo.rgit_dir(), name)?.map(|(full_name, _)| full_name)
+    };
+
+    Ok(match full_name {
+        Some(full_name) => ["refs/heads/", "refs/tags/", "refs/remotes/", "refs/"]
+            .iter()
+            .find_map(|prefix| full_name.strip_prefix(prefix))
+            .unwrap_or(&full_name)
+            .to_string(),
+        None => repo.resolve(name)?.to_string(),
+    })
+}
+
+/// Resolves `revision` to the objects it stands for, more than one for a range.
+fn parse(repo: &Repository, revision: &str, verify: bool) -> Result<Vec<RevisionArg>> {
+    if verify {
+        return Ok(vec![RevisionArg {
+            hash: repo.resolve(revision)?,
+            excluded: false,
+        }]);
+    }
+    resolve_revision_arg(repo.db(), repo.rgit_dir(), revision).map_err(|err| {
+        match err.downcast_ref::<RGitError>() {
+            Some(RGitError::InvalidRef { .. }) => RGitError::new(
+                format!(
+                    "fatal: ambiguous argument '{}': unknown revision or path not in the working \
+                     tree.\nUse '--' to separate paths from revisions, like this:\n\
+                     'rgit <command> [<revision>...] -- [<file>...]'",
+                    revision
+                ),
+                128,
+            ),
+            _ => err,
+        }
+    })
+}
+
+fn rev_parse(dir: &Path, args: &RevParseArgs, writer: &mut dyn io::Write) -> Result<u8> {
+    let repo = Repository::discover(dir)?;
+    if args.verify && args.revisions.len() != 1 {
+        if args.quiet {
+            return Ok(1);
+        }
+        return Err(RGitError::new(
+            "fatal: Needed a single revision".to_string(),
+            128,
+        ));
+    }
+
+    // everything is resolved before anything is printed
+    let mut lines = Vec::new();
+    for revision in &args.revisions {
+        if args.abbrev_ref {
+            lines.push(abbrev_ref(&repo, revision)?);
+            continue;
+        }
+        let parsed = match parse(&repo, revision, args.verify) {
+            Ok(parsed) => parsed,
+            Err(_) if args.quiet => return Ok(1),
+            Err(_) if args.verify => {
+                return Err(RGitError::new(
+                    "fatal: Needed a single revision".to_string(),
+                    128,
+                ))
+            }
+            Err(err) => return Err(err),
+        };
+        for RevisionArg { hash, excluded } in parsed {
+            let name = match args.short {
+                Some(len) => abbreviate_to(repo.db(), &hash, len)?,
+                None => hash.to_string(),
+            };
+            lines.push(format!("{}{}", if excluded { "^" } else { "" }, name));
+        }
+    }
+    for line in lines {
+        writeln!(writer, "{}", line)?;
+    }
+    Ok(0)
+}
+
+pub fn rgit_rev_parse(args: &RevParseArgs) -> Result<u8> {
+    rev_parse(&env::current_dir()?, args, &mut io::stdout())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::switch::switch_branch;
+    use crate::refs::write_ref;
+    use std::fs;
+    use tempfile::tempdir;
+
+    fn run(path: &Path, args: &[&str]) -> Result<(u8, String)> {
+        let args = RevParseArgs::try_parse_from([&["rev-parse"], args].concat())?;
+        let mut buffer = Vec::new();
+        let code = rev_parse(path, &args, &mut buffer)?;
+        Ok((code, String::from_utf8(buffer)?))
+    }
+
+    #[test]
+    fn test_rev_parse() {
+        let dir = tempdir().unwrap();
+        let path = dir.path();
+        let repo = Repository::init(path, None).unwrap();
+        fs::write(path.join("file"), "first").unwrap();
+        let first = repo.commit("first").unwrap();
+        fs::write(path.join("file"), "second").unwrap();
+        let second = repo.commit("second").unwrap();
+        write_ref(repo.rgit_dir(), "refs/heads/topic", &first).unwrap();
+
+        assert_eq!(
+            run(path, &["HEAD", "main~1"]).unwrap(),
+            (0, format!("{}\n{}\n", second, first))
+        );
+        assert_eq!(
+            run(path, &["--short", "HEAD"]).unwrap().1,
+            format!("{}\n", &second.to_string()[..7])
+        );
+        assert_eq!(
+            run(path, &["--short=10", "HEAD"]).unwrap().1,
+            format!("{}\n", &second.to_string()[..10])
+        );
+        assert_eq!(
+            run(path, &["topic..main"]).unwrap().1,
+            format!("{}\n^{}\n", second, first)
+        );
+        assert_eq!(run(path, &["^topic"]).unwrap().1, format!("^{}\n", first));
+        assert_eq!(
+            run(path, &["topic...main"]).unwrap().1,
+            format!("{}\n{}\n^{}\n", second, first, first)
+        );
+
+        assert_eq!(run(path, &["--abbrev-ref", "HEAD"]).unwrap().1, "main\n");
+        switch_branch(&repo, "topic", false, &mut Vec::new()).unwrap();
+        assert_eq!(run(path, &["--abbrev-ref", "@{-1}"]).unwrap().1, "main\n");
+        assert_eq!(run(path, &["@{-1}"]).unwrap().1, format!("{}\n", second));
+
+        let err = run(path, &["nope"]).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("fatal: ambiguous argument 'nope': unknown revision"));
+        let err = run(path, &["--verify", "nope"]).unwrap_err();
+        assert_eq!(err.to_string(), "fatal: Needed a single revision");
+        let err = run(path, &["--verify", "HEAD", "main"]).unwrap_err();
+        assert_eq!(err.to_string(), "fatal: Needed a single revision");
+        assert_eq!(
+            run(path, &["--verify", "-q", "nope"]).unwrap(),
+            (1, String::new())
+        );
+        assert_eq!(
+            run(path, &["--verify", "topic"]).unwrap().1,
+            format!("{}\n", first)
+        );
+    }
+}
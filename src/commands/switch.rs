@@ -0,0 +1,305 @@
+use crate::commands::branch::branch_ref;
+use crate::error::RGitError;
+use crate::objects::RGitObjectType;
+use crate::refs::{read_ref, update_ref, RefTarget};
+use crate::revision::{peel, previous_checkout};
+use crate::Repository;
+use anyhow::Result;
+use clap::Parser;
+use std::env;
+use std::io;
+use std::path::Path;
+
+/// Switch branches
+#[derive(Parser, Debug)]
+pub struct SwitchArgs {
+    /// Create a branch starting at <branch>, HEAD by default, and switch to it
+    #[arg(short = 'c', long = "create", value_name = "new-branch")]
+    pub create: Option<String>,
+
+    /// Switch even if files with local changes have to be overwritten, discarding the changes
+    #[arg(short, long, alias = "discard-changes")]
+    pub force: bool,
+
+    /// The branch to switch to
+    #[arg(required_unless_present = "create")]
+    pub branch: Option<String>,
+}
+
+/// Names the current position of `HEAD` in reflog messages: its branch, or the commit it is
+/// detached at.
+pub(super) fn head_name(repo: &Repository) -> Result<String> {
+    Ok(match repo.head_branch()? {
+        Some(branch) => branch["refs/heads/".len()..].to_string(),
+        None => repo
+            .head()?
+            .map(|hash| hash.to_string())
+            .unwrap_or_default(),
+    })
+}
+
+/// Replaces `-` and `@{-<n>}` by the branch, or the commit, `HEAD` was on before the last, or
+/// `n`-th last, checkout.
+pub(super) fn expand_previous(repo: &Repository, name: &str) -> Result<String> {
+    let n = match name {
+        "-" => Some(1),
+        _ => name
+            .strip_prefix("@{-")
+            .and_then(|n| n.strip_suffix('}'))
+            .and_then(|n| n.parse::<usize>().ok()),
+    };
+    let Some(n) = n else {
+        return Ok(name.to_string());
+    };
+    previous_checkout(repo.rgit_dir(), n)?
+        .ok_or_else(|| RGitError::new(format!("fatal: invalid reference: {}", name), 128))
+}
+
+/// Checks out the branch `name` and points `HEAD` to it.
+pub(super) fn switch_branch(
+    repo: &Repository,
+    name: &str,
+    force: bool,
+    writer: &mut dyn io::Write,
+) -> Result<u8> {
+    let ref_name = format!("refs/heads/{}", name);
+    if repo.head_branch()?.as_ref() == Some(&ref_name) {
+        writeln!(writer, "Already on '{}'", name)?;
+        return Ok(0);
+    }
+    let Some(hash) = read_ref(repo.rgit_dir(), &ref_name)? else {
+        return Err(RGitError::new(
+            format!("fatal: invalid reference: {}", name),
+            128,
+        ));
+    };
+
+    repo.checkout(&hash, force)?;
+    let message = format!("checkout: moving from {} to {}", head_name(repo)?, name);
+    repo.set_head(&RefTarget::Symbolic(ref_name), &message)?;
+    writeln!(writer, "Switched to branch '{}'", name)?;
+    Ok(0)
+}
+
+fn switch(dir: &Path, name: &str, force: bool, writer: &mut dyn io::Write) -> Result<u8> {
+    let repo = Repository::discover(dir)?;
+    let name = expand_previous(&repo, name)?;
+    switch_branch(&repo, &name, force, writer)
+}
+
+/// Creates the branch `name` at `start_point`, `HEAD` by default, checks it out and points `HEAD`
+/// to it.
+fn create_and_switch(
+    dir: &Path,
+    name: &str,
+    start_point: Option<&str>,
+    force: bool,
+    writer: &mut dyn io::Write,
+) -> Result<u8> {
+    let repo = Repository::discover(dir)?;
+    let ref_name = branch_ref(name)?;
+    if read_ref(repo.rgit_dir(), &ref_name)?.is_some() {
+        return Err(RGitError::new(
+            format!("fatal: a branch named '{}' already exists", name),
+            128,
+        ));
+    }
+
+    let hash = match start_point {
+        Some(start_point) => Some(peel(
+            repo.db(),
+            start_point,
+            repo.resolve(start_point)?,
+            RGitObjectType::Commit,
+        )?),
+        None => repo.head()?,
+    };
+    // without any commit yet, the new branch is unborn too
+    if let Some(hash) = hash {
+        repo.checkout(&hash, force)?;
+        let message = format!("branch: Created from {}", start_point.unwrap_or("HEAD"));
+        update_ref(repo.rgit_dir(), &ref_name, &hash, &message)?;
+    }
+    let message = format!("checkout: moving from {} to {}", head_name(&repo)?, name);
+    repo.set_head(&RefTarget::Symbolic(ref_name), &message)?;
+    writeln!(writer, "Switched to a new branch '{}'", name)?;
+    Ok(0)
+}
+
+pub fn rgit_switch(args: &SwitchArgs) -> Result<u8> {
+    let dir = env::current_dir()?;
+    match &args.create {
+        Some(name) => create_and_switch(
+            &dir,
+            name,
+            args.branch.as_deref(),
+            args.force,
+            &mut io::stdout(),
+        ),
+        None => switch(
+            &dir,
+            args.branch.as_deref().unwrap(),
+            args.force,
+            &mut io::stdout(),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::Commit;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_switch() {
+        let dir = tempdir().unwrap();
+        let path = dir.path();
+        let repo = Repository::init(path, None).unwrap();
+        fs::write(path.join(".rgitignore"), "*.log\n").unwrap();
+        fs::write(path.join("same"), "same").unwrap();
+        fs::write(path.join("changed"), "main").unwrap();
+        fs::write(path.join("removed"), "removed").unwrap();
+        fs::create_dir(path.join("dir")).unwrap();
+        fs::write(path.join("dir/file"), "file").unwrap();
+        let main = repo.commit("main").unwrap();
+
+        let mut buffer = Vec::new();
+        create_and_switch(path, "topic", None, false, &mut buffer).unwrap();
+        assert_eq!(buffer, b"Switched to a new branch 'topic'\n");
+        assert_eq!(repo.head().unwrap(), Some(main));
+        fs::write(path.join("changed"), "topic").unwrap();
+        fs::remove_file(path.join("removed")).unwrap();
+        fs::remove_dir_all(path.join("dir")).unwrap();
+        fs::write(path.join("dir"), "now a file").unwrap();
+        fs::write(path.join("added"), "added").unwrap();
+        let topic = repo.commit("topic").unwrap();
+
+        // only the files that differ are touched, local changes to the others are kept
+        fs::write(path.join("same"), "local change").unwrap();
+        fs::write(path.join("debug.log"), "ignored").unwrap();
+        let mut buffer = Vec::new();
+        switch(path, "main", false, &mut buffer).unwrap();
+        assert_eq!(buffer, b"Switched to branch 'main'\n");
+        assert_eq!(
+            repo.head_branch().unwrap(),
+            Some("refs/heads/main".to_string())
+        );
+        assert_eq!(fs::read_to_string(path.join("changed")).unwrap(), "main");
+        assert_eq!(fs::read_to_string(path.join("removed")).unwrap(), "removed");
+        assert_eq!(fs::read_to_string(path.join("dir/file")).unwrap(), "file");
+        assert!(!path.join("added").exists());
+        assert_eq!(
+            fs::read_to_string(path.join("same")).unwrap(),
+            "local change"
+        );
+        assert!(path.join("debug.log").exists());
+
+        let mut buffer = Vec::new();
+        switch(path, "main", false, &mut buffer).unwrap();
+        assert_eq!(buffer, b"Already on 'main'\n");
+        assert!(switch(path, "nope", false, &mut Vec::new()).is_err());
+
+        // `-` is the branch checked out before
+        let mut buffer = Vec::new();
+        switch(path, "-", false, &mut buffer).unwrap();
+        assert_eq!(buffer, b"Switched to branch 'topic'\n");
+        switch(path, "@{-1}", false, &mut Vec::new()).unwrap();
+        assert_eq!(
+            repo.head_branch().unwrap(),
+            Some("refs/heads/main".to_string())
+        );
+
+        // local changes to files that differ block the switch
+        fs::write(path.join("changed"), "local change").unwrap();
+        fs::write(path.join("added"), "untracked").unwrap();
+        let err = switch(path, "topic", false, &mut Vec::new()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "error: Your local changes to the following files would be overwritten by checkout:\n\
+             \tchanged\n\
+             Please commit your changes or stash them before you switch branches.\n\
+             error: The following untracked working tree files would be overwritten by checkout:\n\
+             \tadded\n\
+             Please move or remove them before you switch branches.\n\
+             Aborting"
+        );
+        assert_eq!(err.downcast_ref::<RGitError>().unwrap().exit_code(), 1);
+        assert_eq!(repo.head().unwrap(), Some(main));
+        assert_eq!(
+            fs::read_to_string(path.join("changed")).unwrap(),
+            "local change"
+        );
+
+        // unless forced
+        switch(path, "topic", true, &mut Vec::new()).unwrap();
+        assert_eq!(repo.head().unwrap(), Some(topic));
+        assert_eq!(fs::read_to_string(path.join("changed")).unwrap(), "topic");
+        assert_eq!(fs::read_to_string(path.join("added")).unwrap(), "added");
+        assert_eq!(fs::read_to_string(path.join("dir")).unwrap(), "now a file");
+        assert_eq!(fs::read_to_string(path.join("same")).unwrap(), "same");
+        assert!(!path.join("removed").exists());
+        let commit = Commit::from_rgit_objects(repo.db(), &topic).unwrap();
+        assert_eq!(repo.build_tree().unwrap().hash(), commit.tree());
+
+        let result = create_and_switch(path, "topic", None, false, &mut Vec::new());
+        assert!(result.unwrap_err().to_string().contains("already exists"));
+        create_and_switch(path, "from-main", Some("main"), false, &mut Vec::new()).unwrap();
+        assert_eq!(repo.head().unwrap(), Some(main));
+        assert_eq!(fs::read_to_string(path.join("changed")).unwrap(), "main");
+    }
+
+    #[test]
+    fn test_switch_untracked_in_the_way() {
+        let dir = tempdir().unwrap();
+        let path = dir.path();
+        let repo = Repository::init(path, None).unwrap();
+        fs::create_dir_all(path.join("dir")).unwrap();
+        fs::write(path.join("dir/file"), "file").unwrap();
+        fs::create_dir_all(path.join("sub")).unwrap();
+        fs::write(path.join("sub/file"), "file").unwrap();
+        repo.commit("main").unwrap();
+        create_and_switch(path, "topic", None, false, &mut Vec::new()).unwrap();
+        fs::remove_dir_all(path.join("dir")).unwrap();
+        fs::write(path.join("dir"), "now a file").unwrap();
+        fs::remove_dir_all(path.join("sub")).unwrap();
+        repo.commit("topic").unwrap();
+
+        // an untracked file where a directory comes back
+        fs::write(path.join("sub"), "untracked").unwrap();
+        let err = switch(path, "main", false, &mut Vec::new()).unwrap_err();
+        assert!(err.to_string().contains(
+            "error: The following untracked working tree files would be overwritten by \
+             checkout:\n\tsub\n"
+        ));
+        fs::remove_file(path.join("sub")).unwrap();
+        switch(path, "main", false, &mut Vec::new()).unwrap();
+
+        // untracked files in a directory that becomes a file
+        fs::write(path.join("dir/extra"), "untracked").unwrap();
+        let err = switch(path, "topic", false, &mut Vec::new()).unwrap_err();
+        assert!(err.to_string().contains(
+            "error: The following untracked working tree files would be overwritten by \
+             checkout:\n\tdir/extra\n"
+        ));
+        assert_eq!(
+            repo.head_branch().unwrap(),
+            Some("refs/heads/main".to_string())
+        );
+    }
+
+    #[test]
+    fn test_switch_unborn() {
+        let dir = tempdir().unwrap();
+        let repo = Repository::init(dir.path(), None).unwrap();
+        let err = switch(dir.path(), "-", false, &mut Vec::new()).unwrap_err();
+        assert_eq!(err.to_string(), "fatal: invalid reference: -");
+        create_and_switch(dir.path(), "trunk", None, false, &mut Vec::new()).unwrap();
+        assert_eq!(
+            repo.head_branch().unwrap(),
+            Some("refs/heads/trunk".to_string())
+        );
+        assert_eq!(repo.head().unwrap(), None);
+    }
+}
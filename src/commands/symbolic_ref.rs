@@ -0,0 +1,196 @@
+use crate::error::RGitError;
+use crate::refs::{check_ref_name, read_ref_target, RefTarget, RefTransaction};
+use crate::Repository;
+use anyhow::Result;
+use clap::Parser;
+use std::env;
+use std::io;
+use std::path::Path;
+
+/// Read, modify and delete symbolic refs
+#[derive(Parser, Debug)]
+pub struct SymbolicRefArgs {
+    /// Exit with status 1 without an error message when <name> is not a symbolic ref
+    #[arg(short, long)]
+    pub quiet: bool,
+
+    /// Shorten the name of the ref printed, e.g. refs/heads/main to main
+    #[arg(long)]
+    pub short: bool,
+
+    /// Delete the symbolic ref <name>
+    #[arg(short, long, conflicts_with = "reference")]
+    pub delete: bool,
+
+    /// The reason of the update, recorded in the reflog
+    #[arg(short, value_name = "reason")]
+    pub message: Option<String>,
+
+    /// The symbolic ref, such as HEAD
+    pub name: String,
+
+    /// The ref to point <name> to. <name> is read when omitted.
+    pub reference: Option<String>,
+}
+
+/// Returns the target of the symbolic ref `name`, `None` when it is not one.
+fn symbolic_target(repo: &Repository, name: &str, quiet: bool) -> Result<Option<String>> {
+    match read_ref_target(repo.rgit_dir(), name)? {
+        Some(RefTarget::Symbolic(target)) => Ok(Some(target)),
+        _ if quiet => Ok(None),
+        _ => Err(RGitError::new(
+            format!("fatal: ref {} is not a symbolic ref", name),
+            128,
+        )),
+    }
+}
+
+/// Prints the ref the symbolic ref `name` points to.
+fn read_symbolic_ref(
+    dir: &Path,
+    name: &str,
+    quiet: bool,
+    short: bool,
+    writer: &mut dyn io::Write,
+) -> Result<u8> {
+    let repo = Repository::discover(dir)?;
+    let Some(target) = symbolic_target(&repo, name, quiet)? else {
+        return Ok(1);
+    };
+    let target = match ["refs/heads/", "refs/tags/", "refs/remotes/", "refs/"]
+        .iter()
+        .find_map(|prefix| target.strip_prefix(prefix))
+    {
+        Some(short_name) if short => short_name,
+        _ => &target,
+    };
+    writeln!(writer, "{}", target)?;
+    Ok(0)
+}
+
+/// Points the symbolic ref `name` to the ref `target`.
+fn set_symbolic_ref(dir: &Path, name: &str, target: &str, message: &str) -> Result<u8> {
+    let repo = Repository::discover(dir)?;
+    if name == "HEAD" && !target.starts_with("refs/") {
+        return Err(RGitError::new(
+            "fatal: Refusing to point HEAD outside of refs/".to_string(),
+            128,
+        ));
+    }
+    if !check_ref_name(target) {
+        return Err(RGitError::new(
+            format!(
+                "fatal: Refusing to set '{}' to invalid ref '{}'",
+                name, target
+            ),
+            128,
+        ));
+    }
+    let mut transaction = RefTransaction::new(repo.rgit_dir());
+    transaction.replace(
+        name,
+        &RefTarget::Symbolic(target.to_string()),
+        None,
+        message,
+    );
+    transaction.commit()?;
+    Ok(0)
+}
+
+fn delete_symbolic_ref(dir: &Path, name: &str, quiet: bool) -> Result<u8> {
+    let repo = Repository::discover(dir)?;
+    if name == "HEAD" {
+        return Err(RGitError::new(
+            "fatal: deleting 'HEAD' is not allowed".to_string(),
+            128,
+        ));
+    }
+    match read_ref_target(repo.rgit_dir(), name)? {
+        Some(RefTarget::Symbolic(_)) => {}
+        _ if quiet => return Ok(1),
+        _ => {
+            return Err(RGitError::new(
+                format!("fatal: Cannot delete {}, not a symbolic ref", name),
+                128,
+            ))
+        }
+    }
+    let mut transaction = RefTransaction::new(repo.rgit_dir());
+    transaction.no_deref().delete(name, None);
+    transaction.commit()?;
+    Ok(0)
+}
+
+pub fn rgit_symbolic_ref(args: &SymbolicRefArgs) -> Result<u8> {
+    let dir = env::current_dir()?;
+    if args.delete {
+        return delete_symbolic_ref(&dir, &args.name, args.quiet);
+    }
+    match &args.reference {
+        Some(target) => set_symbolic_ref(
+            &dir,
+            &args.name,
+            target,
+            args.message.as_deref().unwrap_or_default(),
+        ),
+        None => read_symbolic_ref(&dir, &args.name, args.quiet, args.short, &mut io::stdout()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::refs::read_reflog;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_symbolic_ref() {
+        let dir = tempdir().unwrap();
+        let path = dir.path();
+        let repo = Repository::init(path, None).unwrap();
+        fs::write(path.join("file"), "file").unwrap();
+        let commit = repo.commit("first").unwrap();
+
+        let mut buffer = Vec::new();
+        read_symbolic_ref(path, "HEAD", false, false, &mut buffer).unwrap();
+        assert_eq!(buffer, b"refs/heads/main\n");
+        let mut buffer = Vec::new();
+        read_symbolic_ref(path, "HEAD", false, true, &mut buffer).unwrap();
+        assert_eq!(buffer, b"main\n");
+
+        set_symbolic_ref(path, "HEAD", "refs/heads/topic", "to topic").unwrap();
+        assert_eq!(
+            repo.head_branch().unwrap(),
+            Some("refs/heads/topic".to_string())
+        );
+        // the unborn branch is not recorded in the reflog
+        assert_eq!(read_reflog(repo.rgit_dir(), "HEAD").unwrap().len(), 1);
+        set_symbolic_ref(path, "HEAD", "refs/heads/main", "back to main").unwrap();
+        let reflog = read_reflog(repo.rgit_dir(), "HEAD").unwrap();
+        assert_eq!(reflog.last().unwrap().message, "back to main");
+        assert_eq!(reflog.last().unwrap().new, commit);
+
+        let err = set_symbolic_ref(path, "HEAD", "main", "").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "fatal: Refusing to point HEAD outside of refs/"
+        );
+        let err = read_symbolic_ref(path, "refs/heads/main", false, false, &mut Vec::new());
+        assert_eq!(
+            err.unwrap_err().to_string(),
+            "fatal: ref refs/heads/main is not a symbolic ref"
+        );
+        let mut buffer = Vec::new();
+        let code = read_symbolic_ref(path, "refs/heads/main", true, false, &mut buffer).unwrap();
+        assert_eq!(code, 1);
+        assert!(buffer.is_empty());
+
+        set_symbolic_ref(path, "refs/remotes/origin/HEAD", "refs/heads/main", "").unwrap();
+        assert!(delete_symbolic_ref(path, "HEAD", false).is_err());
+        assert!(delete_symbolic_ref(path, "refs/heads/main", false).is_err());
+        delete_symbolic_ref(path, "refs/remotes/origin/HEAD", false).unwrap();
+        assert!(!repo.rgit_dir().join("refs/remotes").exists());
+        assert_eq!(repo.head().unwrap(), Some(commit));
+    }
+}
@@ -0,0 +1,257 @@
+use crate::config::Config;
+use crate::error::RGitError;
+use crate::hash::ObjectId;
+use crate::objects::{ObjectDatabase, Signature, Tag};
+use crate::refs::{check_ref_name, list_refs, read_ref, RefTransaction};
+use crate::revision::{abbreviate, resolve_revision};
+use crate::utils::get_rgit_dir;
+use anyhow::Result;
+use clap::Parser;
+use std::env;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// Create or list tags
+#[derive(Parser, Debug)]
+pub struct TagArgs {
+    /// Make an annotated tag object
+    #[arg(short = 'a', long = "annotate")]
+    pub annotate: bool,
+
+    /// Use the given tag message, implies -a
+    #[arg(short, long, conflicts_with = "file")]
+    pub message: Option<String>,
+
+    /// Take the tag message from the given file, `-` reads the standard input, implies -a
+    #[arg(short = 'F', long)]
+    pub file: Option<String>,
+
+    /// Replace an existing tag
+    #[arg(short, long)]
+    pub force: bool,
+
+    /// The name of the tag to create, the tags are listed when omitted
+    pub tagname: Option<String>,
+
+    /// The object the new tag refers to, `HEAD` by default
+    pub object: Option<String>,
+}
+
+/// Strips trailing whitespace from every line and surrounding blank lines, and ends the message
+/// with a newline, like `git stripspace`.
+fn clean_message(message: &str) -> String {
+    let lines = message.lines().map(str::trim_end).collect::<Vec<_>>();
+    let start = lines.iter().position(|line| !line.is_empty());
+    let end = lines.iter().rposition(|line| !line.is_empty());
+    match (start, end) {
+        (Some(start), Some(end)) => lines[start..=end].join("\n") + "\n",
+        _ => String::new(),
+    }
+}
+
+fn list_tags(rgit_dir: &Path, writer: &mut dyn io::Write) -> Result<u8> {
+    for (name, _) in list_refs(rgit_dir)? {
+        if let Some(tag) = name.strip_prefix("refs/tags/") {
+            writeln!(writer, "{}", tag)?;
+        }
+    }
+    Ok(0)
+}
+
+/// Creates `refs/tags/<name>` pointing to `object`, through a new tag object when `message` is
+/// given.
+fn tag(
+    dir: &Path,
+    name: &str,
+    object: &str,
+    message: Option<&str>,
+    force: bool,
+    writer: &mut dyn io::Write,
+) -> Result<u8> {
+    let rgit_dir = get_rgit_dir(dir)?;
+    let db = ObjectDatabase::open(&rgit_dir)?;
+
+    let ref_name = format!("refs/tags/{}", name);
+    if !check_ref_name(&ref_name) {
+        return Err(RGitError::new(
+            format!("fatal: '{}' is not a valid tag name.", name),
+            128,
+        ));
+    }
+    let previous = read_ref(&rgit_dir, &ref_name)?;
+    if previous.is_some() && !force {
+        return Err(RGitError::new(
+            format!("fatal: tag '{}' already exists", name),
+            128,
+        ));
+    }
+
+    let mut hash = resolve_revision(&db, &rgit_dir, object)?;
+    if let Some(message) = message {
+        let message = clean_message(message);
+        if message.is_empty() {
+            return Err(RGitError::new(
+                "fatal: no tag message given".to_string(),
+                128,
+            ));
+        }
+        let (tagger_name, tagger_email) = Config::load(&rgit_dir)?.identity()?;
+        let tag = Tag::new(
+            hash,
+            db.read_header(&hash)?.object_type,
+            name.to_string(),
+            Some(Signature::now(&tagger_name, &tagger_email)?),
+            message,
+        );
+        hash = tag.write_to_rgit_objects(&db)?;
+    }
+
+    // another process creating or moving the tag meanwhile makes the update fail
+    let old = previous.unwrap_or_else(|| ObjectId::null(hash.format()));
+    let mut transaction = RefTransaction::new(&rgit_dir);
+    transaction.update(&ref_name, &hash, Some(&old), "");
+    transaction.commit()?;
+    if let Some(previous) = previous.filter(|previous| *previous != hash) {
+        writeln!(
+            writer,
+            "Updated tag '{}' (was {})",
+            name,
+            abbreviate(&db, &previous)?
+        )?;
+    }
+
+    Ok(0)
+}
+
+pub fn rgit_tag(args: &TagArgs) -> Result<u8> {
+    let dir = env::current_dir()?;
+    let Some(name) = &args.tagname else {
+        return list_tags(&get_rgit_dir(&dir)?, &mut io::stdout());
+    };
+
+    let message = match (&args.message, &args.file) {
+        (Some(message), _) => Some(message.clone()),
+        (None, Some(file)) if file == "-" => {
+            let mut message = String::new();
+            io::stdin().read_to_string(&mut message)?;
+            Some(message)
+        }
+        (None, Some(file)) => Some(fs::read_to_string(file)?),
+        (None, None) if args.annotate => {
+            return Err(RGitError::new(
+                "fatal: no tag message given, use -m or -F".to_string(),
+                128,
+            ))
+        }
+        (None, None) => None,
+    };
+
+    tag(
+        &dir,
+        name,
+        args.object.as_deref().unwrap_or("HEAD"),
+        message.as_deref(),
+        args.force,
+        &mut io::stdout(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::ObjectFormat;
+    use crate::objects::{Commit, RGitObjectType, Tree};
+    use crate::utils::init_rgit_dir;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_clean_message() {
+        assert_eq!(clean_message("message"), "message\n");
+        assert_eq!(
+            clean_message("\n\nRelease  \n\nSigned-off-by: A <a@b>\n\n\n"),
+            "Release\n\nSigned-off-by: A <a@b>\n"
+        );
+        assert_eq!(clean_message(" \n\n"), "");
+    }
+
+    #[test]
+    fn test_tag() {
+        let dir = tempdir().unwrap();
+        let rgit_dir = init_rgit_dir(dir.path()).unwrap();
+        let db = ObjectDatabase::open(&rgit_dir).unwrap();
+        let mut config = Config::load(&rgit_dir).unwrap();
+        config.set("user.name", "A U Thor").unwrap();
+        config.set("user.email", "author@example.com").unwrap();
+        config.save().unwrap();
+
+        fs::write(dir.path().join("file"), "file content").unwrap();
+        let tree = Tree::from_directory(dir.path(), ObjectFormat::Sha1).unwrap();
+        tree.write_to_rgit_objects(&db).unwrap();
+        let commit = Commit::new(*tree.hash(), Vec::new(), "commit".to_string()).unwrap();
+        let commit_hash = commit.write_to_rgit_objects(&db).unwrap();
+        fs::write(rgit_dir.join("HEAD"), hex::encode(commit_hash)).unwrap();
+
+        // a lightweight tag and an annotated one
+        let mut buffer = Vec::new();
+        tag(dir.path(), "light", "HEAD", None, false, &mut buffer).unwrap();
+        let message = "Release 1.0\n\nSigned-off-by: A U Thor <author@example.com>";
+        tag(
+            dir.path(),
+            "v1.0",
+            "HEAD",
+            Some(message),
+            false,
+            &mut buffer,
+        )
+        .unwrap();
+        assert!(buffer.is_empty());
+
+        let refs = list_refs(&rgit_dir).unwrap();
+        assert_eq!(refs[1], ("refs/tags/light".to_string(), commit_hash));
+        let (_, tag_hash) = refs[2];
+        assert_eq!(
+            db.read_header(&tag_hash).unwrap().object_type,
+            RGitObjectType::Tag
+        );
+        let tag_object = Tag::from_rgit_objects(&db, &tag_hash).unwrap();
+        assert_eq!(tag_object.object(), &commit_hash);
+        assert_eq!(tag_object.message, format!("{}\n", message));
+        assert_eq!(
+            resolve_revision(&db, &rgit_dir, "v1.0^{tree}").unwrap(),
+            *tree.hash()
+        );
+
+        let mut buffer = Vec::new();
+        list_tags(&rgit_dir, &mut buffer).unwrap();
+        assert_eq!(String::from_utf8(buffer).unwrap(), "light\nv1.0\n");
+
+        // existing tags are only replaced with force
+        let result = tag(dir.path(), "light", "v1.0", None, false, &mut Vec::new());
+        assert!(result.unwrap_err().to_string().contains("already exists"));
+        let mut buffer = Vec::new();
+        tag(dir.path(), "light", "v1.0", None, true, &mut buffer).unwrap();
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            format!(
+                "Updated tag 'light' (was {})\n",
+                &commit_hash.to_string()[..7]
+            )
+        );
+
+        let result = tag(dir.path(), "v1..0", "HEAD", None, false, &mut Vec::new());
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("not a valid tag name"));
+        let result = tag(
+            dir.path(),
+            "empty",
+            "HEAD",
+            Some("\n"),
+            false,
+            &mut Vec::new(),
+        );
+        assert!(result.unwrap_err().to_string().contains("no tag message"));
+    }
+}
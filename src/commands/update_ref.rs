@@ -0,0 +1,397 @@
+use crate::error::RGitError;
+use crate::hash::ObjectId;
+use crate::objects::RGitObjectType;
+use crate::refs::RefTransaction;
+use crate::Repository;
+use anyhow::Result;
+use clap::Parser;
+use std::env;
+use std::io::{self, BufRead};
+use std::path::Path;
+
+const USAGE: &str = "usage: rgit update-ref [<options>] -d <refname> [<old-val>]\n   \
+                     or: rgit update-ref [<options>]    <refname> <new-val> [<old-val>]\n   \
+                     or: rgit update-ref [<options>] --stdin";
+
+/// Update the object name stored in a ref safely
+#[derive(Parser, Debug)]
+pub struct UpdateRefArgs {
+    /// Delete the ref, after checking that it still points to <old-val> when given
+    #[arg(short)]
+    pub delete: bool,
+
+    /// Update <refname> itself rather than the ref it points to when it is a symbolic ref
+    #[arg(long)]
+    pub no_deref: bool,
+
+    /// The reason of the update, recorded in the reflog
+    #[arg(short, value_name = "reason")]
+    pub message: Option<String>,
+
+    /// Read `update`, `create`, `delete` and `verify` commands from standard input, one per line,
+    /// and apply them all together or not at all
+    #[arg(long, conflicts_with = "delete")]
+    pub stdin: bool,
+
+    /// <refname> <new-val> [<old-val>], or <refname> [<old-val>] with -d. A null <old-val>
+    /// requires the ref not to exist.
+    #[arg(value_name = "args")]
+    pub args: Vec<String>,
+}
+
+/// Resolves a value given on the command line: an object name, or a null ID.
+fn resolve_value(repo: &Repository, value: &str) -> Result<ObjectId> {
+    repo.resolve(value)
+        .map_err(|_| RGitError::new(format!("fatal: {}: not a valid object name", value), 128))
+}
+
+/// Checks that `new` can be stored in the ref `name`: branches only point to commits.
+fn check_new_value(repo: &Repository, name: &str, new: &ObjectId) -> Result<()> {
+    if !repo.db().contains(new)? {
+        return Err(RGitError::new(
+            format!(
+                "fatal: trying to write ref '{}' with nonexistent object {}",
+                name, new
+            ),
+            128,
+        ));
+    }
+    if name.starts_with("refs/heads/")
+        && repo.db().read_header(new)?.object_type != RGitObjectType::Commit
+    {
+        return Err(RGitError::new(
+            format!(
+                "fatal: trying to write non-commit object {} to branch '{}'",
+                new, name
+            ),
+            128,
+        ));
+    }
+    Ok(())
+}
+
+/// Adds the update of `name` to `new`, which deletes it when `new` is null.
+fn add_update(
+    repo: &Repository,
+    transaction: &mut RefTransaction,
+    name: &str,
+    new: &ObjectId,
+    old: Option<&ObjectId>,
+    message: &str,
+) -> Result<()> {
+    if new.is_null() {
+        transaction.delete(name, old);
+    } else {
+        check_new_value(repo, name, new)?;
+        transaction.update(name, new, old, message);
+    }
+    Ok(())
+}
+
+fn update_ref(
+    dir: &Path,
+    args: &[String],
+    delete: bool,
+    no_deref: bool,
+    message: &str,
+) -> Result<u8> {
+    let repo = Repository::discover(dir)?;
+    let mut transaction = RefTransaction::new(repo.rgit_dir());
+    if no_deref {
+        transaction.no_deref();
+    }
+
+    match (delete, args) {
+        (true, [name]) => {
+            transaction.delete(name, None);
+        }
+        (true, [name, old]) => {
+            transaction.delete(name, Some(&resolve_value(&repo, old)?));
+        }
+        (false, [name, new]) => {
+            let new = resolve_value(&repo, new)?;
+            add_update(&repo, &mut transaction, name, &new, None, message)?;
+        }
+        (false, [name, new, old]) => {
+            let new = resolve_value(&repo, new)?;
+            let old = resolve_value(&repo, old)?;
+            add_update(&repo, &mut transaction, name, &new, Some(&old), message)?;
+        }
+        _ => return Err(RGitError::new(USAGE.to_string(), 129)),
+    }
+    transaction.commit()?;
+    Ok(0)
+}
+
+/// Applies the commands read from `reader` in a single transaction:
+///
+/// ```text
+/// update <ref> <new-val> [<old-val>]
+/// create <ref> <new-val>
+/// delete <ref> [<old-val>]
+/// verify <ref> [<old-val>]
+/// ```
+///
+/// An empty or null `<old-val>` requires the ref not to exist, and so does a missing one for
+/// `verify`.
+fn update_ref_stdin(
+    dir: &Path,
+    reader: &mut dyn BufRead,
+    no_deref: bool,
+    message: &str,
+) -> Result<u8> {
+    let repo = Repository::discover(dir)?;
+    let mut transaction = RefTransaction::new(repo.rgit_dir());
+    if no_deref {
+        transaction.no_deref();
+    }
+
+    for line in reader.lines() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        let mut words = line.split(' ');
+        let command = words.next().unwrap();
+        let (min, max) = match command {
+            "update" => (2, 3),
+            "create" => (2, 2),
+            "delete" | "verify" => (1, 2),
+            _ => {
+                return Err(RGitError::new(
+                    format!("fatal: unknown command: {}", line),
+                    128,
+                ))
+            }
+        };
+        let args = words.collect::<Vec<_>>();
+        let fail = |reason: String| {
+            RGitError::new(format!("fatal: {} {}: {}", command, args[0], reason), 128)
+        };
+        if args.is_empty() || args[0].is_empty() {
+            return Err(RGitError::new(
+                format!("fatal: {}: missing <ref>", command),
+                128,
+            ));
+        }
+        if args.len() < min {
+            return Err(fail("missing <newvalue>".to_string()));
+        }
+        if args.len() > max {
+            return Err(fail(format!("extra input: {}", args[max..].join(" "))));
+        }
+
+        let name = args[0];
+        let value = |value: &str, kind: &str| -> Result<ObjectId> {
+            if value.is_empty() {
+                return Ok(ObjectId::null(repo.format()));
+            }
+            repo.resolve(value)
+                .map_err(|_| fail(format!("invalid <{}>: {}", kind, value)))
+        };
+        let old = match command {
+            "create" => Some(ObjectId::null(repo.format())),
+            "update" => args.get(2).map(|old| value(old, "oldvalue")).transpose()?,
+            "verify" => Some(value(args.get(1).copied().unwrap_or_default(), "oldvalue")?),
+            _ => args.get(1).map(|old| value(old, "oldvalue")).transpose()?,
+        };
+        match command {
+            "update" | "create" => {
+                let new = value(args[1], "newvalue")?;
+                add_update(&repo, &mut transaction, name, &new, old.as_ref(), message)?;
+            }
+            "delete" => {
+                transaction.delete(name, old.as_ref());
+            }
+            _ => {
+                transaction.verify(name, old.as_ref());
+            }
+        }
+    }
+    transaction.commit()?;
+    Ok(0)
+}
+
+pub fn rgit_update_ref(args: &UpdateRefArgs) -> Result<u8> {
+    let dir = env::current_dir()?;
+    let message = args.message.as_deref().unwrap_or_default();
+    if args.stdin {
+        if !args.args.is_empty() {
+            return Err(RGitError::new(USAGE.to_string(), 129));
+        }
+        update_ref_stdin(&dir, &mut io::stdin().lock(), args.no_deref, message)
+    } else {
+        update_ref(&dir, &args.args, args.delete, args.no_deref, message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::refs::{read_ref, read_reflog};
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_update_ref() {
+        let dir = tempdir().unwrap();
+        let path = dir.path();
+        let repo = Repository::init(path, None).unwrap();
+        fs::write(path.join("file"), "first").unwrap();
+        let first = repo.commit("first").unwrap();
+        fs::write(path.join("file"), "second").unwrap();
+        let second = repo.commit("second").unwrap();
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+
+        let topic = args(&["refs/heads/topic", &first.to_string()]);
+        update_ref(path, &topic, false, false, "create topic").unwrap();
+        assert_eq!(
+            read_ref(repo.rgit_dir(), "refs/heads/topic").unwrap(),
+            Some(first)
+        );
+        let reflog = read_reflog(repo.rgit_dir(), "refs/heads/topic").unwrap();
+        assert_eq!(reflog[0].message, "create topic");
+
+        // the old value must match
+        let stale = args(&["refs/heads/topic", "main", "main"]);
+        let err = update_ref(path, &stale, false, false, "").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "fatal: cannot lock ref 'refs/heads/topic': is at {} but expected {}",
+                first, second
+            )
+        );
+        let moved = args(&["refs/heads/topic", "main", "topic"]);
+        update_ref(path, &moved, false, false, "").unwrap();
+        assert_eq!(
+            read_ref(repo.rgit_dir(), "refs/heads/topic").unwrap(),
+            Some(second)
+        );
+
+        // HEAD is followed to its branch, unless --no-deref
+        let head = args(&["HEAD", &first.to_string()]);
+        update_ref(path, &head, false, false, "").unwrap();
+        assert_eq!(
+            read_ref(repo.rgit_dir(), "refs/heads/main").unwrap(),
+            Some(first)
+        );
+        let head = args(&["HEAD", "topic"]);
+        update_ref(path, &head, false, true, "").unwrap();
+        assert_eq!(repo.head_branch().unwrap(), None);
+        assert_eq!(
+            read_ref(repo.rgit_dir(), "refs/heads/main").unwrap(),
+            Some(first)
+        );
+
+        let tree = args(&["refs/heads/tree", "HEAD^{tree}"]);
+        let err = update_ref(path, &tree, false, false, "").unwrap_err();
+        assert!(err.to_string().contains("non-commit object"));
+        let err = update_ref(path, &args(&["refs/heads/x", "nope"]), false, false, "");
+        assert_eq!(
+            err.unwrap_err().to_string(),
+            "fatal: nope: not a valid object name"
+        );
+        let err = update_ref(path, &args(&["refs/heads/x"]), false, false, "").unwrap_err();
+        assert_eq!(err.downcast_ref::<RGitError>().unwrap().exit_code(), 129);
+
+        let delete = args(&["refs/heads/topic", &first.to_string()]);
+        assert!(update_ref(path, &delete, true, false, "").is_err());
+        let delete = args(&["refs/heads/topic", &second.to_string()]);
+        update_ref(path, &delete, true, false, "").unwrap();
+        assert_eq!(read_ref(repo.rgit_dir(), "refs/heads/topic").unwrap(), None);
+    }
+
+    #[test]
+    fn test_update_ref_stdin() {
+        let dir = tempdir().unwrap();
+        let path = dir.path();
+        let repo = Repository::init(path, None).unwrap();
+        fs::write(path.join("file"), "first").unwrap();
+        let first = repo.commit("first").unwrap();
+        fs::write(path.join("file"), "second").unwrap();
+        let second = repo.commit("second").unwrap();
+
+        let mut input = format!(
+            "create refs/heads/a {}\n\
+             update refs/heads/b {} \n\
+             verify refs/heads/main {}\n",
+            first, second, second
+        );
+        update_ref_stdin(path, &mut input.as_bytes(), false, "batch").unwrap();
+        assert_eq!(
+            read_ref(repo.rgit_dir(), "refs/heads/a").unwrap(),
+            Some(first)
+        );
+        assert_eq!(
+            read_ref(repo.rgit_dir(), "refs/heads/b").unwrap(),
+            Some(second)
+        );
+
+        // a single failing command leaves every ref as it was
+        input = format!(
+            "update refs/heads/a {} {}\n\
+             delete refs/heads/b\n\
+             create refs/heads/main {}\n",
+            second, first, first
+        );
+        let err = update_ref_stdin(path, &mut input.as_bytes(), false, "").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "fatal: cannot lock ref 'refs/heads/main': reference already exists"
+        );
+        assert_eq!(
+            read_ref(repo.rgit_dir(), "refs/heads/a").unwrap(),
+            Some(first)
+        );
+        assert_eq!(
+            read_ref(repo.rgit_dir(), "refs/heads/b").unwrap(),
+            Some(second)
+        );
+
+        input = format!(
+            "update refs/heads/a {} {}\n\
+             delete refs/heads/b\n",
+            second, first
+        );
+        update_ref_stdin(path, &mut input.as_bytes(), false, "").unwrap();
+        assert_eq!(
+            read_ref(repo.rgit_dir(), "refs/heads/a").unwrap(),
+            Some(second)
+        );
+        assert_eq!(read_ref(repo.rgit_dir(), "refs/heads/b").unwrap(), None);
+
+        // verify without <old-val> requires the ref not to exist
+        update_ref_stdin(path, &mut "verify refs/heads/b\n".as_bytes(), false, "").unwrap();
+        let input = "verify refs/heads/a\ndelete refs/heads/main\n";
+        let err = update_ref_stdin(path, &mut input.as_bytes(), false, "").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "fatal: cannot lock ref 'refs/heads/a': reference already exists"
+        );
+        assert_eq!(
+            read_ref(repo.rgit_dir(), "refs/heads/main").unwrap(),
+            Some(second)
+        );
+
+        for (input, message) in [
+            ("foo\n", "fatal: unknown command: foo"),
+            (
+                "update refs/heads/a\n",
+                "fatal: update refs/heads/a: missing <newvalue>",
+            ),
+            (
+                "create refs/heads/c HEAD extra\n",
+                "fatal: create refs/heads/c: extra input: extra",
+            ),
+            ("delete\n", "fatal: delete: missing <ref>"),
+            (
+                "update refs/heads/a nope\n",
+                "fatal: update refs/heads/a: invalid <newvalue>: nope",
+            ),
+        ] {
+            let err = update_ref_stdin(path, &mut input.as_bytes(), false, "").unwrap_err();
+            assert_eq!(err.to_string(), message);
+        }
+    }
+}
@@ -1,17 +1,12 @@
-use crate::objects::Tree;
-use crate::utils::get_rgit_dir;
+use crate::Repository;
 use anyhow::Result;
 use std::env;
 use std::io;
 use std::path::Path;
 
 pub fn write_tree(dir: &Path, writer: &mut dyn io::Write) -> Result<u8> {
-    let rgit_dir = get_rgit_dir(dir)?;
-    let tree = Tree::from_directory(dir)?;
-    let tree_hash = tree.hash();
-    tree.write_to_rgit_objects(rgit_dir.as_path())?;
-
-    writeln!(writer, "{}", hex::encode(tree_hash))?;
+    let tree_hash = Repository::discover(dir)?.write_tree()?;
+    writeln!(writer, "{}", tree_hash)?;
     Ok(0)
 }
 
@@ -22,7 +17,8 @@ pub fn rgit_write_tree() -> Result<u8> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::objects::RGitObject;
+    use crate::hash::ObjectId;
+    use crate::objects::{ObjectDatabase, RGitObject, Tree};
     use crate::utils::init_rgit_dir;
     use std::fs;
     use tempfile::tempdir;
@@ -30,7 +26,7 @@ mod tests {
     #[test]
     fn test_write_tree() {
         let dir = tempdir().unwrap();
-        let rgit_dir = init_rgit_dir(dir.path()).unwrap();
+        let db = ObjectDatabase::open(&init_rgit_dir(dir.path()).unwrap()).unwrap();
 
         let file1_path = dir.path().join("file1.txt");
         let file2_path = dir.path().join("file2.txt");
@@ -47,11 +43,9 @@ mod tests {
         assert!(result.is_ok());
         let tree_hash = String::from_utf8(buffer).unwrap();
         let tree_hash = hex::decode(tree_hash.trim()).unwrap();
-        assert_eq!(tree_hash.len(), 20);
-        let mut tree_hash_array = [0; 20];
-        tree_hash_array.copy_from_slice(&tree_hash);
+        let tree_hash_array = ObjectId::from_bytes(&tree_hash).unwrap();
 
-        let tree = Tree::from_rgit_objects(rgit_dir.as_path(), &tree_hash_array).unwrap();
+        let tree = Tree::from_rgit_objects(&db, &tree_hash_array).unwrap();
         let mut buffer = Vec::new();
         tree.print(&mut buffer).unwrap();
         let tree_content = String::from_utf8(buffer).unwrap();
@@ -70,12 +64,9 @@ mod tests {
             .nth(2)
             .unwrap();
         let subdir_tree_hash = hex::decode(subdir_tree_hash).unwrap();
-        assert_eq!(subdir_tree_hash.len(), 20);
-        let mut subdir_tree_hash_array = [0; 20];
-        subdir_tree_hash_array.copy_from_slice(&subdir_tree_hash);
+        let subdir_tree_hash_array = ObjectId::from_bytes(&subdir_tree_hash).unwrap();
 
-        let subdir_tree =
-            Tree::from_rgit_objects(rgit_dir.as_path(), &subdir_tree_hash_array).unwrap();
+        let subdir_tree = Tree::from_rgit_objects(&db, &subdir_tree_hash_array).unwrap();
         let mut buffer = Vec::new();
         subdir_tree.print(&mut buffer).unwrap();
         let subdir_tree_content = String::from_utf8(buffer).unwrap();
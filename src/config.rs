@@ -0,0 +1,262 @@
+use crate::error::RGitError;
+use crate::hash::ObjectFormat;
+use anyhow::Result;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The repository configuration stored in `.rgit/config`, in git's ini-like format.
+///
+/// Only plain `[section]` and `[section "subsection"]` headers with `key = value` lines are
+/// supported. Names are addressed as `section.key` or `section.subsection.key`.
+#[derive(Debug)]
+pub struct Config {
+    path: PathBuf,
+    /// `(section, key, value)` in file order, sections include their subsection
+    entries: Vec<(String, String, String)>,
+}
+
+fn parse_section(line: &str) -> Result<String> {
+    let header = line
+        .strip_prefix('[')
+        .and_then(|line| line.strip_suffix(']'))
+        .ok_or_else(|| anyhow::anyhow!("Invalid config section: {:?}", line))?;
+    match header.split_once(' ') {
+        Some((section, subsection)) => {
+            let subsection = subsection
+                .trim()
+                .strip_prefix('"')
+                .and_then(|subsection| subsection.strip_suffix('"'))
+                .ok_or_else(|| anyhow::anyhow!("Invalid config section: {:?}", line))?;
+            Ok(format!("{}.{}", section.to_lowercase(), subsection))
+        }
+        None => Ok(header.to_lowercase()),
+    }
+}
+
+fn split_name(name: &str) -> Result<(String, String)> {
+    let (section, key) = name
+        .rsplit_once('.')
+        .ok_or_else(|| anyhow::anyhow!("Invalid config name: {:?}", name))?;
+    // section names and keys are case-insensitive, subsection names are not
+    let section = match section.split_once('.') {
+        Some((section, subsection)) => format!("{}.{}", section.to_lowercase(), subsection),
+        None => section.to_lowercase(),
+    };
+    Ok((section, key.to_lowercase()))
+}
+
+impl Config {
+    /// Loads the configuration of the repository at `rgit_dir`. A missing file is an empty
+    /// configuration.
+    pub fn load(rgit_dir: &Path) -> Result<Self> {
+        let path = rgit_dir.join("config");
+        let mut entries = Vec::new();
+        if path.is_file() {
+            let mut section = None;
+            for line in fs::read_to_string(&path)?.lines() {
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                    continue;
+                }
+                if line.starts_with('[') {
+                    section = Some(parse_section(line)?);
+                    continue;
+                }
+
+                let section = section
+                    .clone()
+                    .ok_or_else(|| anyhow::anyhow!("Config entry outside of a section"))?;
+                let (key, value) = line.split_once('=').unwrap_or((line, "true"));
+                let value = value.trim();
+                let value = value
+                    .strip_prefix('"')
+                    .and_then(|value| value.strip_suffix('"'))
+                    .unwrap_or(value);
+                entries.push((section, key.trim().to_lowercase(), value.to_string()));
+            }
+        }
+
+        Ok(Self { path, entries })
+    }
+
+    /// Returns the last value set for `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        let (section, key) = split_name(name).ok()?;
+        self.entries
+            .iter()
+            .rev()
+            .find(|entry| entry.0 == section && entry.1 == key)
+            .map(|entry| entry.2.as_str())
+    }
+
+    pub fn set(&mut self, name: &str, value: &str) -> Result<()> {
+        let (section, key) = split_name(name)?;
+        match self
+            .entries
+            .iter_mut()
+            .rev()
+            .find(|entry| entry.0 == section && entry.1 == key)
+        {
+            Some(entry) => entry.2 = value.to_string(),
+            None => {
+                // keep the entries of a section together
+                let pos = self
+                    .entries
+                    .iter()
+                    .rposition(|entry| entry.0 == section)
+                    .map_or(self.entries.len(), |pos| pos + 1);
+                self.entries.insert(pos, (section, key, value.to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let mut content = String::new();
+        let mut current_section = None;
+        for (section, key, value) in self.entries.iter() {
+            if current_section != Some(section) {
+                match section.split_once('.') {
+                    Some((section, subsection)) => {
+                        content.push_str(&format!("[{} \"{}\"]\n", section, subsection))
+                    }
+                    None => content.push_str(&format!("[{}]\n", section)),
+                }
+                current_section = Some(section);
+            }
+            content.push_str(&format!("\t{} = {}\n", key, value));
+        }
+
+        let lock_path = self.path.with_extension("lock");
+        fs::write(&lock_path, content)?;
+        fs::rename(&lock_path, &self.path)?;
+        Ok(())
+    }
+
+    /// The name and email to record as the author of tags, from `RGIT_COMMITTER_NAME` and
+    /// `RGIT_COMMITTER_EMAIL` or else `user.name` and `user.email`.
+    pub fn identity(&self) -> Result<(String, String)> {
+        self.identity_with(|variable| env::var(variable).ok())
+    }
+
+    /// Like [`Config::identity`], reading the environment variables through `env`.
+    pub fn identity_with(&self, env: impl Fn(&str) -> Option<String>) -> Result<(String, String)> {
+        let lookup = |variable: &str, name: &str| {
+            env(variable)
+                .or_else(|| self.get(name).map(|value| value.to_string()))
+                .filter(|value| !value.trim().is_empty())
+        };
+        let (Some(name), Some(email)) = (
+            lookup("RGIT_COMMITTER_NAME", "user.name"),
+            lookup("RGIT_COMMITTER_EMAIL", "user.email"),
+        ) else {
+            return Err(RGitError::new(
+                "fatal: unable to auto-detect the committer identity, set user.name and \
+                 user.email in .rgit/config or RGIT_COMMITTER_NAME and RGIT_COMMITTER_EMAIL"
+                    .to_string(),
+                128,
+            ));
+        };
+        if [&name, &email]
+            .iter()
+            .any(|value| value.contains(['<', '>', '\n']))
+        {
+            return Err(RGitError::new(
+                format!("fatal: invalid identity '{} <{}>'", name, email),
+                128,
+            ));
+        }
+        Ok((name.trim().to_string(), email.trim().to_string()))
+    }
+
+    /// The number of threads to hash the work tree with, from `index.threads`. As in git, `true`
+    /// or 0 (the default) use one thread per CPU and `false` or 1 hash serially.
+    pub fn threads(&self) -> Result<usize> {
+        match self.get("index.threads") {
+            None | Some("true") => Ok(0),
+            Some("false") => Ok(1),
+            Some(threads) => threads.parse().map_err(|_| {
+                RGitError::new(
+                    format!(
+                        "fatal: bad numeric config value '{}' for 'index.threads'",
+                        threads
+                    ),
+                    128,
+                )
+            }),
+        }
+    }
+
+    /// The object format recorded by `rgit init`, repositories without one use SHA-1.
+    pub fn object_format(&self) -> Result<ObjectFormat> {
+        match self.get("extensions.objectformat") {
+            Some(format) => format.to_lowercase().parse(),
+            None => Ok(ObjectFormat::Sha1),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_config() {
+        let dir = tempdir().unwrap();
+        let config = Config::load(dir.path()).unwrap();
+        assert_eq!(config.get("core.bare"), None);
+        assert_eq!(config.object_format().unwrap(), ObjectFormat::Sha1);
+
+        fs::write(
+            dir.path().join("config"),
+            "# comment\n[core]\n\trepositoryformatversion = 1\n\tbare\n\
+             [remote \"Origin\"]\n\turl = \"../other\"\n[Extensions]\n\tobjectFormat = sha256\n",
+        )
+        .unwrap();
+        let mut config = Config::load(dir.path()).unwrap();
+        assert_eq!(config.get("core.repositoryformatversion"), Some("1"));
+        assert_eq!(config.get("CORE.Bare"), Some("true"));
+        assert_eq!(config.get("remote.Origin.url"), Some("../other"));
+        assert_eq!(config.get("remote.origin.url"), None);
+        assert_eq!(config.object_format().unwrap(), ObjectFormat::Sha256);
+
+        config.set("core.repositoryformatversion", "0").unwrap();
+        config.set("core.filemode", "true").unwrap();
+        config.set("user.name", "rgit").unwrap();
+        config.save().unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.path().join("config")).unwrap(),
+            "[core]\n\trepositoryformatversion = 0\n\tbare = true\n\tfilemode = true\n\
+             [remote \"Origin\"]\n\turl = ../other\n[extensions]\n\tobjectformat = sha256\n\
+             [user]\n\tname = rgit\n"
+        );
+        let mut config = Config::load(dir.path()).unwrap();
+        assert_eq!(config.get("user.name"), Some("rgit"));
+
+        // without RGIT_COMMITTER_NAME and RGIT_COMMITTER_EMAIL in the environment
+        let no_env = |_: &str| None;
+        assert!(config.identity_with(no_env).is_err());
+        config.set("user.email", "rgit@example.com").unwrap();
+        assert_eq!(
+            config.identity_with(no_env).unwrap(),
+            ("rgit".to_string(), "rgit@example.com".to_string())
+        );
+        let env = |variable: &str| (variable == "RGIT_COMMITTER_NAME").then(|| "env".to_string());
+        assert_eq!(
+            config.identity_with(env).unwrap(),
+            ("env".to_string(), "rgit@example.com".to_string())
+        );
+        config.set("user.email", "<rgit@example.com>").unwrap();
+        assert!(config.identity_with(no_env).is_err());
+
+        assert_eq!(config.threads().unwrap(), 0);
+        for (value, threads) in [("true", 0), ("false", 1), ("4", 4)] {
+            config.set("index.threads", value).unwrap();
+            assert_eq!(config.threads().unwrap(), threads);
+        }
+        config.set("index.threads", "many").unwrap();
+        assert!(config.threads().is_err());
+    }
+}
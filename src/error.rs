@@ -1,20 +1,146 @@
-use anyhow::Error;
+use crate::hash::ObjectId;
+use crate::objects::RGitObjectType;
 use std::fmt::{Display, Formatter, Result};
+use std::io;
+use std::path::PathBuf;
 
+/// The errors rgit reports to its users. Library consumers can tell them apart by downcasting
+/// the `anyhow::Error` returned by the API, the binary prints them and exits with
+/// [`RGitError::exit_code`].
 #[derive(Debug)]
-pub struct RGitError {
-    pub message: String,
-    pub exit_code: u8,
+pub enum RGitError {
+    /// No `.rgit` directory in `path`, or in the current directory and any of its parents when
+    /// `path` is `None`.
+    NotARepository {
+        path: Option<PathBuf>,
+    },
+    /// The object database holds no object with this ID.
+    ObjectNotFound {
+        id: ObjectId,
+    },
+    /// The object is stored but can't be read back. Unlike git, which names loose and packed
+    /// objects differently, the message is the same for both.
+    CorruptObject {
+        id: ObjectId,
+        reason: String,
+    },
+    /// The object is not of the type it is read as.
+    WrongObjectType {
+        id: ObjectId,
+        expected: RGitObjectType,
+        actual: RGitObjectType,
+    },
+    /// A name that is not one of `blob`, `tree`, `commit` and `tag`.
+    InvalidObjectType {
+        name: String,
+    },
+    /// An abbreviated object name matches several objects.
+    AmbiguousName {
+        name: String,
+        candidates: Vec<(ObjectId, RGitObjectType)>,
+    },
+    /// A name that is neither an object ID nor a ref.
+    InvalidRef {
+        name: String,
+    },
+    Io(io::Error),
+    /// Any other fatal error, with the message and exit code to report.
+    Fatal {
+        message: String,
+        exit_code: u8,
+    },
 }
 
 impl RGitError {
-    pub fn new(message: String, exit_code: u8) -> Error {
-        Error::msg(Self { message, exit_code })
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(message: String, exit_code: u8) -> anyhow::Error {
+        Self::Fatal { message, exit_code }.into()
+    }
+
+    /// Reports that the object `id` failed to parse because of `reason`.
+    pub fn corrupt_object(id: &ObjectId, reason: impl Display) -> anyhow::Error {
+        Self::CorruptObject {
+            id: *id,
+            reason: reason.to_string(),
+        }
+        .into()
+    }
+
+    /// Reports that the object `id` is of type `actual` where one of type `expected` is needed.
+    pub fn wrong_object_type(
+        id: &ObjectId,
+        expected: RGitObjectType,
+        actual: RGitObjectType,
+    ) -> anyhow::Error {
+        Self::WrongObjectType {
+            id: *id,
+            expected,
+            actual,
+        }
+        .into()
+    }
+
+    /// The exit code git uses for the same failure, 128 for anything it `die()`s on.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Self::Fatal { exit_code, .. } => *exit_code,
+            _ => 128,
+        }
     }
 }
 
 impl Display for RGitError {
     fn fmt(&self, f: &mut Formatter) -> Result {
-        write!(f, "{}", self.message,)
+        match self {
+            Self::NotARepository { path: None } => write!(
+                f,
+                "fatal: not a rgit repository (or any of the parent directories): .rgit"
+            ),
+            Self::NotARepository { path: Some(path) } => {
+                write!(f, "fatal: not a rgit repository: '{}'", path.display())
+            }
+            Self::ObjectNotFound { id } => write!(f, "fatal: Not a valid object name {}", id),
+            Self::CorruptObject { id, reason } => {
+                write!(f, "fatal: object {} is corrupt: {}", id, reason)
+            }
+            Self::WrongObjectType {
+                id,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "fatal: object {} is a {}, not a {}",
+                id, actual, expected
+            ),
+            Self::InvalidObjectType { name } => {
+                write!(f, "fatal: invalid object type \"{}\"", name)
+            }
+            Self::AmbiguousName { name, candidates } => {
+                writeln!(f, "error: short object ID {} is ambiguous", name)?;
+                writeln!(f, "hint: The candidates are:")?;
+                for (id, object_type) in candidates {
+                    writeln!(f, "hint:   {} {}", id, object_type)?;
+                }
+                write!(f, "fatal: Not a valid object name {}", name)
+            }
+            Self::InvalidRef { name } => write!(f, "fatal: Not a valid object name {}", name),
+            Self::Io(err) => write!(f, "fatal: {}", err),
+            Self::Fatal { message, .. } => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for RGitError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for RGitError {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
     }
 }
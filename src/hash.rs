@@ -1,11 +1,13 @@
 use crate::error::RGitError;
 use anyhow::Result;
 use sha1::{self, Digest};
+use std::fmt;
 use std::io::Read;
+use std::str::FromStr;
 
 pub trait Hasher {
     fn update(&mut self, data: &[u8]);
-    fn finalize(self) -> [u8; 20];
+    fn finalize(self: Box<Self>) -> ObjectId;
 }
 
 pub struct Sha1 {
@@ -20,19 +22,255 @@ impl Sha1 {
     }
 }
 
+impl Default for Sha1 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Hasher for Sha1 {
     fn update(&mut self, data: &[u8]) {
         self.inner.update(data);
     }
 
-    fn finalize(self) -> [u8; 20] {
-        self.inner.finalize().into()
+    fn finalize(self: Box<Self>) -> ObjectId {
+        ObjectId::from_bytes(&self.inner.finalize()).unwrap()
+    }
+}
+
+pub struct Sha256 {
+    inner: sha2::Sha256,
+}
+
+impl Sha256 {
+    pub fn new() -> Self {
+        Self {
+            inner: sha2::Sha256::new(),
+        }
+    }
+}
+
+impl Default for Sha256 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Hasher for Sha256 {
+    fn update(&mut self, data: &[u8]) {
+        self.inner.update(data);
+    }
+
+    fn finalize(self: Box<Self>) -> ObjectId {
+        ObjectId::from_bytes(&self.inner.finalize()).unwrap()
+    }
+}
+
+/// The hash function a repository names its objects with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ObjectFormat {
+    Sha1,
+    Sha256,
+}
+
+impl fmt::Display for ObjectFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ObjectFormat::Sha1 => write!(f, "sha1"),
+            ObjectFormat::Sha256 => write!(f, "sha256"),
+        }
+    }
+}
+
+impl FromStr for ObjectFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "sha1" => Ok(ObjectFormat::Sha1),
+            "sha256" => Ok(ObjectFormat::Sha256),
+            _ => Err(RGitError::new(
+                format!("fatal: unknown hash algorithm '{}'", s),
+                128,
+            )),
+        }
+    }
+}
+
+impl ObjectFormat {
+    /// Length of an object ID in bytes.
+    pub fn raw_len(&self) -> usize {
+        match self {
+            ObjectFormat::Sha1 => 20,
+            ObjectFormat::Sha256 => 32,
+        }
+    }
+
+    pub fn hasher(&self) -> Box<dyn Hasher> {
+        match self {
+            ObjectFormat::Sha1 => Box::new(Sha1::new()),
+            ObjectFormat::Sha256 => Box::new(Sha256::new()),
+        }
+    }
+}
+
+/// An object ID of any of the supported object formats.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ObjectId {
+    format: ObjectFormat,
+    // only the first `format.raw_len()` bytes are used, the rest stays zeroed
+    bytes: [u8; 32],
+}
+
+impl ObjectId {
+    /// Creates an object ID from its raw bytes, the format is told apart by their length.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let format = match bytes.len() {
+            20 => ObjectFormat::Sha1,
+            32 => ObjectFormat::Sha256,
+            len => return Err(anyhow::anyhow!("Invalid object ID length: {}", len)),
+        };
+        let mut id = Self {
+            format,
+            bytes: [0; 32],
+        };
+        id.bytes[..bytes.len()].copy_from_slice(bytes);
+        Ok(id)
+    }
+
+    /// The all-zero ID of `format`, which reflogs use for a ref that does not exist.
+    pub fn null(format: ObjectFormat) -> Self {
+        Self {
+            format,
+            bytes: [0; 32],
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.bytes.iter().all(|&byte| byte == 0)
+    }
+
+    pub fn from_hex(hex: &str) -> Result<Self> {
+        let invalid = || -> anyhow::Error {
+            RGitError::InvalidRef {
+                name: hex.to_string(),
+            }
+            .into()
+        };
+        if hex.len() != 40 && hex.len() != 64 {
+            return Err(invalid());
+        }
+        let bytes = hex::decode(hex).map_err(|_| invalid())?;
+        Self::from_bytes(&bytes)
+    }
+
+    pub fn format(&self) -> ObjectFormat {
+        self.format
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.format.raw_len()]
+    }
+}
+
+impl AsRef<[u8]> for ObjectId {
+    fn as_ref(&self) -> &[u8] {
+        self.as_bytes()
     }
 }
 
-pub fn hash_object<R: Read>(mut reader: R) -> Result<[u8; 20]> {
-    let mut hasher = Sha1::new();
-    let mut buffer = [0; 1024];
+impl fmt::Display for ObjectId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", hex::encode(self.as_bytes()))
+    }
+}
+
+impl fmt::Debug for ObjectId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ObjectId({})", self)
+    }
+}
+
+/// The first hex digits of an object ID, as written in an abbreviated object name.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct ObjectIdPrefix {
+    // the digits packed two per byte like in an ID, the rest stays zeroed
+    id: ObjectId,
+    len: usize,
+}
+
+impl ObjectIdPrefix {
+    /// Parses the hex digits `hex`, the beginning of an object ID of `format`.
+    pub fn from_hex(hex: &str, format: ObjectFormat) -> Result<Self> {
+        let invalid = || -> anyhow::Error {
+            RGitError::InvalidRef {
+                name: hex.to_string(),
+            }
+            .into()
+        };
+        if hex.len() > format.raw_len() * 2 {
+            return Err(invalid());
+        }
+        let mut id = ObjectId::null(format);
+        for (i, digit) in hex.chars().enumerate() {
+            let digit = digit.to_digit(16).ok_or_else(invalid)? as u8;
+            id.bytes[i / 2] |= if i % 2 == 0 { digit << 4 } else { digit };
+        }
+        Ok(Self { id, len: hex.len() })
+    }
+
+    /// The first `len` hex digits of `hash`, all of them if it has fewer.
+    pub fn of(hash: &ObjectId, len: usize) -> Self {
+        let len = len.min(hash.format.raw_len() * 2);
+        let mut id = ObjectId::null(hash.format);
+        id.bytes[..len / 2].copy_from_slice(&hash.bytes[..len / 2]);
+        if len % 2 == 1 {
+            id.bytes[len / 2] = hash.bytes[len / 2] & 0xf0;
+        }
+        Self { id, len }
+    }
+
+    /// The number of hex digits.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The smallest object ID that starts with this prefix, where a sorted list of IDs is
+    /// searched from.
+    pub fn first(&self) -> ObjectId {
+        self.id
+    }
+
+    pub fn matches(&self, hash: &ObjectId) -> bool {
+        let whole = self.len / 2;
+        hash.format == self.id.format
+            && hash.bytes[..whole] == self.id.bytes[..whole]
+            && (self.len.is_multiple_of(2) || hash.bytes[whole] & 0xf0 == self.id.bytes[whole])
+    }
+}
+
+impl fmt::Display for ObjectIdPrefix {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", &self.id.to_string()[..self.len])
+    }
+}
+
+impl fmt::Debug for ObjectIdPrefix {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ObjectIdPrefix({})", self)
+    }
+}
+
+/// How much of an object is read at once when hashing it.
+const HASH_BUFFER_SIZE: usize = 128 * 1024;
+
+pub fn hash_object<R: Read>(mut reader: R, format: ObjectFormat) -> Result<ObjectId> {
+    let mut hasher = format.hasher();
+    let mut buffer = vec![0; HASH_BUFFER_SIZE];
     loop {
         let bytes_read = reader.read(&mut buffer)?;
         if bytes_read == 0 {
@@ -43,51 +281,85 @@ pub fn hash_object<R: Read>(mut reader: R) -> Result<[u8; 20]> {
     Ok(hasher.finalize())
 }
 
-pub fn hash_array_from_str(hash: &str) -> Result<[u8; 20]> {
-    let mut hash_array = [0u8; 20];
-    hex::decode_to_slice(hash, &mut hash_array)
-        .map_err(|_| RGitError::new("fatal: Not a valid object name".to_string(), 128))?;
-    Ok(hash_array)
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::process;
 
-    #[test]
-    fn test_hash_object() {
-        // use `echo -n "<content>" | sha1sum | awk '{print $1}'` to get the ground truth
-        let content = "hello world";
-        let result = hash_object(content.as_bytes()).unwrap();
-
-        let command = process::Command::new("sh")
+    fn ground_truth(content: &str, command: &str) -> String {
+        let output = process::Command::new("sh")
             .arg("-c")
             .arg(format!(
-                "echo -n \"{}\" | sha1sum | awk '{{print $1}}'",
-                content
+                "echo -n \"{}\" | {} | awk '{{print $1}}'",
+                content, command
             ))
             .output()
             .unwrap();
-        let ground_truth = String::from_utf8(command.stdout).unwrap();
-
-        assert_eq!(hex::encode(result), ground_truth.trim());
+        String::from_utf8(output.stdout).unwrap().trim().to_string()
     }
 
     #[test]
-    fn test_hash_array_from_str() {
-        let command = process::Command::new("sh")
-            .arg("-c")
-            .arg("echo -n \"hello world\" | sha1sum | awk '{print $1}'")
-            .output()
-            .unwrap();
-        let ground_truth = String::from_utf8(command.stdout).unwrap();
+    fn test_hash_object() {
+        // use `echo -n "<content>" | sha1sum | awk '{print $1}'` to get the ground truth
+        let content = "hello world";
+        let result = hash_object(content.as_bytes(), ObjectFormat::Sha1).unwrap();
+        assert_eq!(result.format(), ObjectFormat::Sha1);
+        assert_eq!(result.to_string(), ground_truth(content, "sha1sum"));
 
-        let result = hash_array_from_str(&ground_truth.trim()).unwrap();
-        assert_eq!(hex::encode(result), ground_truth.trim());
+        let result = hash_object(content.as_bytes(), ObjectFormat::Sha256).unwrap();
+        assert_eq!(result.format(), ObjectFormat::Sha256);
+        assert_eq!(result.to_string(), ground_truth(content, "sha256sum"));
+    }
+
+    #[test]
+    fn test_object_id_from_hex() {
+        for command in ["sha1sum", "sha256sum"] {
+            let ground_truth = ground_truth("hello world", command);
+            let result = ObjectId::from_hex(&ground_truth).unwrap();
+            assert_eq!(result.to_string(), ground_truth);
+            assert_eq!(result.as_bytes().len(), ground_truth.len() / 2);
+        }
 
         // test invalid hash
-        let result = hash_array_from_str("invalid hash");
+        let result = ObjectId::from_hex("invalid hash");
         assert!(result.is_err());
+        let result = ObjectId::from_hex(&"ab".repeat(24));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_object_id_prefix() {
+        let hash = ObjectId::from_hex(&ground_truth("hello world", "sha1sum")).unwrap();
+        let hex = hash.to_string();
+        for len in [0, 1, 4, 7, 40] {
+            let prefix = ObjectIdPrefix::from_hex(&hex[..len], ObjectFormat::Sha1).unwrap();
+            assert_eq!(prefix, ObjectIdPrefix::of(&hash, len));
+            assert_eq!(prefix.len(), len);
+            assert_eq!(prefix.to_string(), hex[..len]);
+            assert!(prefix.matches(&hash));
+            assert!(prefix.first() <= hash);
+        }
+        assert_eq!(ObjectIdPrefix::of(&hash, 41).len(), 40);
+
+        let prefix = ObjectIdPrefix::from_hex("2aa", ObjectFormat::Sha1).unwrap();
+        assert_eq!(prefix.first().to_string(), format!("2aa{}", "0".repeat(37)));
+        assert!(prefix.matches(&ObjectId::from_hex(&format!("2aaf{}", "0".repeat(36))).unwrap()));
+        assert!(!prefix.matches(&ObjectId::from_hex(&format!("2ab0{}", "0".repeat(36))).unwrap()));
+        assert!(!prefix.matches(&ObjectId::null(ObjectFormat::Sha256)));
+
+        assert!(ObjectIdPrefix::from_hex("xyz", ObjectFormat::Sha1).is_err());
+        assert!(ObjectIdPrefix::from_hex(&"a".repeat(41), ObjectFormat::Sha1).is_err());
+        assert!(ObjectIdPrefix::from_hex(&"a".repeat(64), ObjectFormat::Sha256).is_ok());
+    }
+
+    #[test]
+    fn test_object_format() {
+        assert_eq!("sha1".parse::<ObjectFormat>().unwrap(), ObjectFormat::Sha1);
+        assert_eq!(
+            "sha256".parse::<ObjectFormat>().unwrap(),
+            ObjectFormat::Sha256
+        );
+        assert!("md5".parse::<ObjectFormat>().is_err());
+        assert_eq!(ObjectFormat::Sha256.to_string(), "sha256");
     }
 }
@@ -110,8 +110,9 @@ fn path_equal(p: &path::Path, q: &path::Path) -> Result<bool> {
 }
 
 pub fn is_ignored(file_path: &path::Path) -> Result<RGitIgnoreResult> {
-    let rgit_dir = get_rgit_dir(file_path)?;
+    // the parent decides the repository, a nested repository's own .rgit does not count
     let mut cur_dir = file_path.parent().unwrap();
+    let rgit_dir = get_rgit_dir(cur_dir)?;
     let mut result = None;
     let mut matched_rule = None;
 
@@ -0,0 +1,20 @@
+//! rgit, a simple git implementation.
+//!
+//! [`Repository`] is the entry point to use rgit as a library: it finds, creates or opens a
+//! repository, reads and writes its objects, builds trees from the work tree and records commits.
+//! The `rgit` binary is a thin command line interface over the [`commands`] built on top of it.
+
+mod checkout;
+pub mod commands;
+pub mod config;
+pub mod error;
+pub mod hash;
+pub mod ignore;
+pub mod objects;
+pub mod refs;
+mod repository;
+pub mod revision;
+pub mod utils;
+
+pub use error::RGitError;
+pub use repository::Repository;
@@ -1,48 +1,64 @@
 mod cli;
-mod commands;
-mod error;
-mod hash;
-mod ignore;
-mod objects;
-mod utils;
 
 use clap::Parser;
 use cli::{RustGitArgs, RustGitSubCommands};
-use commands::{
-    rgit_cat_file, rgit_check_ignore, rgit_commit, rgit_hash_object, rgit_init, rgit_read_tree,
-    rgit_write_tree,
+use rgit::commands::{
+    rgit_branch, rgit_cat_file, rgit_check_ignore, rgit_checkout, rgit_clone, rgit_commit,
+    rgit_fsck, rgit_gc, rgit_hash_object, rgit_init, rgit_pack_refs, rgit_prune, rgit_read_tree,
+    rgit_reflog, rgit_repack, rgit_rev_parse, rgit_switch, rgit_symbolic_ref, rgit_tag,
+    rgit_update_ref, rgit_write_tree,
 };
-use error::RGitError;
+use rgit::RGitError;
+use std::io;
 use std::process;
 
 fn main() {
     let args = RustGitArgs::parse();
 
     let result = match &args.command {
-        Some(RustGitSubCommands::Init) => rgit_init(),
+        Some(RustGitSubCommands::Init(args)) => rgit_init(args),
+        Some(RustGitSubCommands::Clone(args)) => rgit_clone(args),
         Some(RustGitSubCommands::HashObject(args)) => rgit_hash_object(args),
         Some(RustGitSubCommands::CatFile(args)) => rgit_cat_file(args),
         Some(RustGitSubCommands::WriteTree) => rgit_write_tree(),
         Some(RustGitSubCommands::CheckIgnore(args)) => rgit_check_ignore(args),
         Some(RustGitSubCommands::ReadTree(args)) => rgit_read_tree(args),
         Some(RustGitSubCommands::Commit(args)) => rgit_commit(args),
+        Some(RustGitSubCommands::Tag(args)) => rgit_tag(args),
+        Some(RustGitSubCommands::Branch(args)) => rgit_branch(args),
+        Some(RustGitSubCommands::Switch(args)) => rgit_switch(args),
+        Some(RustGitSubCommands::Checkout(args)) => rgit_checkout(args),
+        Some(RustGitSubCommands::Reflog(args)) => rgit_reflog(args),
+        Some(RustGitSubCommands::UpdateRef(args)) => rgit_update_ref(args),
+        Some(RustGitSubCommands::SymbolicRef(args)) => rgit_symbolic_ref(args),
+        Some(RustGitSubCommands::PackRefs(args)) => rgit_pack_refs(args),
+        Some(RustGitSubCommands::RevParse(args)) => rgit_rev_parse(args),
+        Some(RustGitSubCommands::Repack(args)) => rgit_repack(args),
+        Some(RustGitSubCommands::Gc(args)) => rgit_gc(args),
+        Some(RustGitSubCommands::Fsck(args)) => rgit_fsck(args),
+        Some(RustGitSubCommands::Prune(args)) => rgit_prune(args),
         None => Err(RGitError::new(
             "fatal: no command provided".to_string(),
             128,
         )),
     };
 
-    if let Err(err) = result {
-        match err.downcast_ref::<RGitError>() {
-            Some(rgit_err) => {
-                eprintln!("{}", rgit_err.message);
-                process::exit(rgit_err.exit_code.into());
-            }
-            None => {
-                eprintln!("An unexpected error occurred: {}", err);
-                process::exit(1);
-            }
+    let err = match result {
+        Ok(code) => process::exit(code.into()),
+        // I/O failures are fatal, like in git
+        Err(err) => match err.downcast::<io::Error>() {
+            Ok(io_err) => RGitError::from(io_err).into(),
+            Err(err) => err,
+        },
+    };
+    match err.downcast_ref::<RGitError>() {
+        Some(rgit_err) => {
+            eprintln!("{}", rgit_err);
+            process::exit(rgit_err.exit_code().into());
+        }
+        None => {
+            eprintln!("An unexpected error occurred: {}", err);
+            process::exit(1);
         }
     }
-    process::exit(result.unwrap().into());
 }
@@ -1,40 +1,33 @@
 use crate::error::RGitError;
-use crate::hash::hash_object;
-use crate::objects::{RGitObject, RGitObjectHeader, RGitObjectType};
-use crate::utils::get_rgit_object_path;
+use crate::hash::{ObjectFormat, ObjectId};
+use crate::objects::{ObjectDatabase, RGitObject, RGitObjectHeader, RGitObjectType};
 use anyhow::Result;
+use std::ffi::OsString;
 use std::fs;
-use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::io::{self, Read, Write};
+use std::os::unix::ffi::OsStringExt;
+use std::os::unix::fs::{symlink, PermissionsExt};
 use std::path::{Path, PathBuf};
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+enum BlobSource {
+    /// A plain file in the working tree
+    File(PathBuf),
+    /// An object in the given object database
+    Object(ObjectDatabase),
+    /// Content kept in memory, such as the target of a symlink
+    Memory(Vec<u8>),
+}
+
+#[derive(Debug, Clone)]
 pub struct Blob {
-    path: PathBuf,
+    source: BlobSource,
     size: usize,
-    content_offset: u64,
-    hash: [u8; 20],
+    hash: ObjectId,
 }
 
 impl Blob {
-    fn new(path: &Path, size: usize, content_offset: u64, hash: Option<[u8; 20]>) -> Result<Self> {
-        let hash = if let Some(hash) = hash {
-            hash
-        } else {
-            let mut file = fs::File::open(path)?;
-            file.seek(SeekFrom::Start(content_offset))?;
-            let mut content = file.take(size as u64);
-            hash_object(&mut content)?
-        };
-
-        Ok(Self {
-            path: path.to_path_buf(),
-            size,
-            content_offset,
-            hash,
-        })
-    }
-
-    pub fn from_file(path: &Path) -> Result<Self> {
+    pub fn from_file(path: &Path, format: ObjectFormat) -> Result<Self> {
         if fs::metadata(path).is_err() {
             return Err(RGitError::new(
                 format!(
@@ -46,37 +39,56 @@ impl Blob {
         }
 
         let size = fs::metadata(path)?.len() as usize;
-        Self::new(path, size, 0, None)
+        let header = RGitObjectHeader::new(RGitObjectType::Blob, size);
+        let hash = header.hash(fs::File::open(path)?.take(size as u64), format)?;
+
+        Ok(Self {
+            source: BlobSource::File(path.to_path_buf()),
+            size,
+            hash,
+        })
     }
 
-    pub fn from_rgit_objects(rgit_dir: &Path, hash: &[u8; 20]) -> Result<Self> {
-        let object_path = get_rgit_object_path(rgit_dir, hash, true)?;
-        let mut reader = fs::File::open(&object_path)?;
+    /// Creates a blob of the target of the symlink at `path`, the way git stores symlinks.
+    pub fn from_symlink(path: &Path, format: ObjectFormat) -> Result<Self> {
+        let target = fs::read_link(path)?.into_os_string().into_vec();
+        let header = RGitObjectHeader::new(RGitObjectType::Blob, target.len());
+        let hash = header.hash(target.as_slice(), format)?;
+
+        Ok(Self {
+            size: target.len(),
+            source: BlobSource::Memory(target),
+            hash,
+        })
+    }
 
-        let header = RGitObjectHeader::deserialize(&mut reader)?;
+    pub fn from_rgit_objects(db: &ObjectDatabase, hash: &ObjectId) -> Result<Self> {
+        let header = db.read_header(hash)?;
         if header.object_type != RGitObjectType::Blob {
-            return Err(anyhow::anyhow!(format!(
-                "Invalid object type: {:?}",
-                header.object_type
-            )));
+            return Err(RGitError::wrong_object_type(
+                hash,
+                RGitObjectType::Blob,
+                header.object_type,
+            ));
         }
 
-        let content_offset = reader.stream_position()?;
-        Blob::new(
-            object_path.as_path(),
-            header.content_size,
-            content_offset,
-            Some(*hash),
-        )
+        Ok(Self {
+            source: BlobSource::Object(db.clone()),
+            size: header.content_size,
+            hash: *hash,
+        })
     }
 
     fn content(&self) -> Result<impl Read> {
-        let mut file = fs::File::open(&self.path)?;
-        file.seek(SeekFrom::Start(self.content_offset))?;
-        Ok(file.take(self.size as u64))
+        let reader: Box<dyn Read> = match &self.source {
+            BlobSource::File(path) => Box::new(fs::File::open(path)?),
+            BlobSource::Object(db) => db.read(&self.hash)?.1,
+            BlobSource::Memory(content) => Box::new(io::Cursor::new(content.clone())),
+        };
+        Ok(reader.take(self.size as u64))
     }
 
-    pub fn hash(&self) -> &[u8; 20] {
+    pub fn hash(&self) -> &ObjectId {
         &self.hash
     }
 
@@ -87,14 +99,38 @@ impl Blob {
         Ok(())
     }
 
-    pub fn write_to_rgit_objects(&self, rgit_dir: &Path) -> Result<()> {
-        let object_path = get_rgit_object_path(rgit_dir, &self.hash, false)?;
-        fs::create_dir_all(object_path.parent().unwrap())?;
-        let mut file = fs::File::create(&object_path)?;
+    /// Writes the blob as an executable file, executable by whoever may read it.
+    pub fn write_to_executable(&self, path: &Path) -> Result<()> {
+        self.write_to_file(path)?;
+        let mut permissions = fs::metadata(path)?.permissions();
+        let mode = permissions.mode();
+        permissions.set_mode(mode | (mode & 0o444) >> 2);
+        fs::set_permissions(path, permissions)?;
+        Ok(())
+    }
 
-        self.serialize(&mut file)?;
+    /// Creates a symlink to the target stored in the blob. Where symlinks are not supported the
+    /// target is written to a plain file instead, like git does with `core.symlinks` unset.
+    pub fn write_to_symlink(&self, path: &Path) -> Result<()> {
+        let mut target = Vec::new();
+        self.content()?.read_to_end(&mut target)?;
+        match symlink(OsString::from_vec(target.clone()), path) {
+            Ok(()) => Ok(()),
+            Err(err)
+                if matches!(
+                    err.kind(),
+                    io::ErrorKind::PermissionDenied | io::ErrorKind::Unsupported
+                ) =>
+            {
+                fs::write(path, target)?;
+                Ok(())
+            }
+            Err(err) => Err(err.into()),
+        }
+    }
 
-        Ok(())
+    pub fn write_to_rgit_objects(&self, db: &ObjectDatabase) -> Result<()> {
+        db.write(&self.hash, self)
     }
 }
 
@@ -125,6 +161,7 @@ impl RGitObject for Blob {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::hash::ObjectFormat;
     use crate::objects::Tree;
     use crate::utils::init_rgit_dir;
     use tempfile::tempdir;
@@ -133,7 +170,7 @@ mod tests {
     fn test_blob_from_file() {
         let dir = tempdir().unwrap();
 
-        let result = Blob::from_file(dir.path().join("test.txt").as_path());
+        let result = Blob::from_file(dir.path().join("test.txt").as_path(), ObjectFormat::Sha1);
         assert!(result.is_err());
         assert!(result
             .unwrap_err()
@@ -141,44 +178,64 @@ mod tests {
             .contains("No such file or directory"));
 
         fs::write(dir.path().join("test.txt"), "Hello, World!").unwrap();
-        let blob = Blob::from_file(dir.path().join("test.txt").as_path()).unwrap();
+        let blob =
+            Blob::from_file(dir.path().join("test.txt").as_path(), ObjectFormat::Sha1).unwrap();
         assert_eq!(blob.size, 13);
     }
 
+    #[test]
+    fn test_blob_hash() {
+        // use `echo -n "Hello, World!" | git hash-object --stdin` to get the ground truth
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("test.txt"), "Hello, World!").unwrap();
+        let blob =
+            Blob::from_file(dir.path().join("test.txt").as_path(), ObjectFormat::Sha1).unwrap();
+        assert_eq!(
+            hex::encode(blob.hash()),
+            "b45ef6fec89518d314f546fd6c3025367b721684"
+        );
+    }
+
     #[test]
     fn test_blob_from_rgit_objects() {
         let dir = tempdir().unwrap();
-        init_rgit_dir(dir.path()).unwrap();
+        let db = ObjectDatabase::open(&init_rgit_dir(dir.path()).unwrap()).unwrap();
 
         fs::write(dir.path().join("test.txt"), "Hello, World!").unwrap();
-        let blob = Blob::from_file(dir.path().join("test.txt").as_path()).unwrap();
-        blob.write_to_rgit_objects(dir.path()).unwrap();
+        let blob =
+            Blob::from_file(dir.path().join("test.txt").as_path(), ObjectFormat::Sha1).unwrap();
+        blob.write_to_rgit_objects(&db).unwrap();
 
-        let blob = Blob::from_rgit_objects(dir.path(), &blob.hash).unwrap();
+        let blob = Blob::from_rgit_objects(&db, &blob.hash).unwrap();
         assert_eq!(blob.size, 13);
 
-        let tree = Tree::from_directory(dir.path()).unwrap();
-        tree.write_to_rgit_objects(dir.path()).unwrap();
+        let tree = Tree::from_directory(dir.path(), ObjectFormat::Sha1).unwrap();
+        tree.write_to_rgit_objects(&db).unwrap();
 
-        let result = Blob::from_rgit_objects(dir.path(), tree.hash());
-        assert!(result.is_err());
-        assert!(result
-            .unwrap_err()
-            .to_string()
-            .contains("Invalid object type"));
+        let err = Blob::from_rgit_objects(&db, tree.hash()).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(RGitError::WrongObjectType { id, expected: RGitObjectType::Blob, actual: RGitObjectType::Tree })
+                if id == tree.hash()
+        ));
+        assert_eq!(
+            err.to_string(),
+            format!("fatal: object {} is a tree, not a blob", tree.hash())
+        );
     }
 
     #[test]
     fn test_blob_write_to_file() {
         let dir = tempdir().unwrap();
-        init_rgit_dir(dir.path()).unwrap();
+        let db = ObjectDatabase::open(&init_rgit_dir(dir.path()).unwrap()).unwrap();
 
         fs::write(dir.path().join("test.txt"), "Hello, World!").unwrap();
-        let blob = Blob::from_file(dir.path().join("test.txt").as_path()).unwrap();
-        blob.write_to_rgit_objects(dir.path()).unwrap();
+        let blob =
+            Blob::from_file(dir.path().join("test.txt").as_path(), ObjectFormat::Sha1).unwrap();
+        blob.write_to_rgit_objects(&db).unwrap();
 
         fs::remove_file(dir.path().join("test.txt")).unwrap();
-        let blob = Blob::from_rgit_objects(dir.path(), &blob.hash).unwrap();
+        let blob = Blob::from_rgit_objects(&db, &blob.hash).unwrap();
         blob.write_to_file(dir.path().join("test.txt").as_path())
             .unwrap();
         assert_eq!(
@@ -190,11 +247,12 @@ mod tests {
     #[test]
     fn test_blob_print() {
         let dir = tempdir().unwrap();
-        init_rgit_dir(dir.path()).unwrap();
+        let db = ObjectDatabase::open(&init_rgit_dir(dir.path()).unwrap()).unwrap();
 
         fs::write(dir.path().join("test.txt"), "Hello, World!").unwrap();
-        let blob = Blob::from_file(dir.path().join("test.txt").as_path()).unwrap();
-        blob.write_to_rgit_objects(dir.path()).unwrap();
+        let blob =
+            Blob::from_file(dir.path().join("test.txt").as_path(), ObjectFormat::Sha1).unwrap();
+        blob.write_to_rgit_objects(&db).unwrap();
 
         let mut buffer = Vec::new();
         blob.print(&mut buffer).unwrap();
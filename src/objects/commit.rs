@@ -1,21 +1,23 @@
-use crate::hash::hash_object;
-use crate::objects::{RGitObject, RGitObjectHeader, RGitObjectType};
-use crate::utils::get_rgit_object_path;
+use crate::error::RGitError;
+use crate::hash::{ObjectFormat, ObjectId};
+use crate::objects::{ObjectDatabase, RGitObject, RGitObjectHeader, RGitObjectType, RawObject};
 use anyhow::Result;
-use chrono::{DateTime, FixedOffset, Local, Offset, TimeZone};
-use std::fs;
-use std::io::{Read, Write};
-use std::path::Path;
+use chrono::{DateTime, FixedOffset, Local, Offset, TimeZone, Timelike};
+use std::io::Write;
+use std::str;
 
 #[derive(Debug)]
 pub struct Commit {
-    tree: [u8; 20],
-    parents: Vec<[u8; 20]>,
+    tree: ObjectId,
+    parents: Vec<ObjectId>,
     time: DateTime<FixedOffset>,
     pub commit_message: String,
 }
 
-fn parse_timezone_offset(offset_str: &str) -> Result<FixedOffset> {
+pub(super) fn parse_timezone_offset(offset_str: &str) -> Result<FixedOffset> {
+    if offset_str.len() != 5 || !offset_str.is_ascii() {
+        return Err(anyhow::anyhow!("Invalid timezone offset"));
+    }
     let offset_sign = offset_str
         .chars()
         .next()
@@ -38,7 +40,7 @@ fn parse_timezone_offset(offset_str: &str) -> Result<FixedOffset> {
     FixedOffset::east_opt(offset_secs).ok_or_else(|| anyhow::anyhow!("Invalid timezone offset"))
 }
 
-fn serialize_timezone_offset(offset: &FixedOffset) -> String {
+pub(super) fn serialize_timezone_offset(offset: &FixedOffset) -> String {
     let offset_str = offset.to_string();
     let offset_sign = offset_str.chars().next().unwrap();
     let offset_hours = offset_str[1..3].parse::<i32>().unwrap();
@@ -47,43 +49,65 @@ fn serialize_timezone_offset(offset: &FixedOffset) -> String {
     format!("{}{:02}{:02}", offset_sign, offset_hours, offset_minutes)
 }
 
+/// The current local time, with the offset of the local timezone and to the second like objects
+/// record it.
+pub(super) fn current_time() -> Result<DateTime<FixedOffset>> {
+    let now = Local::now().with_nanosecond(0).unwrap();
+    let offset = now.offset().fix();
+    offset
+        .from_local_datetime(&now.naive_local())
+        .single()
+        .ok_or_else(|| anyhow::anyhow!("Invalid timestamp"))
+}
+
 impl Commit {
-    pub fn new(tree: [u8; 20], parents: Vec<[u8; 20]>, commit_message: String) -> Result<Self> {
-        let now = Local::now();
-        let offset = now.offset().fix();
-        let time = offset.from_local_datetime(&now.naive_local()).single();
-        if time.is_none() {
-            return Err(anyhow::anyhow!("Invalid timestamp"));
+    pub fn new(tree: ObjectId, parents: Vec<ObjectId>, commit_message: String) -> Result<Self> {
+        if parents
+            .iter()
+            .any(|parent| parent.format() != tree.format())
+        {
+            return Err(anyhow::anyhow!(
+                "Commit parents must use the {} object format of the tree",
+                tree.format()
+            ));
         }
-        let time = time.unwrap();
-
         Ok(Self {
             tree,
             parents,
-            time,
+            time: current_time()?,
             commit_message,
         })
     }
 
-    pub fn from_rgit_objects(rgit_dir: &Path, hash: &[u8; 20]) -> Result<Self> {
-        let object_path = get_rgit_object_path(rgit_dir, hash, true)?;
-        let mut reader = fs::File::open(object_path)?;
-
-        let header = RGitObjectHeader::deserialize(&mut reader)?;
-        if header.object_type != RGitObjectType::Commit {
-            return Err(anyhow::anyhow!(
-                "Invalid object type: {:?}",
-                header.object_type
+    pub fn from_rgit_objects(db: &ObjectDatabase, hash: &ObjectId) -> Result<Self> {
+        let raw = RawObject::from_rgit_objects(db, hash)?;
+        if raw.object_type != RGitObjectType::Commit {
+            return Err(RGitError::wrong_object_type(
+                hash,
+                RGitObjectType::Commit,
+                raw.object_type,
             ));
         }
+        Self::deserialize(&raw.content, hash.format())
+            .map_err(|err| RGitError::corrupt_object(hash, err))
+    }
 
-        let mut content = String::new();
-        reader.read_to_string(&mut content)?;
+    /// Parses the content of a commit object that refers to objects of the given format.
+    pub fn deserialize(content: &[u8], format: ObjectFormat) -> Result<Self> {
+        let parse_id = |hex: &str| {
+            ObjectId::from_hex(hex)
+                .ok()
+                .filter(|id| id.format() == format)
+        };
 
+        let content = str::from_utf8(content)?;
         let mut lines = content.lines();
 
-        let tree_line = lines.next().unwrap();
-        let tree = hex::decode(&tree_line[5..])?.as_slice().try_into()?;
+        let tree_line = lines.next().unwrap_or_default();
+        let tree = tree_line
+            .strip_prefix("tree ")
+            .and_then(parse_id)
+            .ok_or_else(|| anyhow::anyhow!("Invalid tree line: {:?}", tree_line))?;
 
         let mut parents = Vec::new();
         let mut line = lines.next();
@@ -92,13 +116,14 @@ impl Commit {
                 break;
             }
 
-            let parent: [u8; 20] = hex::decode(&parent_line[7..])?.as_slice().try_into()?;
+            let parent = parse_id(&parent_line[7..])
+                .ok_or_else(|| anyhow::anyhow!("Invalid parent line: {:?}", parent_line))?;
             parents.push(parent);
             line = lines.next();
         }
 
         // time_line should be `line`, cannot be `lines.next()`
-        let time_line = line.unwrap();
+        let time_line = line.unwrap_or_default();
         let time_parts: Vec<&str> = time_line.split_whitespace().collect();
         if time_parts.len() != 3 || time_parts[0] != "time" {
             return Err(anyhow::anyhow!("Invalid time line: {:?}", time_line));
@@ -121,18 +146,28 @@ impl Commit {
         })
     }
 
-    pub fn hash(&self) -> Result<[u8; 20]> {
-        hash_object(self.content().as_bytes())
+    pub fn tree(&self) -> &ObjectId {
+        &self.tree
     }
 
-    pub fn write_to_rgit_objects(&self, rgit_dir: &Path) -> Result<[u8; 20]> {
-        let hash = self.hash()?;
-        let object_path = get_rgit_object_path(rgit_dir, &hash, false)?;
-        fs::create_dir_all(object_path.parent().unwrap())?;
-        let mut writer = fs::File::create(object_path)?;
+    pub fn parents(&self) -> &[ObjectId] {
+        &self.parents
+    }
 
-        self.serialize(&mut writer)?;
+    /// When the commit was made, in the timezone of its author.
+    pub fn time(&self) -> &DateTime<FixedOffset> {
+        &self.time
+    }
 
+    pub fn hash(&self) -> Result<ObjectId> {
+        let content = self.content();
+        RGitObjectHeader::new(self.object_type(), content.len())
+            .hash(content.as_bytes(), self.tree.format())
+    }
+
+    pub fn write_to_rgit_objects(&self, db: &ObjectDatabase) -> Result<ObjectId> {
+        let hash = self.hash()?;
+        db.write(&hash, self)?;
         Ok(hash)
     }
 
@@ -143,9 +178,9 @@ impl Commit {
             content.push_str(format!("parent {}\n", hex::encode(parent)).as_str());
         }
         let offset = self.time.offset();
-        let offset_string = serialize_timezone_offset(&offset);
+        let offset_string = serialize_timezone_offset(offset);
         content.push_str(format!("time {} {}\n", self.time.timestamp(), offset_string).as_str());
-        content.push_str("\n");
+        content.push('\n');
         content.push_str(self.commit_message.as_str());
 
         content
@@ -178,8 +213,10 @@ impl RGitObject for Commit {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::hash::ObjectFormat;
     use crate::objects::Tree;
     use crate::utils::init_rgit_dir;
+    use std::fs;
     use tempfile::tempdir;
 
     #[test]
@@ -204,7 +241,7 @@ mod tests {
     fn test_from_rgit_objects() {
         let dir = tempdir().unwrap();
         let path = dir.path();
-        let rgit_dir = init_rgit_dir(dir.path()).unwrap();
+        let db = ObjectDatabase::open(&init_rgit_dir(dir.path()).unwrap()).unwrap();
 
         let file_path = path.join("file");
         fs::write(&file_path, "file content").unwrap();
@@ -214,16 +251,15 @@ mod tests {
         let subfile_path = subdir_path.join("subfile");
         fs::write(&subfile_path, "subfile content").unwrap();
 
-        let tree = Tree::from_directory(path).unwrap();
-        tree.write_to_rgit_objects(rgit_dir.as_path()).unwrap();
+        let tree = Tree::from_directory(path, ObjectFormat::Sha1).unwrap();
+        tree.write_to_rgit_objects(&db).unwrap();
 
-        let mut tree_hash = [0; 20];
-        tree_hash.copy_from_slice(tree.hash());
+        let tree_hash = *tree.hash();
 
         let commit = Commit::new(tree_hash, Vec::new(), "Initial commit".to_string()).unwrap();
-        let commit_hash = commit.write_to_rgit_objects(rgit_dir.as_path()).unwrap();
+        let commit_hash = commit.write_to_rgit_objects(&db).unwrap();
 
-        let commit = Commit::from_rgit_objects(rgit_dir.as_path(), &commit_hash).unwrap();
+        let commit = Commit::from_rgit_objects(&db, &commit_hash).unwrap();
         assert_eq!(commit.tree, tree_hash);
         assert_eq!(commit.parents.len(), 0);
         assert_eq!(commit.commit_message, "Initial commit");
@@ -0,0 +1,234 @@
+use anyhow::Result;
+use std::collections::HashMap;
+
+/// Length of the blocks of the base that are indexed when searching for copy candidates.
+const BLOCK_SIZE: usize = 16;
+/// Largest copy a single instruction may describe, larger matches are split.
+const MAX_COPY_SIZE: usize = 0x10000;
+/// Largest number of literal bytes a single insert instruction may carry.
+const MAX_INSERT_SIZE: usize = 0x7f;
+
+fn write_delta_size(delta: &mut Vec<u8>, mut size: usize) {
+    loop {
+        let byte = (size & 0x7f) as u8;
+        size >>= 7;
+        if size == 0 {
+            delta.push(byte);
+            return;
+        }
+        delta.push(byte | 0x80);
+    }
+}
+
+fn write_copy(delta: &mut Vec<u8>, offset: usize, size: usize) {
+    let opcode_pos = delta.len();
+    let mut opcode = 0x80u8;
+    delta.push(opcode);
+    for i in 0..4 {
+        let byte = (offset >> (i * 8)) as u8;
+        if byte != 0 {
+            opcode |= 1 << i;
+            delta.push(byte);
+        }
+    }
+    for i in 0..3 {
+        let byte = (size >> (i * 8)) as u8;
+        if byte != 0 {
+            opcode |= 1 << (4 + i);
+            delta.push(byte);
+        }
+    }
+    delta[opcode_pos] = opcode;
+}
+
+fn flush_insert(delta: &mut Vec<u8>, insert: &mut Vec<u8>) {
+    for chunk in insert.chunks(MAX_INSERT_SIZE) {
+        delta.push(chunk.len() as u8);
+        delta.extend_from_slice(chunk);
+    }
+    insert.clear();
+}
+
+/// Encodes `target` as a git delta instruction stream against `base`.
+pub fn create_delta(base: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut index: HashMap<&[u8], usize> = HashMap::new();
+    for offset in (0..base.len().saturating_sub(BLOCK_SIZE - 1)).step_by(BLOCK_SIZE) {
+        index
+            .entry(&base[offset..offset + BLOCK_SIZE])
+            .or_insert(offset);
+    }
+
+    let mut delta = Vec::new();
+    write_delta_size(&mut delta, base.len());
+    write_delta_size(&mut delta, target.len());
+
+    let mut insert = Vec::new();
+    let mut cursor = 0;
+    while cursor < target.len() {
+        let candidate = target
+            .get(cursor..cursor + BLOCK_SIZE)
+            .and_then(|block| index.get(block));
+        match candidate {
+            Some(&offset) => {
+                let mut size = BLOCK_SIZE;
+                while offset + size < base.len()
+                    && cursor + size < target.len()
+                    && base[offset + size] == target[cursor + size]
+                {
+                    size += 1;
+                }
+
+                flush_insert(&mut delta, &mut insert);
+                let mut copied = 0;
+                while copied < size {
+                    let chunk = (size - copied).min(MAX_COPY_SIZE);
+                    write_copy(&mut delta, offset + copied, chunk);
+                    copied += chunk;
+                }
+                cursor += size;
+            }
+            None => {
+                insert.push(target[cursor]);
+                cursor += 1;
+            }
+        }
+    }
+    flush_insert(&mut delta, &mut insert);
+
+    delta
+}
+
+fn read_delta_size(delta: &[u8], cursor: &mut usize) -> Result<usize> {
+    let mut size = 0;
+    let mut shift = 0;
+    loop {
+        let byte = *delta
+            .get(*cursor)
+            .ok_or_else(|| anyhow::anyhow!("Truncated delta"))?;
+        *cursor += 1;
+        size |= ((byte & 0x7f) as usize) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 {
+            return Ok(size);
+        }
+    }
+}
+
+/// Returns the size of the object the delta rebuilds, read from the start of `delta`.
+pub fn delta_result_size(delta: &[u8]) -> Result<usize> {
+    let mut cursor = 0;
+    read_delta_size(delta, &mut cursor)?;
+    read_delta_size(delta, &mut cursor)
+}
+
+/// Reconstructs an object from its delta base and a git delta instruction stream.
+pub fn apply_delta(base: &[u8], delta: &[u8]) -> Result<Vec<u8>> {
+    let mut cursor = 0;
+    let base_size = read_delta_size(delta, &mut cursor)?;
+    if base_size != base.len() {
+        return Err(anyhow::anyhow!(
+            "Delta base size mismatch: expected {}, got {}",
+            base_size,
+            base.len()
+        ));
+    }
+    let result_size = read_delta_size(delta, &mut cursor)?;
+
+    // the declared size is not trusted for allocating, a delta rarely grows much past its base
+    let mut result = Vec::with_capacity(result_size.min(base.len() + delta.len()));
+    while cursor < delta.len() {
+        let opcode = delta[cursor];
+        cursor += 1;
+
+        if opcode & 0x80 != 0 {
+            // copy from base: bits 0-3 select offset bytes, bits 4-6 select size bytes
+            let mut values = [0usize; 2];
+            let mut bit = 0;
+            for (value, bytes) in values.iter_mut().zip([4, 3]) {
+                for i in 0..bytes {
+                    if opcode & (1 << bit) != 0 {
+                        let byte = *delta
+                            .get(cursor)
+                            .ok_or_else(|| anyhow::anyhow!("Truncated delta"))?;
+                        cursor += 1;
+                        *value |= (byte as usize) << (i * 8);
+                    }
+                    bit += 1;
+                }
+            }
+            let [offset, mut size] = values;
+            if size == 0 {
+                size = 0x10000;
+            }
+            let chunk = base
+                .get(offset..offset + size)
+                .ok_or_else(|| anyhow::anyhow!("Delta copy out of bounds"))?;
+            result.extend_from_slice(chunk);
+        } else if opcode != 0 {
+            let size = opcode as usize;
+            let chunk = delta
+                .get(cursor..cursor + size)
+                .ok_or_else(|| anyhow::anyhow!("Truncated delta"))?;
+            cursor += size;
+            result.extend_from_slice(chunk);
+        } else {
+            return Err(anyhow::anyhow!("Invalid delta opcode: 0"));
+        }
+    }
+
+    if result.len() != result_size {
+        return Err(anyhow::anyhow!(
+            "Delta result size mismatch: expected {}, got {}",
+            result_size,
+            result.len()
+        ));
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_delta() {
+        let base = b"Hello, World!";
+        // base size 13, result size 18, copy "Hello, " then insert "Rust" then copy "World!"
+        let delta = [
+            13, 18, 0x90, 7, 4, b'R', b'u', b's', b't', 0x91, 7, 6, 0x01, b'\n',
+        ];
+        let result = apply_delta(base, &delta).unwrap();
+        assert_eq!(result, b"Hello, RustWorld!\n");
+
+        let result = apply_delta(b"Hello", &delta);
+        assert!(result.is_err());
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("Delta base size mismatch"));
+    }
+
+    #[test]
+    fn test_create_delta() {
+        let base = (0..1000)
+            .map(|i| format!("line {}\n", i))
+            .collect::<String>()
+            .into_bytes();
+        let target = String::from_utf8(base.clone())
+            .unwrap()
+            .replace("line 500\n", "a changed line\n")
+            .into_bytes();
+
+        let delta = create_delta(&base, &target);
+        assert!(delta.len() < target.len() / 10);
+        assert_eq!(apply_delta(&base, &delta).unwrap(), target);
+
+        // nothing in common degrades to inserts only
+        let delta = create_delta(b"", &target);
+        assert_eq!(apply_delta(b"", &delta).unwrap(), target);
+
+        let target = vec![b'x'; 3 * MAX_COPY_SIZE];
+        let delta = create_delta(&target, &target);
+        assert_eq!(apply_delta(&target, &delta).unwrap(), target);
+    }
+}
@@ -0,0 +1,323 @@
+use crate::error::RGitError;
+use crate::hash::{ObjectId, ObjectIdPrefix};
+use crate::objects::{ObjectStore, RGitObject, RGitObjectHeader};
+use anyhow::Result;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::SystemTime;
+
+static TEMP_FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+fn is_zlib_stream(prefix: &[u8]) -> bool {
+    // CMF must declare deflate (low nibble 8) and CMF/FLG must be a multiple of 31
+    prefix.len() >= 2
+        && prefix[0] & 0x0f == 8
+        && ((prefix[0] as u16) << 8 | prefix[1] as u16).is_multiple_of(31)
+}
+
+/// Opens a loose object and returns its header together with a reader positioned at the
+/// start of its content.
+///
+/// Objects are stored zlib-compressed like git does. Objects written by older versions of
+/// rgit were stored uncompressed, those are still readable.
+fn read_loose_object(path: &Path) -> Result<(RGitObjectHeader, Box<dyn Read>)> {
+    let mut reader = BufReader::new(fs::File::open(path).map_err(RGitError::from)?);
+    let mut reader: Box<dyn Read> = if is_zlib_stream(reader.fill_buf().map_err(RGitError::from)?) {
+        Box::new(ZlibDecoder::new(reader))
+    } else {
+        Box::new(reader)
+    };
+
+    let header = RGitObjectHeader::deserialize(&mut reader)?;
+    Ok((header, reader))
+}
+
+/// Objects stored one file each under `objects/xx/xxxxxx...`.
+#[derive(Debug)]
+pub struct LooseStore {
+    objects_dir: PathBuf,
+}
+
+impl LooseStore {
+    pub fn new(objects_dir: &Path) -> Self {
+        Self {
+            objects_dir: objects_dir.to_path_buf(),
+        }
+    }
+
+    pub fn object_path(&self, hash: &ObjectId) -> PathBuf {
+        let hash = hex::encode(hash);
+        self.objects_dir.join(&hash[..2]).join(&hash[2..])
+    }
+
+    /// Returns the ID and path of every loose object, ordered by ID.
+    pub fn objects(&self) -> Result<Vec<(ObjectId, PathBuf)>> {
+        if !self.objects_dir.is_dir() {
+            return Ok(Vec::new());
+        }
+
+        let mut objects = Vec::new();
+        for dir in fs::read_dir(&self.objects_dir)? {
+            let dir = dir?;
+            let prefix = dir.file_name().to_string_lossy().to_string();
+            if prefix.len() != 2 || !dir.file_type()?.is_dir() {
+                continue;
+            }
+            for file in fs::read_dir(dir.path())? {
+                let file = file?;
+                let name = format!("{}{}", prefix, file.file_name().to_string_lossy());
+                if let Ok(hash) = ObjectId::from_hex(&name) {
+                    objects.push((hash, file.path()));
+                }
+            }
+        }
+        objects.sort();
+        Ok(objects)
+    }
+
+    /// Deletes a loose object, along with its fan-out directory once that is empty.
+    pub fn remove(&self, hash: &ObjectId) -> Result<()> {
+        let object_path = self.object_path(hash);
+        fs::remove_file(&object_path)?;
+        let parent = object_path.parent().unwrap();
+        if fs::read_dir(parent)?.next().is_none() {
+            fs::remove_dir(parent)?;
+        }
+        Ok(())
+    }
+}
+
+impl ObjectStore for LooseStore {
+    fn read_header(&self, hash: &ObjectId) -> Result<Option<RGitObjectHeader>> {
+        Ok(self.read(hash)?.map(|(header, _)| header))
+    }
+
+    fn read(&self, hash: &ObjectId) -> Result<Option<(RGitObjectHeader, Box<dyn Read>)>> {
+        let object_path = self.object_path(hash);
+        if !object_path.is_file() {
+            return Ok(None);
+        }
+        // failing to open the file is an I/O error, anything else means the object is corrupt
+        let object = read_loose_object(&object_path).map_err(|err| match err.downcast() {
+            Ok(err) => RGitError::into(err),
+            Err(err) => RGitError::corrupt_object(hash, err),
+        })?;
+        Ok(Some(object))
+    }
+
+    /// Writes the object to a temporary file and renames it into place once complete, so that
+    /// a valid object name never refers to a truncated file. Objects that already exist are
+    /// freshened instead.
+    fn write(&self, hash: &ObjectId, object: &dyn RGitObject) -> Result<()> {
+        if self.freshen(hash)? {
+            return Ok(());
+        }
+        let object_path = self.object_path(hash);
+        fs::create_dir_all(object_path.parent().unwrap())?;
+
+        // unique per process and per write, concurrent writers never share a temporary file
+        let tmp_path = self.objects_dir.join(format!(
+            "tmp_obj_{}_{}",
+            process::id(),
+            TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let result = (|| -> Result<()> {
+            let file = io::BufWriter::new(fs::File::create_new(&tmp_path)?);
+            let mut encoder = ZlibEncoder::new(file, Compression::default());
+            object.serialize(&mut encoder)?;
+            let file = encoder.finish()?.into_inner()?;
+            file.sync_all()?;
+            file.set_permissions(fs::Permissions::from_mode(0o444))?;
+            // another writer may have stored the same object meanwhile, its content is identical
+            fs::rename(&tmp_path, &object_path)?;
+            Ok(())
+        })();
+        if result.is_err() {
+            let _ = fs::remove_file(&tmp_path);
+        }
+        result
+    }
+
+    fn contains(&self, hash: &ObjectId) -> Result<bool> {
+        Ok(self.object_path(hash).is_file())
+    }
+
+    fn freshen(&self, hash: &ObjectId) -> Result<bool> {
+        let object_path = self.object_path(hash);
+        if !object_path.is_file() {
+            return Ok(false);
+        }
+        // like git, an object whose time cannot be updated, in a read-only alternate say, is
+        // written again instead
+        Ok(fs::File::open(&object_path)
+            .and_then(|file| file.set_modified(SystemTime::now()))
+            .is_ok())
+    }
+
+    /// Only lists the fan-out directory of the first two digits, unless the prefix is shorter.
+    fn find_prefix(&self, prefix: &ObjectIdPrefix) -> Result<Vec<ObjectId>> {
+        if prefix.len() < 2 {
+            return Ok(self
+                .objects()?
+                .into_iter()
+                .map(|(hash, _)| hash)
+                .filter(|hash| prefix.matches(hash))
+                .collect());
+        }
+
+        let dir_name = hex::encode(&prefix.first().as_bytes()[..1]);
+        let dir = self.objects_dir.join(&dir_name);
+        if !dir.is_dir() {
+            return Ok(Vec::new());
+        }
+        let mut hashes = Vec::new();
+        for file in fs::read_dir(dir)? {
+            let name = format!("{}{}", dir_name, file?.file_name().to_string_lossy());
+            match ObjectId::from_hex(&name) {
+                Ok(hash) if prefix.matches(&hash) => hashes.push(hash),
+                _ => {}
+            }
+        }
+        hashes.sort();
+        Ok(hashes)
+    }
+
+    fn iter(&self) -> Result<Box<dyn Iterator<Item = ObjectId> + '_>> {
+        Ok(Box::new(self.objects()?.into_iter().map(|(hash, _)| hash)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::ObjectFormat;
+    use crate::objects::{Blob, RGitObjectType};
+    use rand::Rng;
+    use std::io::Write;
+    use std::os::unix::fs::MetadataExt;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_object_path() {
+        let dir = tempdir().unwrap();
+        let store = LooseStore::new(&dir.path().join("objects"));
+
+        let hash = ObjectId::from_bytes(&rand::thread_rng().gen::<[u8; 20]>()).unwrap();
+        let hash_str = hex::encode(hash);
+        assert_eq!(
+            store.object_path(&hash),
+            dir.path()
+                .join("objects")
+                .join(&hash_str[..2])
+                .join(&hash_str[2..])
+        );
+        assert!(!store.contains(&hash).unwrap());
+        assert!(store.read(&hash).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_write_loose_object() {
+        let dir = tempdir().unwrap();
+        let store = LooseStore::new(&dir.path().join("objects"));
+
+        fs::write(dir.path().join("test.txt"), "Hello, World!").unwrap();
+        let blob =
+            Blob::from_file(dir.path().join("test.txt").as_path(), ObjectFormat::Sha1).unwrap();
+        store.write(blob.hash(), &blob).unwrap();
+        assert!(store.contains(blob.hash()).unwrap());
+
+        // the stored file must be exactly what `git hash-object -w` produces once inflated
+        let object_path = store.object_path(blob.hash());
+        let mut content = Vec::new();
+        ZlibDecoder::new(fs::File::open(&object_path).unwrap())
+            .read_to_end(&mut content)
+            .unwrap();
+        assert_eq!(content, b"blob 13\0Hello, World!");
+
+        let (header, mut reader) = store.read(blob.hash()).unwrap().unwrap();
+        assert_eq!(header.object_type, RGitObjectType::Blob);
+        assert_eq!(header.content_size, 13);
+        let mut content = String::new();
+        reader.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "Hello, World!");
+
+        fs::write(dir.path().join("objects").join("not-an-object"), "").unwrap();
+        assert_eq!(
+            store.iter().unwrap().collect::<Vec<_>>(),
+            vec![*blob.hash()]
+        );
+    }
+
+    #[test]
+    fn test_write_is_atomic_and_deduplicated() {
+        let dir = tempdir().unwrap();
+        let objects_dir = dir.path().join("objects");
+        let store = LooseStore::new(&objects_dir);
+
+        fs::write(dir.path().join("test.txt"), "Hello, World!").unwrap();
+        let blob =
+            Blob::from_file(dir.path().join("test.txt").as_path(), ObjectFormat::Sha1).unwrap();
+        store.write(blob.hash(), &blob).unwrap();
+
+        // finished objects are read-only and no temporary file is left behind
+        let object_path = store.object_path(blob.hash());
+        let mode = fs::metadata(&object_path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o444);
+        let names = fs::read_dir(&objects_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec![hex::encode(blob.hash())[..2].to_string()]);
+
+        // an existing object is not rewritten, only freshened
+        let metadata = fs::metadata(&object_path).unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        store.write(blob.hash(), &blob).unwrap();
+        let rewritten = fs::metadata(&object_path).unwrap();
+        assert_eq!(rewritten.ino(), metadata.ino());
+        assert!(rewritten.modified().unwrap() > metadata.modified().unwrap());
+    }
+
+    #[test]
+    fn test_concurrent_writes() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join("test.txt"), "Hello, World!").unwrap();
+        let blob =
+            Blob::from_file(dir.path().join("test.txt").as_path(), ObjectFormat::Sha1).unwrap();
+        let store = LooseStore::new(&dir.path().join("objects"));
+
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| store.write(blob.hash(), &blob).unwrap());
+            }
+        });
+
+        let (_, mut reader) = store.read(blob.hash()).unwrap().unwrap();
+        let mut content = String::new();
+        reader.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "Hello, World!");
+        assert_eq!(store.iter().unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_read_legacy_loose_object() {
+        let dir = tempdir().unwrap();
+        let object_path = dir.path().join("object");
+        let mut file = fs::File::create(&object_path).unwrap();
+        file.write_all(b"blob 13\0Hello, World!").unwrap();
+
+        let (header, mut reader) = read_loose_object(&object_path).unwrap();
+        assert_eq!(header.object_type, RGitObjectType::Blob);
+        assert_eq!(header.content_size, 13);
+        let mut content = String::new();
+        reader.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "Hello, World!");
+    }
+}
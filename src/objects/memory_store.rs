@@ -0,0 +1,84 @@
+use crate::hash::{ObjectId, ObjectIdPrefix};
+use crate::objects::{ObjectStore, RGitObject, RGitObjectHeader, RawObject};
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::io::{self, Read};
+use std::sync::Mutex;
+
+/// Objects kept in memory, nothing is written to disk.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    objects: Mutex<BTreeMap<ObjectId, RawObject>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl ObjectStore for MemoryStore {
+    fn read_header(&self, hash: &ObjectId) -> Result<Option<RGitObjectHeader>> {
+        let objects = self.objects.lock().unwrap();
+        Ok(objects
+            .get(hash)
+            .map(|object| RGitObjectHeader::new(object.object_type, object.content.len())))
+    }
+
+    fn read(&self, hash: &ObjectId) -> Result<Option<(RGitObjectHeader, Box<dyn Read>)>> {
+        let objects = self.objects.lock().unwrap();
+        Ok(objects
+            .get(hash)
+            .map(|object| -> (RGitObjectHeader, Box<dyn Read>) {
+                (
+                    RGitObjectHeader::new(object.object_type, object.content.len()),
+                    Box::new(io::Cursor::new(object.content.clone())),
+                )
+            }))
+    }
+
+    fn write(&self, hash: &ObjectId, object: &dyn RGitObject) -> Result<()> {
+        let mut serialized = Vec::new();
+        object.serialize(&mut serialized)?;
+        let mut reader = serialized.as_slice();
+        let header = RGitObjectHeader::deserialize(&mut reader)?;
+
+        self.objects.lock().unwrap().insert(
+            *hash,
+            RawObject {
+                object_type: header.object_type,
+                content: reader.to_vec(),
+            },
+        );
+        Ok(())
+    }
+
+    fn contains(&self, hash: &ObjectId) -> Result<bool> {
+        Ok(self.objects.lock().unwrap().contains_key(hash))
+    }
+
+    /// Objects in memory are never pruned, there is nothing to update.
+    fn freshen(&self, hash: &ObjectId) -> Result<bool> {
+        self.contains(hash)
+    }
+
+    fn find_prefix(&self, prefix: &ObjectIdPrefix) -> Result<Vec<ObjectId>> {
+        let objects = self.objects.lock().unwrap();
+        Ok(objects
+            .range(prefix.first()..)
+            .map(|(hash, _)| *hash)
+            .take_while(|hash| prefix.matches(hash))
+            .collect())
+    }
+
+    fn iter(&self) -> Result<Box<dyn Iterator<Item = ObjectId> + '_>> {
+        let hashes = self
+            .objects
+            .lock()
+            .unwrap()
+            .keys()
+            .copied()
+            .collect::<Vec<_>>();
+        Ok(Box::new(hashes.into_iter()))
+    }
+}
@@ -1,9 +1,24 @@
 mod blob;
 mod commit;
+mod delta;
+mod loose_store;
+mod memory_store;
+mod pack;
+mod pack_store;
 mod rgit_object;
+mod store;
+mod tag;
 mod tree;
+mod walk;
 
 pub use blob::Blob;
 pub use commit::Commit;
-pub use rgit_object::{from_rgit_objects, RGitObject, RGitObjectHeader, RGitObjectType};
-pub use tree::Tree;
+pub use loose_store::LooseStore;
+pub use memory_store::MemoryStore;
+pub use pack::{write_pack, PackObject};
+pub use pack_store::PackStore;
+pub use rgit_object::{from_rgit_objects, RGitObject, RGitObjectHeader, RGitObjectType, RawObject};
+pub use store::{read_alternates, ObjectDatabase, ObjectStore};
+pub use tag::{Signature, Tag};
+pub use tree::{parse_tree_entries, read_tree_entries, EntryType, Tree};
+pub use walk::{find_reachable_objects, is_ancestor, merge_bases};
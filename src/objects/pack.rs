@@ -0,0 +1,830 @@
+use crate::hash::{ObjectFormat, ObjectId, ObjectIdPrefix};
+use crate::objects::delta::{apply_delta, create_delta, delta_result_size};
+use crate::objects::RGitObjectType;
+use anyhow::Result;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::fs;
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+const IDX_MAGIC: [u8; 4] = [0xff, b't', b'O', b'c'];
+const PACK_MAGIC: [u8; 4] = *b"PACK";
+
+const OBJ_COMMIT: u8 = 1;
+const OBJ_TREE: u8 = 2;
+const OBJ_BLOB: u8 = 3;
+const OBJ_TAG: u8 = 4;
+const OBJ_OFS_DELTA: u8 = 6;
+const OBJ_REF_DELTA: u8 = 7;
+
+/// The most bytes the two sizes at the start of a delta take.
+const MAX_DELTA_HEADER: usize = 20;
+/// The longest chain of deltas that is followed, git never writes chains longer than 4095.
+const MAX_DELTA_CHAIN: usize = 10_000;
+
+fn read_u32(bytes: &[u8], pos: usize) -> Result<u32> {
+    let slice = bytes
+        .get(pos..pos + 4)
+        .ok_or_else(|| anyhow::anyhow!("Truncated pack index"))?;
+    Ok(u32::from_be_bytes(slice.try_into()?))
+}
+
+/// A version 2 pack index (`objects/pack/pack-*.idx`).
+#[derive(Debug)]
+pub struct PackIndex {
+    fanout: [u32; 256],
+    hashes: Vec<ObjectId>,
+    offsets: Vec<u64>,
+}
+
+impl PackIndex {
+    pub fn deserialize(bytes: &[u8], format: ObjectFormat) -> Result<Self> {
+        let hash_len = format.raw_len();
+        if bytes.len() < 8 || bytes[..4] != IDX_MAGIC {
+            return Err(anyhow::anyhow!("Unsupported pack index version: 1"));
+        }
+        let version = read_u32(bytes, 4)?;
+        if version != 2 {
+            return Err(anyhow::anyhow!(
+                "Unsupported pack index version: {}",
+                version
+            ));
+        }
+
+        let mut fanout = [0u32; 256];
+        for (i, count) in fanout.iter_mut().enumerate() {
+            *count = read_u32(bytes, 8 + i * 4)?;
+        }
+        // lookups slice the table of names with these counts
+        if fanout.windows(2).any(|pair| pair[0] > pair[1]) {
+            return Err(anyhow::anyhow!(
+                "Corrupt pack index: fanout table is not sorted"
+            ));
+        }
+        let count = fanout[255] as usize;
+
+        let hashes_start = 8 + 256 * 4;
+        let crcs_start = hashes_start + count * hash_len;
+        let offsets_start = crcs_start + count * 4;
+        let large_offsets_start = offsets_start + count * 4;
+        if bytes.len() < large_offsets_start {
+            return Err(anyhow::anyhow!("Truncated pack index"));
+        }
+
+        let mut hashes = Vec::with_capacity(count);
+        for i in 0..count {
+            let start = hashes_start + i * hash_len;
+            let hash = bytes
+                .get(start..start + hash_len)
+                .ok_or_else(|| anyhow::anyhow!("Truncated pack index"))?;
+            hashes.push(ObjectId::from_bytes(hash)?);
+        }
+
+        // the CRC32 table sits between the names and the offsets, it is only needed when
+        // verifying the packed data itself
+
+        let mut offsets = Vec::with_capacity(count);
+        for i in 0..count {
+            let offset = read_u32(bytes, offsets_start + i * 4)?;
+            if offset & 0x8000_0000 == 0 {
+                offsets.push(offset as u64);
+            } else {
+                // the remaining bits index into the table of 64-bit offsets
+                let pos = large_offsets_start + (offset & 0x7fff_ffff) as usize * 8;
+                let high = read_u32(bytes, pos)? as u64;
+                let low = read_u32(bytes, pos + 4)? as u64;
+                offsets.push(high << 32 | low);
+            }
+        }
+
+        Ok(Self {
+            fanout,
+            hashes,
+            offsets,
+        })
+    }
+
+    pub fn from_file(path: &Path, format: ObjectFormat) -> Result<Self> {
+        Self::deserialize(&fs::read(path)?, format)
+    }
+
+    /// The positions of the IDs whose first byte is `first`.
+    fn fanout_range(&self, first: u8) -> Range<usize> {
+        let first = first as usize;
+        let start = if first == 0 {
+            0
+        } else {
+            self.fanout[first - 1] as usize
+        };
+        start..self.fanout[first] as usize
+    }
+
+    fn position(&self, hash: &ObjectId) -> Option<usize> {
+        let range = self.fanout_range(hash.as_bytes()[0]);
+        let start = range.start;
+        self.hashes[range]
+            .binary_search(hash)
+            .ok()
+            .map(|pos| start + pos)
+    }
+
+    /// Returns the IDs that start with `prefix`, found by binary search.
+    pub fn find_prefix(&self, prefix: &ObjectIdPrefix) -> &[ObjectId] {
+        let hashes = match prefix.len() {
+            0 | 1 => &self.hashes[..],
+            _ => &self.hashes[self.fanout_range(prefix.first().as_bytes()[0])],
+        };
+        let first = prefix.first();
+        let start = hashes.partition_point(|hash| *hash < first);
+        let len = hashes[start..].partition_point(|hash| prefix.matches(hash));
+        &hashes[start..start + len]
+    }
+
+    pub fn offset(&self, hash: &ObjectId) -> Option<u64> {
+        self.position(hash).map(|pos| self.offsets[pos])
+    }
+
+    pub fn hashes(&self) -> &[ObjectId] {
+        &self.hashes
+    }
+}
+
+/// Looks up a REF_DELTA base that is not stored in the pack being read.
+pub type ObjectResolver<'a> = dyn Fn(&ObjectId) -> Result<(RGitObjectType, Vec<u8>)> + 'a;
+
+enum DeltaBase {
+    Offset(u64),
+    Hash(ObjectId),
+}
+
+/// The header of a pack entry, followed by its zlib-compressed data.
+struct EntryHeader {
+    pack_type: u8,
+    /// The size of the object, or of the delta for a deltified entry
+    size: usize,
+    base: Option<DeltaBase>,
+}
+
+/// A packfile together with its index.
+#[derive(Debug)]
+pub struct Pack {
+    path: PathBuf,
+    format: ObjectFormat,
+    index: PackIndex,
+}
+
+fn invalid_entry_header(offset: u64) -> anyhow::Error {
+    anyhow::anyhow!("Invalid pack entry header at offset {}", offset)
+}
+
+fn read_byte(reader: &mut dyn Read) -> Result<u8> {
+    let mut byte = [0u8; 1];
+    reader.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+impl Pack {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn index(&self) -> &PackIndex {
+        &self.index
+    }
+
+    /// Opens the packfile belonging to the given `.idx` file, which names objects in `format`.
+    pub fn open(idx_path: &Path, format: ObjectFormat) -> Result<Self> {
+        let index = PackIndex::from_file(idx_path, format)?;
+        let path = idx_path.with_extension("pack");
+
+        let mut header = [0u8; 12];
+        fs::File::open(&path)?.read_exact(&mut header)?;
+        if header[..4] != PACK_MAGIC {
+            return Err(anyhow::anyhow!("Invalid pack file: {:?}", path));
+        }
+        let version = u32::from_be_bytes(header[4..8].try_into()?);
+        if version != 2 && version != 3 {
+            return Err(anyhow::anyhow!("Unsupported pack version: {}", version));
+        }
+        let count = u32::from_be_bytes(header[8..12].try_into()?);
+        if count as usize != index.hashes().len() {
+            return Err(anyhow::anyhow!(
+                "Pack {:?} does not match its index",
+                path.file_name().unwrap()
+            ));
+        }
+
+        Ok(Self {
+            path,
+            format,
+            index,
+        })
+    }
+
+    /// Reads and fully resolves the object `hash`, returning `None` when it is not in this pack.
+    ///
+    /// `resolve_ref` is used to look up REF_DELTA bases that live outside of this pack.
+    pub fn read_object(
+        &self,
+        hash: &ObjectId,
+        resolve_ref: &ObjectResolver,
+    ) -> Result<Option<(RGitObjectType, Vec<u8>)>> {
+        match self.index.offset(hash) {
+            Some(offset) => {
+                let mut file = fs::File::open(&self.path)?;
+                Ok(Some(self.read_at(&mut file, offset, resolve_ref)?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the type and size of the object `hash` without inflating it, `None` when it is
+    /// not in this pack. Only the headers of the delta chain are read.
+    pub fn read_header(
+        &self,
+        hash: &ObjectId,
+        resolve_ref: &ObjectResolver,
+    ) -> Result<Option<(RGitObjectType, usize)>> {
+        match self.index.offset(hash) {
+            Some(offset) => {
+                let mut file = fs::File::open(&self.path)?;
+                Ok(Some(self.read_header_at(&mut file, offset, resolve_ref)?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Reads the header of the entry at `offset`, leaving `reader` at its compressed data.
+    fn read_entry_header(&self, reader: &mut dyn Read, offset: u64) -> Result<EntryHeader> {
+        let mut byte = read_byte(reader)?;
+        let pack_type = (byte >> 4) & 0x07;
+        let mut size = (byte & 0x0f) as usize;
+        let mut shift = 4;
+        while byte & 0x80 != 0 {
+            if shift > usize::BITS - 7 {
+                return Err(invalid_entry_header(offset));
+            }
+            byte = read_byte(reader)?;
+            size |= ((byte & 0x7f) as usize) << shift;
+            shift += 7;
+        }
+
+        let base = match pack_type {
+            OBJ_OFS_DELTA => {
+                byte = read_byte(reader)?;
+                let mut distance = (byte & 0x7f) as u64;
+                while byte & 0x80 != 0 {
+                    byte = read_byte(reader)?;
+                    distance = distance
+                        .checked_add(1)
+                        .and_then(|distance| distance.checked_mul(1 << 7))
+                        .ok_or_else(|| invalid_entry_header(offset))?
+                        | (byte & 0x7f) as u64;
+                }
+                let base_offset = offset
+                    .checked_sub(distance)
+                    .ok_or_else(|| anyhow::anyhow!("Invalid delta base offset"))?;
+                Some(DeltaBase::Offset(base_offset))
+            }
+            OBJ_REF_DELTA => {
+                let mut base_hash = vec![0u8; self.format.raw_len()];
+                reader.read_exact(&mut base_hash)?;
+                Some(DeltaBase::Hash(ObjectId::from_bytes(&base_hash)?))
+            }
+            _ => None,
+        };
+        Ok(EntryHeader {
+            pack_type,
+            size,
+            base,
+        })
+    }
+
+    fn check_chain_length(&self, deltas: usize, offset: u64) -> Result<()> {
+        if deltas > MAX_DELTA_CHAIN {
+            return Err(anyhow::anyhow!(
+                "Delta chain too long, or looping, at offset {} of {:?}",
+                offset,
+                self.path.file_name().unwrap()
+            ));
+        }
+        Ok(())
+    }
+
+    fn read_header_at(
+        &self,
+        file: &mut fs::File,
+        offset: u64,
+        resolve_ref: &ObjectResolver,
+    ) -> Result<(RGitObjectType, usize)> {
+        let mut size = None;
+        let mut offset = offset;
+        let mut deltas = 0;
+        loop {
+            self.check_chain_length(deltas, offset)?;
+            file.seek(SeekFrom::Start(offset))?;
+            let mut reader = BufReader::new(&mut *file);
+            let entry = self.read_entry_header(&mut reader, offset)?;
+            let Some(base) = entry.base else {
+                return Ok((
+                    object_type_from_pack(entry.pack_type)?,
+                    size.unwrap_or(entry.size),
+                ));
+            };
+
+            // the object has the type of the end of the chain, and its size is at the start of
+            // its own delta
+            if size.is_none() {
+                let mut delta_header = Vec::with_capacity(MAX_DELTA_HEADER);
+                ZlibDecoder::new(reader)
+                    .take(entry.size.min(MAX_DELTA_HEADER) as u64)
+                    .read_to_end(&mut delta_header)?;
+                size = Some(delta_result_size(&delta_header)?);
+            }
+            deltas += 1;
+            offset = match base {
+                DeltaBase::Offset(base_offset) => base_offset,
+                DeltaBase::Hash(base_hash) => match self.index.offset(&base_hash) {
+                    Some(base_offset) => base_offset,
+                    None => return Ok((resolve_ref(&base_hash)?.0, size.unwrap())),
+                },
+            };
+        }
+    }
+
+    fn read_at(
+        &self,
+        file: &mut fs::File,
+        offset: u64,
+        resolve_ref: &ObjectResolver,
+    ) -> Result<(RGitObjectType, Vec<u8>)> {
+        // the deltas are collected down to the base, then applied back up
+        let mut deltas = Vec::new();
+        let mut offset = offset;
+        let (object_type, mut content) = loop {
+            self.check_chain_length(deltas.len(), offset)?;
+            file.seek(SeekFrom::Start(offset))?;
+            let mut reader = BufReader::new(&mut *file);
+            let EntryHeader {
+                pack_type,
+                size,
+                base,
+            } = self.read_entry_header(&mut reader, offset)?;
+
+            // the declared size is not trusted for allocating, the content grows as it inflates
+            let mut content = Vec::new();
+            ZlibDecoder::new(reader)
+                .take(size as u64)
+                .read_to_end(&mut content)?;
+            if content.len() != size {
+                return Err(anyhow::anyhow!("Truncated pack entry at offset {}", offset));
+            }
+
+            let Some(base) = base else {
+                break (object_type_from_pack(pack_type)?, content);
+            };
+            deltas.push(content);
+            offset = match base {
+                DeltaBase::Offset(base_offset) => base_offset,
+                DeltaBase::Hash(base_hash) => match self.index.offset(&base_hash) {
+                    Some(base_offset) => base_offset,
+                    None => break resolve_ref(&base_hash)?,
+                },
+            };
+        };
+        for delta in deltas.iter().rev() {
+            content = apply_delta(&content, delta)?;
+        }
+        Ok((object_type, content))
+    }
+}
+
+fn object_type_from_pack(pack_type: u8) -> Result<RGitObjectType> {
+    match pack_type {
+        OBJ_COMMIT => Ok(RGitObjectType::Commit),
+        OBJ_TREE => Ok(RGitObjectType::Tree),
+        OBJ_BLOB => Ok(RGitObjectType::Blob),
+        OBJ_TAG => Ok(RGitObjectType::Tag),
+        _ => Err(anyhow::anyhow!("Invalid pack object type: {}", pack_type)),
+    }
+}
+
+fn object_type_to_pack(object_type: RGitObjectType) -> u8 {
+    match object_type {
+        RGitObjectType::Commit => OBJ_COMMIT,
+        RGitObjectType::Tree => OBJ_TREE,
+        RGitObjectType::Blob => OBJ_BLOB,
+        RGitObjectType::Tag => OBJ_TAG,
+    }
+}
+
+/// An object to be stored in a new pack.
+#[derive(Debug)]
+pub struct PackObject {
+    pub hash: ObjectId,
+    pub object_type: RGitObjectType,
+    pub content: Vec<u8>,
+    /// Path the object was found at, objects with the same file name make good delta bases
+    pub path: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub struct PackWriteResult {
+    pub pack_path: PathBuf,
+    pub object_count: usize,
+    pub delta_count: usize,
+    /// Combined size of the `.pack` and `.idx` files
+    pub size: u64,
+}
+
+fn write_entry_header(buf: &mut Vec<u8>, pack_type: u8, mut size: usize) {
+    let mut byte = (pack_type << 4) | (size & 0x0f) as u8;
+    size >>= 4;
+    while size > 0 {
+        buf.push(byte | 0x80);
+        byte = (size & 0x7f) as u8;
+        size >>= 7;
+    }
+    buf.push(byte);
+}
+
+fn write_base_distance(buf: &mut Vec<u8>, mut distance: u64) {
+    let mut bytes = vec![(distance & 0x7f) as u8];
+    distance >>= 7;
+    while distance > 0 {
+        distance -= 1;
+        bytes.push(0x80 | (distance & 0x7f) as u8);
+        distance >>= 7;
+    }
+    bytes.reverse();
+    buf.extend(bytes);
+}
+
+fn compress(data: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
+}
+
+fn checksum(data: &[u8], format: ObjectFormat) -> ObjectId {
+    let mut hasher = format.hasher();
+    hasher.update(data);
+    hasher.finalize()
+}
+
+/// Picks a delta base for every object by trying the `window` objects sorted right before it.
+/// Returns the base position and delta for each object that is worth storing as a delta.
+fn find_deltas(
+    objects: &[PackObject],
+    order: &[usize],
+    window: usize,
+    depth: usize,
+) -> Vec<Option<(usize, Vec<u8>)>> {
+    let mut deltas: Vec<Option<(usize, Vec<u8>)>> = (0..objects.len()).map(|_| None).collect();
+    let mut depths = vec![0; objects.len()];
+
+    for (pos, &i) in order.iter().enumerate() {
+        let object = &objects[i];
+        let mut best: Option<(usize, Vec<u8>)> = None;
+
+        for &j in order[pos.saturating_sub(window)..pos].iter().rev() {
+            let base = &objects[j];
+            if base.object_type != object.object_type || depths[j] >= depth {
+                continue;
+            }
+
+            // a delta is only kept when it saves at least half of the object, on ties the
+            // shallower base wins to keep chains short
+            let delta = create_delta(&base.content, &object.content);
+            let is_better = match &best {
+                Some((best_base, best_delta)) => {
+                    (delta.len(), depths[j]) < (best_delta.len(), depths[*best_base])
+                }
+                None => delta.len() < object.content.len() / 2,
+            };
+            if is_better {
+                best = Some((j, delta));
+            }
+        }
+
+        if let Some((j, delta)) = best {
+            depths[i] = depths[j] + 1;
+            deltas[i] = Some((j, delta));
+        }
+    }
+
+    deltas
+}
+
+/// Writes `objects` into a new `pack-<checksum>.pack` and its version 2 index inside
+/// `pack_dir`. Objects are delta-compressed against each other using a sliding window of
+/// `window` candidates and chains of at most `depth` deltas.
+///
+/// The checksums use the hash function of `format`, which all the objects must be named with.
+pub fn write_pack(
+    pack_dir: &Path,
+    objects: &[PackObject],
+    window: usize,
+    depth: usize,
+    format: ObjectFormat,
+) -> Result<PackWriteResult> {
+    if objects.iter().any(|object| object.hash.format() != format) {
+        return Err(anyhow::anyhow!(
+            "Every packed object must use the {} object format",
+            format
+        ));
+    }
+
+    // cluster objects of the same type and file name, largest first, so that the window
+    // mostly holds good delta bases
+    let mut order = (0..objects.len()).collect::<Vec<_>>();
+    order.sort_by(|&a, &b| {
+        let (a, b) = (&objects[a], &objects[b]);
+        let name = |object: &PackObject| {
+            object
+                .path
+                .rsplit(|&byte| byte == b'/')
+                .next()
+                .map(<[u8]>::to_vec)
+        };
+        object_type_to_pack(a.object_type)
+            .cmp(&object_type_to_pack(b.object_type))
+            .then_with(|| name(a).cmp(&name(b)))
+            .then_with(|| b.content.len().cmp(&a.content.len()))
+    });
+    let deltas = find_deltas(objects, &order, window, depth);
+
+    let mut pack = PACK_MAGIC.to_vec();
+    pack.extend(2u32.to_be_bytes());
+    pack.extend((objects.len() as u32).to_be_bytes());
+
+    let mut offsets = vec![0u64; objects.len()];
+    let mut crcs = vec![0u32; objects.len()];
+    for &i in order.iter() {
+        let object = &objects[i];
+        let offset = pack.len();
+        offsets[i] = offset as u64;
+
+        match &deltas[i] {
+            Some((base, delta)) => {
+                write_entry_header(&mut pack, OBJ_OFS_DELTA, delta.len());
+                write_base_distance(&mut pack, offsets[i] - offsets[*base]);
+                pack.extend(compress(delta)?);
+            }
+            None => {
+                write_entry_header(
+                    &mut pack,
+                    object_type_to_pack(object.object_type),
+                    object.content.len(),
+                );
+                pack.extend(compress(&object.content)?);
+            }
+        }
+        crcs[i] = crc32fast::hash(&pack[offset..]);
+    }
+    let pack_checksum = checksum(&pack, format);
+    pack.extend(pack_checksum.as_bytes());
+
+    let mut sorted = (0..objects.len()).collect::<Vec<_>>();
+    sorted.sort_by_key(|&i| objects[i].hash);
+
+    let mut index = IDX_MAGIC.to_vec();
+    index.extend(2u32.to_be_bytes());
+    for first in 0..256usize {
+        let count = sorted
+            .iter()
+            .filter(|&&i| objects[i].hash.as_bytes()[0] as usize <= first)
+            .count();
+        index.extend((count as u32).to_be_bytes());
+    }
+    for &i in sorted.iter() {
+        index.extend(objects[i].hash.as_bytes());
+    }
+    for &i in sorted.iter() {
+        index.extend(crcs[i].to_be_bytes());
+    }
+    let mut large_offsets = Vec::new();
+    for &i in sorted.iter() {
+        if offsets[i] < 0x8000_0000 {
+            index.extend((offsets[i] as u32).to_be_bytes());
+        } else {
+            index.extend((0x8000_0000 | large_offsets.len() as u32).to_be_bytes());
+            large_offsets.push(offsets[i]);
+        }
+    }
+    for offset in large_offsets {
+        index.extend(offset.to_be_bytes());
+    }
+    index.extend(pack_checksum.as_bytes());
+    let index_checksum = checksum(&index, format);
+    index.extend(index_checksum.as_bytes());
+
+    // the index goes in last, packs are only picked up once both files exist
+    fs::create_dir_all(pack_dir)?;
+    let name = format!("pack-{}", hex::encode(pack_checksum));
+    let pack_path = pack_dir.join(format!("{}.pack", name));
+    let idx_path = pack_dir.join(format!("{}.idx", name));
+    for (path, content) in [(&pack_path, &pack), (&idx_path, &index)] {
+        let tmp_path = pack_dir.join(format!(
+            "tmp_{}",
+            path.file_name().unwrap().to_string_lossy()
+        ));
+        let mut file = fs::File::create(&tmp_path)?;
+        file.write_all(content)?;
+        file.sync_all()?;
+        fs::rename(&tmp_path, path)?;
+    }
+
+    Ok(PackWriteResult {
+        pack_path,
+        object_count: objects.len(),
+        delta_count: deltas.iter().filter(|delta| delta.is_some()).count(),
+        size: (pack.len() + index.len()) as u64,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::ObjectFormat;
+    use crate::objects::RGitObjectHeader;
+    use std::process;
+    use tempfile::tempdir;
+
+    fn pack_objects() -> Vec<PackObject> {
+        let content = (0..500)
+            .map(|i| format!("line {}\n", i))
+            .collect::<String>();
+        let mut objects = Vec::new();
+        for (i, path) in ["a/file", "b/file", "c/other"].iter().enumerate() {
+            let content = content
+                .replace("line 250", &format!("line {}", i))
+                .into_bytes();
+            let header = RGitObjectHeader::new(RGitObjectType::Blob, content.len());
+            objects.push(PackObject {
+                hash: header.hash(content.as_slice(), ObjectFormat::Sha1).unwrap(),
+                object_type: RGitObjectType::Blob,
+                content,
+                path: path.as_bytes().to_vec(),
+            });
+        }
+        objects
+    }
+
+    #[test]
+    fn test_write_pack() {
+        let dir = tempdir().unwrap();
+        let objects = pack_objects();
+
+        let result = write_pack(dir.path(), &objects, 10, 50, ObjectFormat::Sha1).unwrap();
+        assert_eq!(result.object_count, 3);
+        assert_eq!(result.delta_count, 2);
+
+        let pack = Pack::open(&result.pack_path.with_extension("idx"), ObjectFormat::Sha1).unwrap();
+        let no_ref = |_: &ObjectId| -> Result<(RGitObjectType, Vec<u8>)> { unreachable!() };
+        for object in objects.iter() {
+            let (object_type, content) = pack.read_object(&object.hash, &no_ref).unwrap().unwrap();
+            assert_eq!(object_type, RGitObjectType::Blob);
+            assert_eq!(content, object.content);
+        }
+
+        // git must accept the pack and its index as well
+        let output = process::Command::new("git")
+            .args(["verify-pack", "-v"])
+            .arg(result.pack_path.with_extension("idx"))
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let output = String::from_utf8(output.stdout).unwrap();
+        assert!(output.contains("chain length = 1: 2 objects"), "{}", output);
+    }
+
+    #[test]
+    fn test_write_pack_depth() {
+        let dir = tempdir().unwrap();
+        let objects = pack_objects();
+
+        let result = write_pack(dir.path(), &objects, 10, 0, ObjectFormat::Sha1).unwrap();
+        assert_eq!(result.delta_count, 0);
+
+        let result = write_pack(dir.path(), &objects, 0, 50, ObjectFormat::Sha1).unwrap();
+        assert_eq!(result.delta_count, 0);
+    }
+
+    #[test]
+    fn test_pack_index_deserialize() {
+        let mut raw = [[0u8; 20]; 2];
+        raw[0][0] = 0x01;
+        raw[1][0] = 0xab;
+        let hashes = raw.map(|raw| ObjectId::from_bytes(&raw).unwrap());
+
+        let mut bytes = IDX_MAGIC.to_vec();
+        bytes.extend(2u32.to_be_bytes());
+        for i in 0..256 {
+            let count = raw.iter().filter(|hash| hash[0] as usize <= i).count();
+            bytes.extend((count as u32).to_be_bytes());
+        }
+        for hash in raw.iter() {
+            bytes.extend(hash);
+        }
+        bytes.extend(0xdeadbeefu32.to_be_bytes());
+        bytes.extend(0xcafebabeu32.to_be_bytes());
+        bytes.extend(12u32.to_be_bytes());
+        bytes.extend(0x8000_0000u32.to_be_bytes());
+        bytes.extend((1u64 << 33).to_be_bytes());
+
+        let index = PackIndex::deserialize(&bytes, ObjectFormat::Sha1).unwrap();
+        assert_eq!(index.offset(&hashes[0]), Some(12));
+        assert_eq!(index.offset(&hashes[1]), Some(1 << 33));
+        assert_eq!(
+            index.offset(&ObjectId::from_bytes(&[0u8; 20]).unwrap()),
+            None
+        );
+
+        let result = PackIndex::deserialize(b"\xfftOc\x00\x00\x00\x03", ObjectFormat::Sha1);
+        assert!(result.is_err());
+
+        // a fanout table that goes down, or counts more names than there are, is rejected
+        let mut corrupt = bytes.clone();
+        corrupt[8 + 0xab * 4..8 + 0xac * 4].copy_from_slice(&0u32.to_be_bytes());
+        let err = PackIndex::deserialize(&corrupt, ObjectFormat::Sha1).unwrap_err();
+        assert!(err.to_string().contains("fanout table is not sorted"));
+        let mut corrupt = bytes.clone();
+        for i in 0xab..256 {
+            corrupt[8 + i * 4..8 + i * 4 + 4].copy_from_slice(&1000u32.to_be_bytes());
+        }
+        assert!(PackIndex::deserialize(&corrupt, ObjectFormat::Sha1).is_err());
+    }
+
+    /// Writes a pack holding the single `entry`, named `hash`, and opens it.
+    fn single_entry_pack(dir: &Path, hash: &ObjectId, entry: &[u8]) -> Pack {
+        let mut pack = PACK_MAGIC.to_vec();
+        pack.extend(2u32.to_be_bytes());
+        pack.extend(1u32.to_be_bytes());
+        pack.extend(entry);
+        pack.extend(checksum(&pack, ObjectFormat::Sha1).as_bytes());
+
+        let mut index = IDX_MAGIC.to_vec();
+        index.extend(2u32.to_be_bytes());
+        for i in 0..256 {
+            index.extend(((i >= hash.as_bytes()[0] as usize) as u32).to_be_bytes());
+        }
+        index.extend(hash.as_bytes());
+        index.extend(0u32.to_be_bytes());
+        index.extend(12u32.to_be_bytes());
+        fs::write(dir.join("pack-test.pack"), pack).unwrap();
+        fs::write(dir.join("pack-test.idx"), index).unwrap();
+        Pack::open(&dir.join("pack-test.idx"), ObjectFormat::Sha1).unwrap()
+    }
+
+    #[test]
+    fn test_read_looping_delta() {
+        let dir = tempdir().unwrap();
+        let hash = ObjectId::from_bytes(&[0x42u8; 20]).unwrap();
+
+        // a delta whose base is itself
+        let mut entry = Vec::new();
+        let delta = [1, 1, 1, b'x'];
+        write_entry_header(&mut entry, OBJ_OFS_DELTA, delta.len());
+        write_base_distance(&mut entry, 0);
+        entry.extend(compress(&delta).unwrap());
+
+        let pack = single_entry_pack(dir.path(), &hash, &entry);
+        let resolve_ref = |_: &ObjectId| -> Result<(RGitObjectType, Vec<u8>)> { unreachable!() };
+        let err = pack.read_object(&hash, &resolve_ref).unwrap_err();
+        assert!(err.to_string().contains("Delta chain too long"));
+        let err = pack.read_header(&hash, &resolve_ref).unwrap_err();
+        assert!(err.to_string().contains("Delta chain too long"));
+    }
+
+    #[test]
+    fn test_read_invalid_entry_headers() {
+        let dir = tempdir().unwrap();
+        let hash = ObjectId::from_bytes(&[0x42u8; 20]).unwrap();
+        let resolve_ref = |_: &ObjectId| -> Result<(RGitObjectType, Vec<u8>)> { unreachable!() };
+
+        // sizes and base distances too large for their types are errors, not overflows
+        let mut size_overflow = vec![0x80 | (OBJ_BLOB << 4)];
+        size_overflow.extend([0xff; 12]);
+        let mut distance_overflow = vec![OBJ_OFS_DELTA << 4 | 1];
+        distance_overflow.extend([0xff; 12]);
+        for entry in [size_overflow, distance_overflow] {
+            let pack = single_entry_pack(dir.path(), &hash, &entry);
+            let err = pack.read_object(&hash, &resolve_ref).unwrap_err();
+            assert!(err.to_string().contains("Invalid pack entry header"));
+            let err = pack.read_header(&hash, &resolve_ref).unwrap_err();
+            assert!(err.to_string().contains("Invalid pack entry header"));
+        }
+
+        // a huge declared size is not allocated up front
+        let mut entry = Vec::new();
+        write_entry_header(&mut entry, OBJ_BLOB, 1 << 50);
+        entry.extend(compress(b"small").unwrap());
+        let pack = single_entry_pack(dir.path(), &hash, &entry);
+        let err = pack.read_object(&hash, &resolve_ref).unwrap_err();
+        assert!(err.to_string().contains("Truncated pack entry"));
+    }
+}
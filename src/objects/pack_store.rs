@@ -0,0 +1,423 @@
+use crate::error::RGitError;
+use crate::hash::{ObjectFormat, ObjectId, ObjectIdPrefix};
+use crate::objects::pack::Pack;
+use crate::objects::{LooseStore, ObjectStore, RGitObject, RGitObjectHeader, RGitObjectType};
+use anyhow::Result;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// Objects stored in the packfiles under `objects/pack`.
+#[derive(Debug)]
+pub struct PackStore {
+    pack_dir: PathBuf,
+    format: ObjectFormat,
+    /// The loose objects of the same directory, where REF_DELTA bases missing from the packs
+    /// can be found
+    loose: LooseStore,
+    // packs are immutable once written, so parsed indexes are kept for the lifetime of the
+    // store instead of being reloaded for every lookup
+    cache: Mutex<HashMap<PathBuf, Arc<Pack>>>,
+}
+
+impl PackStore {
+    pub fn new(objects_dir: &Path, format: ObjectFormat) -> Self {
+        Self {
+            pack_dir: objects_dir.join("pack"),
+            format,
+            loose: LooseStore::new(objects_dir),
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn pack_dir(&self) -> &Path {
+        &self.pack_dir
+    }
+
+    /// Returns every pack in the pack directory, ordered by file name.
+    pub fn packs(&self) -> Result<Vec<Arc<Pack>>> {
+        if !self.pack_dir.is_dir() {
+            return Ok(Vec::new());
+        }
+
+        let mut idx_paths = Vec::new();
+        for entry in fs::read_dir(&self.pack_dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "idx")
+                && path.with_extension("pack").is_file()
+            {
+                idx_paths.push(path);
+            }
+        }
+        idx_paths.sort();
+
+        let mut cache = self.cache.lock().unwrap();
+        let mut packs = Vec::new();
+        for idx_path in idx_paths {
+            let pack = match cache.get(&idx_path) {
+                Some(pack) => pack.clone(),
+                None => {
+                    let pack = Arc::new(Pack::open(&idx_path, self.format)?);
+                    cache.insert(idx_path, pack.clone());
+                    pack
+                }
+            };
+            packs.push(pack);
+        }
+        Ok(packs)
+    }
+
+    /// Reads the base of a REF_DELTA, from the loose objects first and then from the packs.
+    fn read_delta_base(&self, base: &ObjectId) -> Result<(RGitObjectType, Vec<u8>)> {
+        if let Some((header, mut reader)) = self.loose.read(base)? {
+            let mut content = Vec::new();
+            reader.read_to_end(&mut content)?;
+            return Ok((header.object_type, content));
+        }
+        self.read_packed_object(base)?
+            .ok_or_else(|| anyhow::anyhow!("Missing delta base object {}", hex::encode(base)))
+    }
+
+    fn read_packed_object(&self, hash: &ObjectId) -> Result<Option<(RGitObjectType, Vec<u8>)>> {
+        let resolve_ref = |base: &ObjectId| self.read_delta_base(base);
+        for pack in self.packs()? {
+            let object = pack
+                .read_object(hash, &resolve_ref)
+                .map_err(|err| corrupt_object(hash, err))?;
+            if let Some(object) = object {
+                return Ok(Some(object));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Reports a pack entry that fails to parse as a corrupt object. I/O errors are passed through,
+/// and so are errors already attributed to an object, such as a corrupt delta base.
+fn corrupt_object(hash: &ObjectId, err: anyhow::Error) -> anyhow::Error {
+    if err.is::<RGitError>() || err.is::<io::Error>() {
+        err
+    } else {
+        RGitError::corrupt_object(hash, err)
+    }
+}
+
+impl ObjectStore for PackStore {
+    fn read_header(&self, hash: &ObjectId) -> Result<Option<RGitObjectHeader>> {
+        let resolve_ref = |base: &ObjectId| self.read_delta_base(base);
+        for pack in self.packs()? {
+            let header = pack
+                .read_header(hash, &resolve_ref)
+                .map_err(|err| corrupt_object(hash, err))?;
+            if let Some((object_type, size)) = header {
+                return Ok(Some(RGitObjectHeader::new(object_type, size)));
+            }
+        }
+        Ok(None)
+    }
+
+    fn read(&self, hash: &ObjectId) -> Result<Option<(RGitObjectHeader, Box<dyn Read>)>> {
+        Ok(self.read_packed_object(hash)?.map(
+            |(object_type, content)| -> (RGitObjectHeader, Box<dyn Read>) {
+                (
+                    RGitObjectHeader::new(object_type, content.len()),
+                    Box::new(io::Cursor::new(content)),
+                )
+            },
+        ))
+    }
+
+    fn write(&self, _hash: &ObjectId, _object: &dyn RGitObject) -> Result<()> {
+        Err(anyhow::anyhow!(
+            "Objects cannot be added to existing packs, use repack instead"
+        ))
+    }
+
+    fn contains(&self, hash: &ObjectId) -> Result<bool> {
+        Ok(self
+            .packs()?
+            .iter()
+            .any(|pack| pack.index().offset(hash).is_some()))
+    }
+
+    /// Freshens the whole pack that holds the object, as git does.
+    fn freshen(&self, hash: &ObjectId) -> Result<bool> {
+        let packs = self.packs()?;
+        let Some(pack) = packs
+            .iter()
+            .find(|pack| pack.index().offset(hash).is_some())
+        else {
+            return Ok(false);
+        };
+        Ok(fs::File::open(pack.path())
+            .and_then(|file| file.set_modified(SystemTime::now()))
+            .is_ok())
+    }
+
+    fn find_prefix(&self, prefix: &ObjectIdPrefix) -> Result<Vec<ObjectId>> {
+        let mut hashes = Vec::new();
+        for pack in self.packs()? {
+            hashes.extend_from_slice(pack.index().find_prefix(prefix));
+        }
+        hashes.sort();
+        Ok(hashes)
+    }
+
+    fn iter(&self) -> Result<Box<dyn Iterator<Item = ObjectId> + '_>> {
+        let mut hashes = Vec::new();
+        for pack in self.packs()? {
+            hashes.extend_from_slice(pack.index().hashes());
+        }
+        Ok(Box::new(hashes.into_iter()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::ObjectFormat;
+    use crate::objects::delta::create_delta;
+    use crate::objects::{Blob, Commit, LooseStore, ObjectDatabase, Tree};
+    use crate::utils::init_rgit_dir;
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use std::process;
+    use tempfile::tempdir;
+
+    /// Packs every loose object with `git pack-objects` and removes the loose copies.
+    fn git_pack_objects(rgit_dir: &Path, extra_args: &[&str]) {
+        let objects = LooseStore::new(&rgit_dir.join("objects"))
+            .objects()
+            .unwrap();
+        let names = objects
+            .iter()
+            .map(|(hash, _)| format!("{}\n", hex::encode(hash)))
+            .collect::<String>();
+
+        let git_dir = rgit_dir.join("git");
+        process::Command::new("git")
+            .args(["init", "-q", "--bare"])
+            .arg(&git_dir)
+            .status()
+            .unwrap();
+        fs::create_dir_all(rgit_dir.join("objects/pack")).unwrap();
+        let mut child = process::Command::new("git")
+            .env("GIT_DIR", &git_dir)
+            .env("GIT_OBJECT_DIRECTORY", rgit_dir.join("objects"))
+            .args(["pack-objects", "-q", "--window=10"])
+            .args(extra_args)
+            .arg(rgit_dir.join("objects/pack/pack"))
+            .stdin(process::Stdio::piped())
+            .stdout(process::Stdio::null())
+            .spawn()
+            .unwrap();
+        io::Write::write_all(child.stdin.as_mut().unwrap(), names.as_bytes()).unwrap();
+        assert!(child.wait().unwrap().success());
+
+        // make sure the pack actually exercises delta resolution
+        let idx_path = fs::read_dir(rgit_dir.join("objects/pack"))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .find(|path| path.extension().unwrap() == "idx")
+            .unwrap();
+        let output = process::Command::new("git")
+            .env("GIT_DIR", &git_dir)
+            .args(["verify-pack", "-v"])
+            .arg(idx_path)
+            .output()
+            .unwrap();
+        assert!(String::from_utf8(output.stdout)
+            .unwrap()
+            .contains("chain length = 1"));
+
+        for (_, path) in objects {
+            fs::remove_file(path).unwrap();
+        }
+    }
+
+    fn write_repository(path: &Path, db: &ObjectDatabase) -> (ObjectId, ObjectId) {
+        let content = (0..200)
+            .map(|i| format!("line {}\n", i))
+            .collect::<String>();
+        fs::write(path.join("file"), &content).unwrap();
+        fs::create_dir(path.join("dir")).unwrap();
+        fs::write(
+            path.join("dir/subfile"),
+            content.replace("line 100", "changed"),
+        )
+        .unwrap();
+
+        let tree = Tree::from_directory(path, ObjectFormat::Sha1).unwrap();
+        tree.write_to_rgit_objects(db).unwrap();
+        let commit = Commit::new(*tree.hash(), Vec::new(), "Initial commit".to_string()).unwrap();
+        (*tree.hash(), commit.write_to_rgit_objects(db).unwrap())
+    }
+
+    fn check_packed_repository(extra_args: &[&str]) {
+        let dir = tempdir().unwrap();
+        let path = dir.path();
+        let rgit_dir = init_rgit_dir(path).unwrap();
+        let db = ObjectDatabase::open(&rgit_dir).unwrap();
+
+        let (tree_hash, commit_hash) = write_repository(path, &db);
+        let blob = Blob::from_file(&path.join("dir/subfile"), ObjectFormat::Sha1).unwrap();
+        git_pack_objects(&rgit_dir, extra_args);
+        let store = PackStore::new(&rgit_dir.join("objects"), ObjectFormat::Sha1);
+        assert_eq!(store.packs().unwrap().len(), 1);
+        assert_eq!(store.iter().unwrap().count(), 5);
+        assert!(store.contains(&commit_hash).unwrap());
+        assert!(store.write(&commit_hash, &blob).is_err());
+
+        // prefixes are searched for in the indexes
+        let mut hashes = store.iter().unwrap().collect::<Vec<_>>();
+        hashes.sort();
+        for len in [0, 1, 2, 3, 40] {
+            let prefix = ObjectIdPrefix::of(&commit_hash, len);
+            let expected = hashes
+                .iter()
+                .filter(|hash| prefix.matches(hash))
+                .copied()
+                .collect::<Vec<_>>();
+            assert_eq!(store.find_prefix(&prefix).unwrap(), expected);
+        }
+        assert_eq!(
+            store
+                .find_prefix(&ObjectIdPrefix::of(&commit_hash, 40))
+                .unwrap(),
+            vec![commit_hash]
+        );
+
+        let commit = Commit::from_rgit_objects(&db, &commit_hash).unwrap();
+        assert_eq!(commit.commit_message, "Initial commit");
+
+        let tree = Tree::from_rgit_objects(&db, &tree_hash).unwrap();
+        let mut buffer = Vec::new();
+        tree.print(&mut buffer).unwrap();
+        assert!(String::from_utf8(buffer).unwrap().contains("040000 tree"));
+
+        // headers are read from the pack entries, deltas included
+        let header = store.read_header(blob.hash()).unwrap().unwrap();
+        assert_eq!(header.object_type, RGitObjectType::Blob);
+        assert_eq!(
+            header.content_size,
+            fs::metadata(path.join("dir/subfile")).unwrap().len() as usize
+        );
+        assert_eq!(
+            store.read_header(&tree_hash).unwrap().unwrap().object_type,
+            RGitObjectType::Tree
+        );
+
+        let blob = Blob::from_rgit_objects(&db, blob.hash()).unwrap();
+        let mut buffer = Vec::new();
+        blob.print(&mut buffer).unwrap();
+        assert_eq!(
+            buffer,
+            fs::read(path.join("dir/subfile")).unwrap(),
+            "delta-compressed blob must be reconstructed exactly"
+        );
+    }
+
+    #[test]
+    fn test_read_ofs_delta_pack() {
+        check_packed_repository(&["--delta-base-offset"]);
+    }
+
+    #[test]
+    fn test_read_ref_delta_pack() {
+        check_packed_repository(&[]);
+    }
+
+    fn sha1(data: &[u8]) -> ObjectId {
+        let mut hasher = ObjectFormat::Sha1.hasher();
+        hasher.update(data);
+        hasher.finalize()
+    }
+
+    /// Appends the header of a pack entry: its type and variable-length size.
+    fn push_entry_header(buf: &mut Vec<u8>, pack_type: u8, mut size: usize) {
+        let mut byte = (pack_type << 4) | (size & 0x0f) as u8;
+        size >>= 4;
+        while size > 0 {
+            buf.push(byte | 0x80);
+            byte = (size & 0x7f) as u8;
+            size >>= 7;
+        }
+        buf.push(byte);
+    }
+
+    #[test]
+    fn test_read_ref_delta_with_loose_base() {
+        let dir = tempdir().unwrap();
+        let rgit_dir = init_rgit_dir(dir.path()).unwrap();
+        let db = ObjectDatabase::open(&rgit_dir).unwrap();
+        let base = (0..100)
+            .map(|i| format!("line {}\n", i))
+            .collect::<String>();
+        let target = base.replace("line 50", "changed");
+        fs::write(dir.path().join("base"), &base).unwrap();
+        let base_blob = Blob::from_file(&dir.path().join("base"), ObjectFormat::Sha1).unwrap();
+        base_blob.write_to_rgit_objects(&db).unwrap();
+        let target_hash = RGitObjectHeader::new(RGitObjectType::Blob, target.len())
+            .hash(target.as_bytes(), ObjectFormat::Sha1)
+            .unwrap();
+
+        // a pack holding only a REF_DELTA against the loose base
+        let delta = create_delta(base.as_bytes(), target.as_bytes());
+        let mut pack = b"PACK".to_vec();
+        pack.extend(2u32.to_be_bytes());
+        pack.extend(1u32.to_be_bytes());
+        push_entry_header(&mut pack, 7, delta.len());
+        pack.extend(base_blob.hash().as_bytes());
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        io::Write::write_all(&mut encoder, &delta).unwrap();
+        pack.extend(encoder.finish().unwrap());
+        let pack_checksum = sha1(&pack);
+        pack.extend(pack_checksum.as_bytes());
+
+        let mut index = vec![0xff, b't', b'O', b'c'];
+        index.extend(2u32.to_be_bytes());
+        for first in 0..256 {
+            let count = (target_hash.as_bytes()[0] as usize <= first) as u32;
+            index.extend(count.to_be_bytes());
+        }
+        index.extend(target_hash.as_bytes());
+        index.extend(0u32.to_be_bytes());
+        index.extend(12u32.to_be_bytes());
+        index.extend(pack_checksum.as_bytes());
+        let index_checksum = sha1(&index);
+        index.extend(index_checksum.as_bytes());
+        let pack_dir = rgit_dir.join("objects/pack");
+        fs::create_dir_all(&pack_dir).unwrap();
+        fs::write(pack_dir.join("pack-thin.pack"), pack).unwrap();
+        fs::write(pack_dir.join("pack-thin.idx"), index).unwrap();
+
+        let store = PackStore::new(&rgit_dir.join("objects"), ObjectFormat::Sha1);
+        let header = store.read_header(&target_hash).unwrap().unwrap();
+        assert_eq!(header.object_type, RGitObjectType::Blob);
+        assert_eq!(header.content_size, target.len());
+        let (_, mut reader) = db.read(&target_hash).unwrap();
+        let mut content = String::new();
+        reader.read_to_string(&mut content).unwrap();
+        assert_eq!(content, target);
+
+        // without its base the packed object can't be read back
+        fs::remove_file(LooseStore::new(&rgit_dir.join("objects")).object_path(base_blob.hash()))
+            .unwrap();
+        let err = store.read(&target_hash).err().unwrap();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(RGitError::CorruptObject { id, .. }) if *id == target_hash
+        ));
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "fatal: object {} is corrupt: Missing delta base object {}",
+                target_hash,
+                base_blob.hash()
+            )
+        );
+    }
+}
@@ -1,16 +1,17 @@
-use crate::objects::{Blob, Commit, Tree};
-use crate::utils::get_rgit_object_path;
+use crate::error::RGitError;
+use crate::hash::{hash_object, ObjectFormat, ObjectId};
+use crate::objects::{Blob, Commit, ObjectDatabase, Tag, Tree};
 use anyhow::Result;
 use std::fmt;
-use std::fs;
-use std::io::{Read, Write};
-use std::path::Path;
+use std::io::{self, Read, Write};
+use std::str::FromStr;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum RGitObjectType {
     Blob,
     Tree,
     Commit,
+    Tag,
 }
 
 impl fmt::Display for RGitObjectType {
@@ -19,17 +20,24 @@ impl fmt::Display for RGitObjectType {
             RGitObjectType::Blob => write!(f, "blob"),
             RGitObjectType::Tree => write!(f, "tree"),
             RGitObjectType::Commit => write!(f, "commit"),
+            RGitObjectType::Tag => write!(f, "tag"),
         }
     }
 }
 
-impl RGitObjectType {
-    pub fn from_str(s: &str) -> Result<Self> {
+impl FromStr for RGitObjectType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
         match s {
             "blob" => Ok(RGitObjectType::Blob),
             "tree" => Ok(RGitObjectType::Tree),
             "commit" => Ok(RGitObjectType::Commit),
-            _ => Err(anyhow::anyhow!("Invalid object type: {:?}", s)),
+            "tag" => Ok(RGitObjectType::Tag),
+            _ => Err(RGitError::InvalidObjectType {
+                name: s.to_string(),
+            }
+            .into()),
         }
     }
 }
@@ -76,13 +84,20 @@ impl RGitObjectHeader {
             .ok_or(anyhow::anyhow!("Invalid object header: {:?}", header))?
             .parse::<usize>()?;
 
-        let object_type = RGitObjectType::from_str(&object_type)?;
+        let object_type = object_type.parse::<RGitObjectType>()?;
 
         Ok(Self {
             object_type,
             content_size,
         })
     }
+
+    /// Computes the object ID the way git does: over the header followed by the content.
+    pub fn hash<R: Read>(&self, content: R, format: ObjectFormat) -> Result<ObjectId> {
+        let mut header = Vec::new();
+        self.serialize(&mut header)?;
+        hash_object(io::Cursor::new(header).chain(content), format)
+    }
 }
 
 pub trait RGitObject {
@@ -93,42 +108,117 @@ pub trait RGitObject {
     fn print(&self, writer: &mut dyn Write) -> Result<()>;
 }
 
-pub fn from_rgit_objects(rgit_dir: &Path, hash: &[u8; 20]) -> Result<Box<dyn RGitObject>> {
-    let object_path = get_rgit_object_path(rgit_dir, &hash, true)?;
-    let mut reader = fs::File::open(&object_path)?;
+/// An object kept as its undecoded content, used when objects are moved between storages.
+#[derive(Debug)]
+pub struct RawObject {
+    pub object_type: RGitObjectType,
+    pub content: Vec<u8>,
+}
+
+impl RawObject {
+    /// Reads the whole content of an object, checking it against the size declared in its
+    /// header.
+    pub fn from_rgit_objects(db: &ObjectDatabase, hash: &ObjectId) -> Result<Self> {
+        let (header, mut reader) = db.read(hash)?;
+        let mut content = Vec::with_capacity(header.content_size);
+        reader.read_to_end(&mut content)?;
+        if content.len() != header.content_size {
+            return Err(RGitError::corrupt_object(
+                hash,
+                format!(
+                    "header declares {} bytes but content has {}",
+                    header.content_size,
+                    content.len()
+                ),
+            ));
+        }
+        Ok(Self {
+            object_type: header.object_type,
+            content,
+        })
+    }
+}
+
+impl RGitObject for RawObject {
+    fn object_type(&self) -> RGitObjectType {
+        self.object_type
+    }
+
+    fn size(&self) -> usize {
+        self.content.len()
+    }
+
+    fn serialize(&self, writer: &mut dyn Write) -> Result<()> {
+        RGitObjectHeader::new(self.object_type, self.content.len()).serialize(writer)?;
+        writer.write_all(&self.content)?;
+        Ok(())
+    }
+
+    fn print(&self, writer: &mut dyn Write) -> Result<()> {
+        writer.write_all(&self.content)?;
+        Ok(())
+    }
+}
 
-    let header = RGitObjectHeader::deserialize(&mut reader)?;
+pub fn from_rgit_objects(db: &ObjectDatabase, hash: &ObjectId) -> Result<Box<dyn RGitObject>> {
+    let header = db.read_header(hash)?;
     match header.object_type {
-        RGitObjectType::Blob => Ok(Box::new(Blob::from_rgit_objects(rgit_dir, hash)?)),
-        RGitObjectType::Tree => Ok(Box::new(Tree::from_rgit_objects(rgit_dir, hash)?)),
-        RGitObjectType::Commit => Ok(Box::new(Commit::from_rgit_objects(rgit_dir, hash)?)),
+        RGitObjectType::Blob => Ok(Box::new(Blob::from_rgit_objects(db, hash)?)),
+        RGitObjectType::Tree => Ok(Box::new(Tree::from_rgit_objects(db, hash)?)),
+        RGitObjectType::Commit => Ok(Box::new(Commit::from_rgit_objects(db, hash)?)),
+        RGitObjectType::Tag => Ok(Box::new(Tag::from_rgit_objects(db, hash)?)),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::hash::ObjectFormat;
     use crate::utils::init_rgit_dir;
+    use std::fs;
     use tempfile::tempdir;
 
+    #[test]
+    fn test_header_hash() {
+        // use `echo -n "hello world" | git hash-object --stdin` to get the ground truth
+        let header = RGitObjectHeader::new(RGitObjectType::Blob, 11);
+        let hash = header
+            .hash("hello world".as_bytes(), ObjectFormat::Sha1)
+            .unwrap();
+        assert_eq!(
+            hex::encode(hash),
+            "95d09f2b10159347eece71399a7e2e907ea3df4f"
+        );
+
+        // same command inside a repository created with `git init --object-format=sha256`
+        let hash = header
+            .hash("hello world".as_bytes(), ObjectFormat::Sha256)
+            .unwrap();
+        assert_eq!(
+            hex::encode(hash),
+            "fee53a18d32820613c0527aa79be5cb30173c823a9b448fa4817767cc84c6f03"
+        );
+    }
+
     #[test]
     fn test_from_rgit_objects() {
         let dir = tempdir().unwrap();
         let rgit_dir = init_rgit_dir(dir.path()).unwrap();
+        let db = ObjectDatabase::open(&rgit_dir).unwrap();
 
         fs::write(dir.path().join("file.txt"), "Hello, world!").unwrap();
         fs::create_dir_all(dir.path().join("subdir")).unwrap();
         fs::write(dir.path().join("subdir/file.txt"), "Hello, world!").unwrap();
 
-        let tree = Tree::from_directory(&dir.path()).unwrap();
-        tree.write_to_rgit_objects(rgit_dir.as_path()).unwrap();
+        let tree = Tree::from_directory(dir.path(), ObjectFormat::Sha1).unwrap();
+        tree.write_to_rgit_objects(&db).unwrap();
 
-        let blob = Blob::from_file(&dir.path().join("file.txt")).unwrap();
+        let blob = Blob::from_file(&dir.path().join("file.txt"), ObjectFormat::Sha1).unwrap();
 
-        let tree = from_rgit_objects(rgit_dir.as_path(), tree.hash()).unwrap();
+        let tree = from_rgit_objects(&db, tree.hash()).unwrap();
         assert_eq!(tree.object_type(), RGitObjectType::Tree);
 
-        let blob = from_rgit_objects(rgit_dir.as_path(), blob.hash()).unwrap();
+        let blob = from_rgit_objects(&db, blob.hash()).unwrap();
         assert_eq!(blob.object_type(), RGitObjectType::Blob);
     }
 }
@@ -0,0 +1,386 @@
+use crate::config::Config;
+use crate::error::RGitError;
+use crate::hash::{ObjectFormat, ObjectId, ObjectIdPrefix};
+use crate::objects::{LooseStore, MemoryStore, PackStore, RGitObject, RGitObjectHeader};
+use anyhow::Result;
+use std::collections::{BTreeSet, HashSet};
+use std::fmt;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// A storage backend for objects.
+///
+/// Lookups return `None` when the backend does not hold the object, so that several backends
+/// can be chained by an [`ObjectDatabase`].
+pub trait ObjectStore: fmt::Debug + Send + Sync {
+    /// Reads only the header of an object.
+    fn read_header(&self, hash: &ObjectId) -> Result<Option<RGitObjectHeader>>;
+
+    /// Returns the header of an object together with a reader over its content.
+    fn read(&self, hash: &ObjectId) -> Result<Option<(RGitObjectHeader, Box<dyn Read>)>>;
+
+    fn write(&self, hash: &ObjectId, object: &dyn RGitObject) -> Result<()>;
+
+    fn contains(&self, hash: &ObjectId) -> Result<bool>;
+
+    /// Marks an object as just written, so that pruning spares it like a new one. Returns whether
+    /// the object could be freshened, `false` when this backend does not hold it.
+    fn freshen(&self, hash: &ObjectId) -> Result<bool>;
+
+    /// Returns the IDs of the objects of this backend that start with `prefix`, ordered.
+    fn find_prefix(&self, prefix: &ObjectIdPrefix) -> Result<Vec<ObjectId>>;
+
+    /// Iterates over the IDs of every object in this backend.
+    fn iter(&self) -> Result<Box<dyn Iterator<Item = ObjectId> + '_>>;
+}
+
+/// The set of object backends of a repository.
+///
+/// Lookups try each backend in turn and writes go to the first one. Cloning is cheap, the
+/// backends are shared.
+///
+/// Every object of a database uses the same object format, IDs of another format are refused.
+#[derive(Debug, Clone)]
+pub struct ObjectDatabase {
+    stores: Arc<Vec<Box<dyn ObjectStore>>>,
+    /// The backends after the first `local` ones belong to alternates
+    local: usize,
+    /// Whether lookups fall back to the alternates
+    alternates: bool,
+    format: ObjectFormat,
+}
+
+fn object_not_found(hash: &ObjectId) -> anyhow::Error {
+    RGitError::ObjectNotFound { id: *hash }.into()
+}
+
+/// Returns the object directories listed in `objects/info/alternates` of `objects_dir` and,
+/// recursively, in those of the alternates themselves.
+///
+/// Relative paths are relative to the object directory that lists them. Directories that were
+/// already visited, including `objects_dir`, are skipped so that cycles terminate, and so are
+/// directories that do not exist.
+pub fn read_alternates(objects_dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut alternates = Vec::new();
+    let mut visited = HashSet::new();
+    visited.insert(objects_dir.canonicalize()?);
+
+    let mut pending = vec![objects_dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let path = dir.join("info").join("alternates");
+        if !path.is_file() {
+            continue;
+        }
+        let mut found = Vec::new();
+        for line in fs::read_to_string(&path)?.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            // like git, a vanished alternate only shows up as missing objects
+            let Ok(canonical) = dir.join(line).canonicalize() else {
+                continue;
+            };
+            if visited.insert(canonical.clone()) {
+                alternates.push(canonical.clone());
+                found.push(canonical);
+            }
+        }
+        // depth first, in the order the alternates are listed
+        pending.extend(found.into_iter().rev());
+    }
+
+    Ok(alternates)
+}
+
+fn check_format(hash: &ObjectId, format: ObjectFormat) -> Result<()> {
+    if hash.format() != format {
+        return Err(RGitError::new(
+            format!(
+                "fatal: {} is a {} object name, but the repository uses {}",
+                hash,
+                hash.format(),
+                format
+            ),
+            128,
+        ));
+    }
+    Ok(())
+}
+
+impl ObjectDatabase {
+    pub fn new(stores: Vec<Box<dyn ObjectStore>>, format: ObjectFormat) -> Self {
+        Self {
+            local: stores.len(),
+            stores: Arc::new(stores),
+            alternates: true,
+            format,
+        }
+    }
+
+    /// Opens the loose objects and packs of the repository at `rgit_dir`, followed by those of
+    /// its alternates.
+    pub fn open(rgit_dir: &Path) -> Result<Self> {
+        if !rgit_dir.is_dir() {
+            return Err(RGitError::NotARepository { path: None }.into());
+        }
+
+        let format = Config::load(rgit_dir)?.object_format()?;
+        let objects_dir = rgit_dir.join("objects");
+        let mut stores: Vec<Box<dyn ObjectStore>> = vec![
+            Box::new(LooseStore::new(&objects_dir)),
+            Box::new(PackStore::new(&objects_dir, format)),
+        ];
+        let local = stores.len();
+        if objects_dir.is_dir() {
+            for alternate in read_alternates(&objects_dir)? {
+                stores.push(Box::new(LooseStore::new(&alternate)));
+                stores.push(Box::new(PackStore::new(&alternate, format)));
+            }
+        }
+
+        Ok(Self {
+            local,
+            ..Self::new(stores, format)
+        })
+    }
+
+    /// Returns a view of this database that ignores the objects borrowed from alternates.
+    pub fn without_alternates(&self) -> Self {
+        Self {
+            alternates: false,
+            ..self.clone()
+        }
+    }
+
+    fn stores(&self) -> &[Box<dyn ObjectStore>] {
+        match self.alternates {
+            true => &self.stores,
+            false => &self.stores[..self.local],
+        }
+    }
+
+    /// Creates a database that keeps every object in memory.
+    pub fn in_memory(format: ObjectFormat) -> Self {
+        Self::new(vec![Box::new(MemoryStore::new())], format)
+    }
+
+    pub fn format(&self) -> ObjectFormat {
+        self.format
+    }
+
+    pub fn read_header(&self, hash: &ObjectId) -> Result<RGitObjectHeader> {
+        check_format(hash, self.format)?;
+        for store in self.stores().iter() {
+            if let Some(header) = store.read_header(hash)? {
+                return Ok(header);
+            }
+        }
+        Err(object_not_found(hash))
+    }
+
+    pub fn read(&self, hash: &ObjectId) -> Result<(RGitObjectHeader, Box<dyn Read>)> {
+        check_format(hash, self.format)?;
+        for store in self.stores().iter() {
+            if let Some(object) = store.read(hash)? {
+                return Ok(object);
+            }
+        }
+        Err(object_not_found(hash))
+    }
+
+    /// Writes an object to the first backend, unless any backend already holds it. An existing
+    /// object is freshened instead, so that a prune running before it gets referenced does not
+    /// delete it.
+    pub fn write(&self, hash: &ObjectId, object: &dyn RGitObject) -> Result<()> {
+        check_format(hash, self.format)?;
+        for store in self.stores().iter() {
+            if store.freshen(hash)? {
+                return Ok(());
+            }
+        }
+        match self.stores().first() {
+            Some(store) => store.write(hash, object),
+            None => Err(anyhow::anyhow!("No object store to write to")),
+        }
+    }
+
+    pub fn contains(&self, hash: &ObjectId) -> Result<bool> {
+        if hash.format() != self.format {
+            return Ok(false);
+        }
+        for store in self.stores().iter() {
+            if store.contains(hash)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Returns the IDs of the objects in any of the backends that start with `prefix`, ordered and
+    /// deduplicated.
+    pub fn find_prefix(&self, prefix: &ObjectIdPrefix) -> Result<Vec<ObjectId>> {
+        let mut hashes = BTreeSet::new();
+        for store in self.stores().iter() {
+            hashes.extend(store.find_prefix(prefix)?);
+        }
+        Ok(hashes.into_iter().collect())
+    }
+
+    /// Returns the IDs of every object in any of the backends, ordered and deduplicated.
+    pub fn hashes(&self) -> Result<Vec<ObjectId>> {
+        let mut hashes = BTreeSet::new();
+        for store in self.stores().iter() {
+            hashes.extend(store.iter()?);
+        }
+        Ok(hashes.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::ObjectFormat;
+    use crate::objects::{Blob, Commit, RGitObjectType, Tree};
+    use crate::utils::init_rgit_dir;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_open() {
+        let dir = tempdir().unwrap();
+        let result = ObjectDatabase::open(&dir.path().join(".rgit"));
+        assert!(result.is_err());
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("not a rgit repository"));
+
+        let rgit_dir = init_rgit_dir(dir.path()).unwrap();
+        let db = ObjectDatabase::open(&rgit_dir).unwrap();
+
+        fs::write(dir.path().join("file"), "file content").unwrap();
+        let blob = Blob::from_file(&dir.path().join("file"), ObjectFormat::Sha1).unwrap();
+        blob.write_to_rgit_objects(&db).unwrap();
+        assert!(db.contains(blob.hash()).unwrap());
+        assert!(rgit_dir
+            .join("objects")
+            .join(&hex::encode(blob.hash())[..2])
+            .is_dir());
+    }
+
+    #[test]
+    fn test_alternates() {
+        let dir = tempdir().unwrap();
+        let mut objects_dirs = Vec::new();
+        for name in ["a", "b", "c"] {
+            fs::create_dir(dir.path().join(name)).unwrap();
+            let rgit_dir = init_rgit_dir(&dir.path().join(name)).unwrap();
+            fs::create_dir_all(rgit_dir.join("objects/info")).unwrap();
+            objects_dirs.push(rgit_dir.join("objects").canonicalize().unwrap());
+        }
+        // a -> b -> c -> b, a -> missing and b -> a
+        fs::write(
+            objects_dirs[0].join("info/alternates"),
+            "# comment\n../../../b/.rgit/objects\n\n/missing/objects\n",
+        )
+        .unwrap();
+        fs::write(
+            objects_dirs[1].join("info/alternates"),
+            format!(
+                "{}\n{}\n",
+                objects_dirs[2].display(),
+                objects_dirs[0].display()
+            ),
+        )
+        .unwrap();
+        fs::write(
+            objects_dirs[2].join("info/alternates"),
+            "../../../b/.rgit/objects\n",
+        )
+        .unwrap();
+        assert_eq!(
+            read_alternates(&objects_dirs[0]).unwrap(),
+            objects_dirs[1..].to_vec()
+        );
+
+        fs::write(dir.path().join("c/file"), "file content").unwrap();
+        let blob = Blob::from_file(&dir.path().join("c/file"), ObjectFormat::Sha1).unwrap();
+        blob.write_to_rgit_objects(&ObjectDatabase::open(&dir.path().join("c/.rgit")).unwrap())
+            .unwrap();
+        let db = ObjectDatabase::open(&dir.path().join("a/.rgit")).unwrap();
+        assert!(db.contains(blob.hash()).unwrap());
+        assert_eq!(db.hashes().unwrap(), vec![*blob.hash()]);
+        assert!(!db.without_alternates().contains(blob.hash()).unwrap());
+        assert!(db.without_alternates().hashes().unwrap().is_empty());
+
+        // objects found in an alternate are not written again
+        blob.write_to_rgit_objects(&db).unwrap();
+        assert!(!db.without_alternates().contains(blob.hash()).unwrap());
+    }
+
+    #[test]
+    fn test_in_memory() {
+        let dir = tempdir().unwrap();
+        // the work tree still needs a repository for its ignore rules
+        let rgit_dir = init_rgit_dir(dir.path()).unwrap();
+        let db = ObjectDatabase::in_memory(ObjectFormat::Sha1);
+
+        fs::write(dir.path().join("file"), "file content").unwrap();
+        fs::create_dir(dir.path().join("dir")).unwrap();
+        fs::write(dir.path().join("dir/subfile"), "subfile content").unwrap();
+        let tree = Tree::from_directory(dir.path(), ObjectFormat::Sha1).unwrap();
+        tree.write_to_rgit_objects(&db).unwrap();
+        let commit = Commit::new(*tree.hash(), Vec::new(), "Initial commit".to_string()).unwrap();
+        let commit_hash = commit.write_to_rgit_objects(&db).unwrap();
+
+        // no object touched the disk
+        assert!(!rgit_dir.join("objects").exists());
+        assert_eq!(db.hashes().unwrap().len(), 5);
+        assert_eq!(
+            db.find_prefix(&ObjectIdPrefix::of(&commit_hash, 0))
+                .unwrap(),
+            db.hashes().unwrap()
+        );
+        assert_eq!(
+            db.find_prefix(&ObjectIdPrefix::of(&commit_hash, 5))
+                .unwrap(),
+            vec![commit_hash]
+        );
+
+        let commit = Commit::from_rgit_objects(&db, &commit_hash).unwrap();
+        assert_eq!(commit.commit_message, "Initial commit");
+        let header = db.read_header(commit.tree()).unwrap();
+        assert_eq!(header.object_type, RGitObjectType::Tree);
+        let tree = Tree::from_rgit_objects(&db, commit.tree()).unwrap();
+        assert_eq!(tree.hash(), commit.tree());
+    }
+
+    #[test]
+    fn test_read_missing_object() {
+        let db = ObjectDatabase::in_memory(ObjectFormat::Sha1);
+        let hash = ObjectId::from_bytes(&[0u8; 20]).unwrap();
+        let result = db.read(&hash);
+        assert!(result.is_err());
+        assert!(result
+            .err()
+            .unwrap()
+            .to_string()
+            .contains("Not a valid object name"));
+        assert!(!db.contains(&hash).unwrap());
+    }
+
+    #[test]
+    fn test_object_format_mismatch() {
+        let db = ObjectDatabase::in_memory(ObjectFormat::Sha256);
+        let hash = ObjectId::from_bytes(&[0u8; 20]).unwrap();
+        let result = db.read_header(&hash);
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("is a sha1 object name, but the repository uses sha256"));
+        assert!(!db.contains(&hash).unwrap());
+    }
+}
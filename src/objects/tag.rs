@@ -0,0 +1,300 @@
+use crate::error::RGitError;
+use crate::hash::{ObjectFormat, ObjectId};
+use crate::objects::commit::{current_time, parse_timezone_offset, serialize_timezone_offset};
+use crate::objects::{ObjectDatabase, RGitObject, RGitObjectHeader, RGitObjectType, RawObject};
+use anyhow::Result;
+use chrono::{DateTime, FixedOffset, TimeZone};
+use std::fmt;
+use std::io::Write;
+use std::str;
+
+/// Who did something and when, serialized as `Name <email> <timestamp> <+hhmm>`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signature {
+    pub name: String,
+    pub email: String,
+    pub time: DateTime<FixedOffset>,
+}
+
+impl Signature {
+    /// Creates a signature for `name` and `email` at the current local time.
+    pub fn now(name: &str, email: &str) -> Result<Self> {
+        Ok(Self {
+            name: name.to_string(),
+            email: email.to_string(),
+            time: current_time()?,
+        })
+    }
+
+    pub fn parse(signature: &str) -> Result<Self> {
+        let invalid = || anyhow::anyhow!("Invalid signature: {:?}", signature);
+
+        let (name, rest) = signature.split_once(" <").ok_or_else(invalid)?;
+        let (email, rest) = rest.split_once("> ").ok_or_else(invalid)?;
+        let (timestamp, offset) = rest.split_once(' ').ok_or_else(invalid)?;
+        let timestamp = timestamp.parse::<i64>().map_err(|_| invalid())?;
+        let time = parse_timezone_offset(offset)?
+            .timestamp_opt(timestamp, 0)
+            .single()
+            .ok_or_else(invalid)?;
+
+        Ok(Self {
+            name: name.to_string(),
+            email: email.to_string(),
+            time,
+        })
+    }
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} <{}> {} {}",
+            self.name,
+            self.email,
+            self.time.timestamp(),
+            serialize_timezone_offset(self.time.offset())
+        )
+    }
+}
+
+/// An annotated tag, naming another object and carrying a message.
+#[derive(Debug)]
+pub struct Tag {
+    object: ObjectId,
+    object_type: RGitObjectType,
+    name: String,
+    /// Absent from some old tags, which git still accepts.
+    tagger: Option<Signature>,
+    pub message: String,
+}
+
+impl Tag {
+    pub fn new(
+        object: ObjectId,
+        object_type: RGitObjectType,
+        name: String,
+        tagger: Option<Signature>,
+        message: String,
+    ) -> Self {
+        Self {
+            object,
+            object_type,
+            name,
+            tagger,
+            message,
+        }
+    }
+
+    pub fn from_rgit_objects(db: &ObjectDatabase, hash: &ObjectId) -> Result<Self> {
+        let raw = RawObject::from_rgit_objects(db, hash)?;
+        if raw.object_type != RGitObjectType::Tag {
+            return Err(RGitError::wrong_object_type(
+                hash,
+                RGitObjectType::Tag,
+                raw.object_type,
+            ));
+        }
+        Self::deserialize(&raw.content, hash.format())
+            .map_err(|err| RGitError::corrupt_object(hash, err))
+    }
+
+    /// Parses the content of a tag object that refers to an object of the given format.
+    pub fn deserialize(content: &[u8], format: ObjectFormat) -> Result<Self> {
+        let content = str::from_utf8(content)?;
+        let (headers, message) = content.split_once("\n\n").unwrap_or((content, ""));
+        let mut lines = headers.lines();
+        let mut header = |key: &str| {
+            let line = lines.next().unwrap_or_default();
+            line.strip_prefix(key)
+                .and_then(|line| line.strip_prefix(' '))
+                .ok_or_else(|| anyhow::anyhow!("Invalid {} line: {:?}", key, line))
+        };
+
+        let object_line = header("object")?;
+        let object = ObjectId::from_hex(object_line)
+            .ok()
+            .filter(|id| id.format() == format)
+            .ok_or_else(|| anyhow::anyhow!("Invalid object line: {:?}", object_line))?;
+        let object_type = header("type")?.parse::<RGitObjectType>()?;
+        let name = header("tag")?.to_string();
+        let tagger = match lines.next() {
+            Some(line) => Some(Signature::parse(
+                line.strip_prefix("tagger ")
+                    .ok_or_else(|| anyhow::anyhow!("Invalid tagger line: {:?}", line))?,
+            )?),
+            None => None,
+        };
+
+        Ok(Self {
+            object,
+            object_type,
+            name,
+            tagger,
+            message: message.to_string(),
+        })
+    }
+
+    /// The object the tag refers to.
+    pub fn object(&self) -> &ObjectId {
+        &self.object
+    }
+
+    /// The type of the object the tag refers to.
+    pub fn target_type(&self) -> RGitObjectType {
+        self.object_type
+    }
+
+    pub fn hash(&self) -> Result<ObjectId> {
+        let content = self.content();
+        RGitObjectHeader::new(self.object_type(), content.len())
+            .hash(content.as_bytes(), self.object.format())
+    }
+
+    pub fn write_to_rgit_objects(&self, db: &ObjectDatabase) -> Result<ObjectId> {
+        let hash = self.hash()?;
+        db.write(&hash, self)?;
+        Ok(hash)
+    }
+
+    fn content(&self) -> String {
+        let tagger = match &self.tagger {
+            Some(tagger) => format!("tagger {}\n", tagger),
+            None => String::new(),
+        };
+        format!(
+            "object {}\ntype {}\ntag {}\n{}\n{}",
+            self.object, self.object_type, self.name, tagger, self.message
+        )
+    }
+}
+
+impl RGitObject for Tag {
+    fn object_type(&self) -> RGitObjectType {
+        RGitObjectType::Tag
+    }
+
+    fn size(&self) -> usize {
+        self.content().len()
+    }
+
+    fn serialize(&self, writer: &mut dyn Write) -> Result<()> {
+        let header = RGitObjectHeader::new(self.object_type(), self.size());
+        header.serialize(writer)?;
+        writer.write_all(self.content().as_bytes())?;
+        Ok(())
+    }
+
+    fn print(&self, writer: &mut dyn Write) -> Result<()> {
+        writer.write_all(self.content().as_bytes())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::{Commit, Tree};
+    use crate::utils::init_rgit_dir;
+    use std::fs;
+    use std::process;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_signature() {
+        let signature = Signature::parse("A U Thor <author@example.com> 1700000000 -0130").unwrap();
+        assert_eq!(signature.name, "A U Thor");
+        assert_eq!(signature.email, "author@example.com");
+        assert_eq!(signature.time.timestamp(), 1700000000);
+        assert_eq!(
+            signature.to_string(),
+            "A U Thor <author@example.com> 1700000000 -0130"
+        );
+
+        assert!(Signature::parse("A U Thor 1700000000 +0000").is_err());
+        assert!(Signature::parse("A U Thor <author@example.com> now +0000").is_err());
+    }
+
+    #[test]
+    fn test_tag() {
+        let dir = tempdir().unwrap();
+        let db = ObjectDatabase::open(&init_rgit_dir(dir.path()).unwrap()).unwrap();
+        fs::write(dir.path().join("file"), "file content").unwrap();
+        let tree = Tree::from_directory(dir.path(), ObjectFormat::Sha1).unwrap();
+        tree.write_to_rgit_objects(&db).unwrap();
+        let commit = Commit::new(*tree.hash(), Vec::new(), "commit".to_string()).unwrap();
+        let commit_hash = commit.write_to_rgit_objects(&db).unwrap();
+
+        let tagger = Signature::now("A U Thor", "author@example.com").unwrap();
+        let tag = Tag::new(
+            commit_hash,
+            RGitObjectType::Commit,
+            "v1.0".to_string(),
+            Some(tagger.clone()),
+            "Release 1.0\n\nSigned-off-by: A U Thor <author@example.com>\n".to_string(),
+        );
+        let tag_hash = tag.write_to_rgit_objects(&db).unwrap();
+
+        let tag = Tag::from_rgit_objects(&db, &tag_hash).unwrap();
+        assert_eq!(tag.object(), &commit_hash);
+        assert_eq!(tag.target_type(), RGitObjectType::Commit);
+        assert_eq!(tag.name, "v1.0");
+        assert_eq!(tag.tagger, Some(tagger));
+        assert!(tag
+            .message
+            .ends_with("Signed-off-by: A U Thor <author@example.com>\n"));
+        assert_eq!(tag.hash().unwrap(), tag_hash);
+
+        assert!(Tag::from_rgit_objects(&db, &commit_hash).is_err());
+        assert!(Tag::deserialize(b"object 1234\ntype commit\n", ObjectFormat::Sha1).is_err());
+
+        // old tags have no tagger
+        let content = format!("object {}\ntype commit\ntag v0.1\n\nOld\n", commit_hash);
+        let tag = Tag::deserialize(content.as_bytes(), ObjectFormat::Sha1).unwrap();
+        assert_eq!(tag.name, "v0.1");
+        assert_eq!(tag.tagger, None);
+        assert_eq!(tag.message, "Old\n");
+        assert_eq!(tag.content(), content);
+        let content = format!(
+            "object {}\ntype commit\ntag v0.1\nbogus\n\nOld\n",
+            commit_hash
+        );
+        assert!(Tag::deserialize(content.as_bytes(), ObjectFormat::Sha1).is_err());
+    }
+
+    #[test]
+    fn test_git_tag() {
+        // a tag written by `git tag -a` must round-trip to the same object ID
+        let dir = tempdir().unwrap();
+        let git = |args: &[&str]| {
+            let output = process::Command::new("git")
+                .args(args)
+                .current_dir(dir.path())
+                .env("GIT_COMMITTER_NAME", "A U Thor")
+                .env("GIT_COMMITTER_EMAIL", "author@example.com")
+                .env("GIT_COMMITTER_DATE", "1700000000 +0900")
+                .env("GIT_AUTHOR_NAME", "A U Thor")
+                .env("GIT_AUTHOR_EMAIL", "author@example.com")
+                .output()
+                .unwrap();
+            assert!(output.status.success(), "{:?}", output);
+            output.stdout
+        };
+        git(&["init", "-q"]);
+        git(&["commit", "-q", "--allow-empty", "-m", "commit"]);
+        git(&["tag", "-a", "v1.0", "-m", "Release 1.0"]);
+        let tag_hash = String::from_utf8(git(&["rev-parse", "v1.0"])).unwrap();
+        let tag_hash = ObjectId::from_hex(tag_hash.trim()).unwrap();
+        let content = git(&["cat-file", "tag", "v1.0"]);
+
+        let tag = Tag::deserialize(&content, ObjectFormat::Sha1).unwrap();
+        assert_eq!(tag.name, "v1.0");
+        assert_eq!(
+            tag.tagger.as_ref().unwrap().to_string(),
+            "A U Thor <author@example.com> 1700000000 +0900"
+        );
+        assert_eq!(tag.message, "Release 1.0\n");
+        assert_eq!(tag.hash().unwrap(), tag_hash);
+    }
+}
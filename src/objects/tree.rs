@@ -1,22 +1,31 @@
-use crate::hash::hash_object;
+use crate::error::RGitError;
+use crate::hash::{ObjectFormat, ObjectId};
 use crate::ignore::is_ignored;
 use crate::objects::blob::Blob;
-use crate::objects::{RGitObject, RGitObjectHeader, RGitObjectType};
-use crate::utils::get_rgit_object_path;
+use crate::objects::{ObjectDatabase, RGitObject, RGitObjectHeader, RGitObjectType, RawObject};
+use crate::refs::list_refs;
+use crate::utils::quote_path;
 use anyhow::Result;
-use std::collections::BTreeMap;
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+use std::cmp::Ordering;
+use std::ffi::OsStr;
+use std::fmt;
 use std::fs;
-use std::io::{self, Read, Write};
+use std::io::{self, Write};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
 use std::os::unix::fs::PermissionsExt;
 use std::path::Path;
 use std::str;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum EntryType {
     Regular,
     Executable,
     Tree,
     Symlink,
+    /// A nested repository, recorded as the commit checked out in it.
+    Gitlink,
 }
 
 impl EntryType {
@@ -24,22 +33,84 @@ impl EntryType {
         match s {
             "100644" => Ok(EntryType::Regular),
             "100755" => Ok(EntryType::Executable),
-            "040000" => Ok(EntryType::Tree),
+            // trees written by older versions of rgit zero-padded the mode of subtrees
+            "40000" | "040000" => Ok(EntryType::Tree),
             "120000" => Ok(EntryType::Symlink),
+            "160000" => Ok(EntryType::Gitlink),
             _ => Err(anyhow::anyhow!("Invalid entry type: {}", s)),
         }
     }
+}
 
-    fn to_string(&self) -> String {
+/// The mode as stored in tree objects, `cat-file -p` pads it to 6 digits like git.
+impl fmt::Display for EntryType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            EntryType::Regular => "100644".to_string(),
-            EntryType::Executable => "100755".to_string(),
-            EntryType::Tree => "040000".to_string(),
-            EntryType::Symlink => "120000".to_string(),
+            EntryType::Regular => write!(f, "100644"),
+            EntryType::Executable => write!(f, "100755"),
+            EntryType::Tree => write!(f, "40000"),
+            EntryType::Symlink => write!(f, "120000"),
+            EntryType::Gitlink => write!(f, "160000"),
         }
     }
 }
 
+/// Reads the raw `(mode, name, hash)` records of a tree object without loading any of the
+/// objects it refers to.
+pub fn read_tree_entries(
+    db: &ObjectDatabase,
+    hash: &ObjectId,
+) -> Result<Vec<(EntryType, Vec<u8>, ObjectId)>> {
+    let raw = RawObject::from_rgit_objects(db, hash)?;
+    if raw.object_type != RGitObjectType::Tree {
+        return Err(RGitError::wrong_object_type(
+            hash,
+            RGitObjectType::Tree,
+            raw.object_type,
+        ));
+    }
+    parse_tree_entries(&raw.content, hash.format())
+        .map_err(|err| RGitError::corrupt_object(hash, err))
+}
+
+/// Parses the content of a tree object into its `(mode, name, hash)` records. Names are kept as
+/// the raw bytes stored in the tree, they are not necessarily UTF-8.
+pub fn parse_tree_entries(
+    content: &[u8],
+    format: ObjectFormat,
+) -> Result<Vec<(EntryType, Vec<u8>, ObjectId)>> {
+    let mut entries = Vec::new();
+    let mut cursor = 0;
+
+    while cursor < content.len() {
+        let space_pos = content[cursor..]
+            .iter()
+            .position(|&x| x == b' ')
+            .ok_or(anyhow::anyhow!("Invalid tree entry"))?;
+        let mode = str::from_utf8(&content[cursor..cursor + space_pos])?.to_string();
+        cursor += space_pos + 1;
+
+        let null_pos = content[cursor..]
+            .iter()
+            .position(|&x| x == 0)
+            .ok_or(anyhow::anyhow!("Invalid tree entry"))?;
+        let name = content[cursor..cursor + null_pos].to_vec();
+        cursor += null_pos + 1;
+
+        let hash = content
+            .get(cursor..cursor + format.raw_len())
+            .and_then(|hash| ObjectId::from_bytes(hash).ok())
+            .ok_or(anyhow::anyhow!("Invalid tree entry"))?;
+        cursor += format.raw_len();
+
+        entries.push((EntryType::from_str(&mode)?, name, hash));
+    }
+
+    Ok(entries)
+}
+
+/// The in-memory object of an entry of a tree built from a directory, which still has to be
+/// written to the database.
 #[derive(Debug)]
 enum EntryObject {
     Blob(Blob),
@@ -48,192 +119,329 @@ enum EntryObject {
 
 #[derive(Debug)]
 pub struct Tree {
-    entries: BTreeMap<String, Entry>,
-    hash: [u8; 20],
+    /// Entries in the order they are stored in the tree object.
+    entries: Vec<Entry>,
+    hash: ObjectId,
+    /// The database the tree was loaded from, which resolves its entries when they are walked.
+    db: Option<ObjectDatabase>,
 }
 
 #[derive(Debug)]
 struct Entry {
     entry_type: EntryType,
-    name: String,
-    object: EntryObject,
+    name: Vec<u8>,
+    hash: ObjectId,
+    /// Unset for entries loaded from the database and for gitlinks.
+    object: Option<EntryObject>,
 }
 
-impl Tree {
-    fn new(entries: BTreeMap<String, Entry>) -> Result<Self> {
-        let mut content = Vec::new();
-
-        for (name, entry) in &entries {
-            match &entry.object {
-                EntryObject::Blob(blob) => {
-                    content
-                        .extend(format!("{} {}\0", entry.entry_type.to_string(), name).as_bytes());
-                    content.extend(blob.hash());
-                }
-                EntryObject::Tree(tree) => {
-                    content
-                        .extend(format!("{} {}\0", entry.entry_type.to_string(), name).as_bytes());
-                    content.extend(tree.hash());
-                }
-            }
+impl EntryType {
+    /// The type of the object an entry of this type refers to.
+    pub fn object_type(&self) -> RGitObjectType {
+        match self {
+            EntryType::Tree => RGitObjectType::Tree,
+            EntryType::Gitlink => RGitObjectType::Commit,
+            EntryType::Regular | EntryType::Executable | EntryType::Symlink => RGitObjectType::Blob,
         }
+    }
+}
 
-        let hash = hash_object(io::Cursor::new(content))?;
+/// An entry reached while walking a tree, named by its path from the root of the walk.
+pub struct TreeEntry<'a> {
+    tree: &'a Tree,
+    entry: &'a Entry,
+    path: Vec<u8>,
+}
 
-        Ok(Self { entries, hash })
+impl TreeEntry<'_> {
+    /// The `/`-separated path of the entry.
+    pub fn path(&self) -> &[u8] {
+        &self.path
     }
 
-    pub fn from_directory(path: &Path) -> Result<Self> {
-        let mut entries = BTreeMap::new();
+    pub fn entry_type(&self) -> EntryType {
+        self.entry.entry_type
+    }
 
-        for entry in fs::read_dir(path)? {
-            let entry = entry?;
-            let file_type = entry.file_type()?;
-            let entry_path = entry.path();
+    pub fn hash(&self) -> &ObjectId {
+        &self.entry.hash
+    }
 
-            // ignore .rgit directory
-            if entry_path.ends_with(".rgit") {
-                continue;
-            }
-            if is_ignored(&entry_path)?.is_ignored {
-                continue;
+    /// Resolves the blob of a file or symlink entry.
+    pub fn blob(&self) -> Result<Blob> {
+        match (&self.entry.object, &self.tree.db) {
+            (Some(EntryObject::Blob(blob)), _) => Ok(blob.clone()),
+            (None, Some(db)) if self.entry.entry_type.object_type() == RGitObjectType::Blob => {
+                Blob::from_rgit_objects(db, &self.entry.hash)
             }
+            _ => Err(anyhow::anyhow!(
+                "{} is not a blob",
+                String::from_utf8_lossy(&self.path)
+            )),
+        }
+    }
+}
 
-            let name = entry
-                .file_name()
-                .into_string()
-                .map_err(|_| anyhow::anyhow!("Invalid file name: {:?}", entry.file_name()))?;
-
-            let entry_type = if file_type.is_dir() {
-                EntryType::Tree
-            } else if file_type.is_symlink() {
-                EntryType::Symlink
-            } else if file_type.is_file() {
-                let metadata = entry.metadata()?;
-                let mode = metadata.permissions().mode();
-                if mode & 0o111 != 0 {
-                    EntryType::Executable
-                } else {
-                    EntryType::Regular
-                }
-            } else {
-                return Err(anyhow::anyhow!("Unsupported entry type: {:?}", entry_path));
-            };
+impl Entry {
+    /// Creates the entry of a file or directory of the work tree, `None` for ignored ones.
+    fn from_dir_entry(entry: &fs::DirEntry, format: ObjectFormat) -> Result<Option<Self>> {
+        let file_type = entry.file_type()?;
+        let entry_path = entry.path();
 
-            let object = match entry_type {
-                EntryType::Regular | EntryType::Executable => {
-                    EntryObject::Blob(Blob::from_file(&entry_path)?)
-                }
-                EntryType::Tree => EntryObject::Tree(Tree::from_directory(&entry_path)?),
-                EntryType::Symlink => {
-                    return Err(anyhow::anyhow!("Symlink is not supported yet"));
-                }
-            };
+        // ignore .rgit directory
+        if entry_path.ends_with(".rgit") {
+            return Ok(None);
+        }
+        if is_ignored(&entry_path)?.is_ignored {
+            return Ok(None);
+        }
 
-            let entry_name = name.clone();
-            entries.insert(
-                name,
-                Entry {
+        let name = entry.file_name().into_vec();
+
+        let entry_type = if file_type.is_dir() && entry_path.join(".rgit").is_dir() {
+            EntryType::Gitlink
+        } else if file_type.is_dir() {
+            EntryType::Tree
+        } else if file_type.is_symlink() {
+            EntryType::Symlink
+        } else if file_type.is_file() {
+            let metadata = entry.metadata()?;
+            let mode = metadata.permissions().mode();
+            // like git, only the owner's execute bit counts
+            if mode & 0o100 != 0 {
+                EntryType::Executable
+            } else {
+                EntryType::Regular
+            }
+        } else {
+            return Err(anyhow::anyhow!("Unsupported entry type: {:?}", entry_path));
+        };
+
+        let object = match entry_type {
+            EntryType::Regular | EntryType::Executable => {
+                EntryObject::Blob(Blob::from_file(&entry_path, format)?)
+            }
+            EntryType::Tree => EntryObject::Tree(Tree::from_directory(&entry_path, format)?),
+            EntryType::Symlink => EntryObject::Blob(Blob::from_symlink(&entry_path, format)?),
+            EntryType::Gitlink => {
+                return Ok(Some(Entry {
                     entry_type,
-                    name: entry_name,
-                    object,
-                },
-            );
-        }
+                    name,
+                    hash: read_gitlink(&entry_path, format)?,
+                    object: None,
+                }));
+            }
+        };
+        let hash = match &object {
+            EntryObject::Blob(blob) => *blob.hash(),
+            EntryObject::Tree(tree) => *tree.hash(),
+        };
+
+        Ok(Some(Entry {
+            entry_type,
+            name,
+            hash,
+            object: Some(object),
+        }))
+    }
+}
 
-        Tree::new(entries)
+/// Returns true when `path` is `prefix` or lies below it.
+fn is_under(path: &[u8], prefix: &[u8]) -> bool {
+    path.starts_with(prefix) && (path.len() == prefix.len() || path[prefix.len()] == b'/')
+}
+
+/// Returns the commit checked out in the nested repository at `path`.
+fn read_gitlink(path: &Path, format: ObjectFormat) -> Result<ObjectId> {
+    let head = list_refs(&path.join(".rgit"))?
+        .into_iter()
+        .find(|(name, _)| name == "HEAD")
+        .map(|(_, hash)| hash)
+        .ok_or_else(|| {
+            anyhow::anyhow!("'{}' does not have a commit checked out", path.display())
+        })?;
+    if head.format() != format {
+        return Err(anyhow::anyhow!(
+            "'{}' uses {}, but the repository uses {}",
+            path.display(),
+            head.format(),
+            format
+        ));
     }
+    Ok(head)
+}
 
-    pub fn write_to_directory(&self, path: &Path) -> Result<()> {
-        for (name, entry) in &self.entries {
-            let entry_path = path.join(name);
-            match &entry.object {
-                EntryObject::Blob(blob) => {
-                    blob.write_to_file(&entry_path)?;
-                }
-                EntryObject::Tree(tree) => {
-                    fs::create_dir(&entry_path)?;
-                    tree.write_to_directory(&entry_path)?;
-                }
-            }
-        }
+/// Compares entries in git's canonical tree order: byte-wise by name, where the name of a subtree
+/// compares as if it ended with `/`. `foo.c` thus sorts before the directory `foo` but after the
+/// file `foo`.
+fn compare_entries(a: &Entry, b: &Entry) -> Ordering {
+    let sort_key = |entry: &Entry| {
+        let suffix: &[u8] = match entry.entry_type {
+            EntryType::Tree => b"/",
+            _ => b"",
+        };
+        entry.name.iter().chain(suffix).copied().collect::<Vec<_>>()
+    };
+    sort_key(a).cmp(&sort_key(b))
+}
 
-        Ok(())
+/// Serializes entries into the content of a tree object.
+fn serialize_entries(entries: &[Entry]) -> Vec<u8> {
+    let mut content = Vec::new();
+    for entry in entries {
+        content.extend(format!("{} ", entry.entry_type).as_bytes());
+        content.extend(&entry.name);
+        content.push(0);
+        content.extend(entry.hash.as_bytes());
+    }
+    content
+}
+
+impl Tree {
+    fn new(entries: Vec<Entry>, format: ObjectFormat, db: Option<ObjectDatabase>) -> Result<Self> {
+        let content = serialize_entries(&entries);
+        let header = RGitObjectHeader::new(RGitObjectType::Tree, content.len());
+        let hash = header.hash(content.as_slice(), format)?;
+
+        Ok(Self { entries, hash, db })
     }
 
-    pub fn from_rgit_objects(rgit_dir: &Path, hash: &[u8; 20]) -> Result<Self> {
-        let object_path = get_rgit_object_path(rgit_dir, hash, true)?;
-        let mut reader = fs::File::open(&object_path)?;
+    /// Builds the tree of the work tree directory `path`. Subdirectories are scanned and files
+    /// hashed in parallel on the current rayon thread pool, entries are sorted afterwards so the
+    /// hash doesn't depend on the scheduling.
+    pub fn from_directory(path: &Path, format: ObjectFormat) -> Result<Self> {
+        let dir_entries = fs::read_dir(path)?.collect::<io::Result<Vec<_>>>()?;
+        let mut entries = dir_entries
+            .into_par_iter()
+            .map(|entry| Entry::from_dir_entry(&entry, format))
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+
+        entries.sort_by(compare_entries);
+        Tree::new(entries, format, None)
+    }
 
-        let header = RGitObjectHeader::deserialize(&mut reader)?;
-        let mut content = vec![0; header.content_size];
-        reader.read_exact(&mut content)?;
+    /// Builds the tree of `path` like [`Tree::from_directory`] on a pool of `threads` threads,
+    /// 0 meaning one per CPU.
+    pub fn from_directory_with_threads(
+        path: &Path,
+        format: ObjectFormat,
+        threads: usize,
+    ) -> Result<Self> {
+        let pool = ThreadPoolBuilder::new().num_threads(threads).build()?;
+        pool.install(|| Tree::from_directory(path, format))
+    }
 
-        let mut entries = BTreeMap::new();
-        let mut cursor = 0;
+    pub fn write_to_directory(&self, path: &Path) -> Result<()> {
+        self.walk(&[], &mut |entry| {
+            let entry_path = path.join(OsStr::from_bytes(entry.path()));
+            match entry.entry_type() {
+                EntryType::Regular => entry.blob()?.write_to_file(&entry_path)?,
+                EntryType::Executable => entry.blob()?.write_to_executable(&entry_path)?,
+                EntryType::Symlink => entry.blob()?.write_to_symlink(&entry_path)?,
+                // the nested repository is not cloned, only its directory is created
+                EntryType::Tree | EntryType::Gitlink => fs::create_dir(&entry_path)?,
+            }
+            Ok(true)
+        })
+    }
 
-        while cursor < content.len() {
-            let space_pos = content[cursor..]
-                .iter()
-                .position(|&x| x == b' ')
-                .ok_or(anyhow::anyhow!("Invalid tree entry"))?;
-            let mode = str::from_utf8(&content[cursor..cursor + space_pos])?.to_string();
-            cursor += space_pos + 1;
+    /// Loads the tree `hash` without reading any of the objects it refers to, they are resolved
+    /// when the tree is walked. Entries are kept in their stored order so that the tree hashes the
+    /// same even if it was not written in canonical order.
+    pub fn from_rgit_objects(db: &ObjectDatabase, hash: &ObjectId) -> Result<Self> {
+        let entries = read_tree_entries(db, hash)?
+            .into_iter()
+            .map(|(entry_type, name, hash)| Entry {
+                entry_type,
+                name,
+                hash,
+                object: None,
+            })
+            .collect();
 
-            let null_pos = content[cursor..]
-                .iter()
-                .position(|&x| x == 0)
-                .ok_or(anyhow::anyhow!("Invalid tree entry"))?;
-            let name = str::from_utf8(&content[cursor..cursor + null_pos])?.to_string();
-            cursor += null_pos + 1;
+        Tree::new(entries, hash.format(), Some(db.clone()))
+    }
 
-            let hash = <[u8; 20]>::try_from(&content[cursor..cursor + 20])
-                .map_err(|_| anyhow::anyhow!("Invalid tree entry"))?;
-            cursor += 20;
+    pub fn hash(&self) -> &ObjectId {
+        &self.hash
+    }
 
-            let entry_type = EntryType::from_str(&mode)?;
+    /// Calls `visit` for every entry of the tree and of its subtrees, a subtree before its
+    /// entries, unless `visit` returns false for it. When `paths` is not empty, only the entries
+    /// at or below one of these paths are visited, and only the subtrees leading to them are
+    /// resolved.
+    pub fn walk(
+        &self,
+        paths: &[&[u8]],
+        visit: &mut dyn FnMut(&TreeEntry) -> Result<bool>,
+    ) -> Result<()> {
+        self.walk_at(&[], paths, visit)
+    }
 
-            let entry_object: EntryObject = match entry_type {
-                EntryType::Regular | EntryType::Executable => {
-                    EntryObject::Blob(Blob::from_rgit_objects(rgit_dir, &hash)?)
-                }
-                EntryType::Tree => EntryObject::Tree(Tree::from_rgit_objects(rgit_dir, &hash)?),
-                EntryType::Symlink => {
-                    return Err(anyhow::anyhow!("Symlink is not supported yet"));
-                }
+    fn walk_at(
+        &self,
+        prefix: &[u8],
+        paths: &[&[u8]],
+        visit: &mut dyn FnMut(&TreeEntry) -> Result<bool>,
+    ) -> Result<()> {
+        for entry in &self.entries {
+            let path = if prefix.is_empty() {
+                entry.name.clone()
+            } else {
+                [prefix, b"/", &entry.name].concat()
             };
+            let selected = paths.is_empty() || paths.iter().any(|filter| is_under(&path, filter));
+            let leads_to_selected = paths.iter().any(|filter| is_under(filter, &path));
+            if !selected && !leads_to_selected {
+                continue;
+            }
 
-            entries.insert(
-                name.clone(),
-                Entry {
-                    entry_type,
-                    name,
-                    object: entry_object,
-                },
-            );
+            let descend = if selected {
+                visit(&TreeEntry {
+                    tree: self,
+                    entry,
+                    path: path.clone(),
+                })?
+            } else {
+                true
+            };
+            if !descend || entry.entry_type != EntryType::Tree {
+                continue;
+            }
+            match (&entry.object, &self.db) {
+                (Some(EntryObject::Tree(tree)), _) => tree.walk_at(&path, paths, visit)?,
+                (None, Some(db)) => {
+                    Tree::from_rgit_objects(db, &entry.hash)?.walk_at(&path, paths, visit)?
+                }
+                _ => {
+                    return Err(anyhow::anyhow!(
+                        "Unresolved tree entry: {}",
+                        String::from_utf8_lossy(&path)
+                    ))
+                }
+            }
         }
 
-        Tree::new(entries)
-    }
-
-    pub fn hash(&self) -> &[u8; 20] {
-        &self.hash
+        Ok(())
     }
 
-    pub fn write_to_rgit_objects(&self, rgit_dir: &Path) -> Result<()> {
-        let object_path = get_rgit_object_path(rgit_dir, &self.hash, false)?;
-        fs::create_dir_all(object_path.parent().unwrap())?;
-        let mut file = fs::File::create(&object_path)?;
-        self.serialize(&mut file)?;
+    /// Writes the tree and the objects of a tree built from a directory. Entries loaded from the
+    /// database are already there.
+    pub fn write_to_rgit_objects(&self, db: &ObjectDatabase) -> Result<()> {
+        db.write(&self.hash, self)?;
 
-        for (_, entry) in &self.entries {
+        for entry in &self.entries {
             match &entry.object {
-                EntryObject::Blob(blob) => {
-                    blob.write_to_rgit_objects(rgit_dir)?;
+                Some(EntryObject::Blob(blob)) => {
+                    blob.write_to_rgit_objects(db)?;
                 }
-                EntryObject::Tree(tree) => {
-                    tree.write_to_rgit_objects(rgit_dir)?;
+                Some(EntryObject::Tree(tree)) => {
+                    tree.write_to_rgit_objects(db)?;
                 }
+                None => {}
             }
         }
 
@@ -248,54 +456,34 @@ impl RGitObject for Tree {
 
     fn size(&self) -> usize {
         let mut size = 0;
-        for (_, entry) in &self.entries {
-            size += entry.entry_type.to_string().len() + 1 + entry.name.len() + 1 + 20;
+        for entry in &self.entries {
+            size += entry.entry_type.to_string().len()
+                + 1
+                + entry.name.len()
+                + 1
+                + self.hash.format().raw_len();
         }
         size
     }
 
     fn serialize(&self, writer: &mut dyn Write) -> Result<()> {
-        let mut content = Vec::new();
-        for (name, entry) in &self.entries {
-            match &entry.object {
-                EntryObject::Blob(blob) => {
-                    content.extend(
-                        format!("{} {}\0", EntryType::Regular.to_string(), name).as_bytes(),
-                    );
-                    content.extend(blob.hash());
-                }
-                EntryObject::Tree(tree) => {
-                    content
-                        .extend(format!("{} {}\0", EntryType::Tree.to_string(), name).as_bytes());
-                    content.extend(tree.hash());
-                }
-            }
-        }
-
+        let content = serialize_entries(&self.entries);
         let header = RGitObjectHeader::new(self.object_type(), content.len());
         header.serialize(writer)?;
         writer.write_all(&content)?;
         Ok(())
     }
 
+    /// Prints one line per entry, without resolving any of them.
     fn print(&self, writer: &mut dyn Write) -> Result<()> {
-        for (name, entry) in &self.entries {
-            let rgit_object_type = match &entry.object {
-                EntryObject::Blob(blob) => blob.object_type(),
-                EntryObject::Tree(tree) => tree.object_type(),
-            };
-            let rgit_object_hash = match &entry.object {
-                EntryObject::Blob(blob) => blob.hash(),
-                EntryObject::Tree(tree) => tree.hash(),
-            };
-
+        for entry in &self.entries {
             writer.write_all(
                 format!(
-                    "{} {} {}\t{}\n",
+                    "{:0>6} {} {}\t{}\n",
                     entry.entry_type.to_string(),
-                    rgit_object_type,
-                    hex::encode(rgit_object_hash),
-                    name
+                    entry.entry_type.object_type(),
+                    hex::encode(entry.hash),
+                    quote_path(&entry.name)
                 )
                 .as_bytes(),
             )?;
@@ -308,10 +496,46 @@ impl RGitObject for Tree {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::hash::ObjectFormat;
+    use crate::objects::{find_reachable_objects, Commit, LooseStore};
     use crate::utils::init_rgit_dir;
     use std::fs;
+    use std::os::unix::fs::symlink;
+    use std::process;
     use tempfile::tempdir;
 
+    fn entry<'a>(tree: &'a Tree, name: &[u8]) -> &'a Entry {
+        tree.entries
+            .iter()
+            .find(|entry| entry.name == name)
+            .unwrap()
+    }
+
+    /// Runs git on the repository `<dir>/git` with the work tree `<dir>/work`, and returns its
+    /// output.
+    fn git(dir: &Path, args: &[&str]) -> String {
+        let output = process::Command::new("git")
+            .arg("--git-dir")
+            .arg(dir.join("git"))
+            .arg("--work-tree")
+            .arg(dir.join("work"))
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        String::from_utf8(output.stdout).unwrap()
+    }
+
+    /// Fills `path` with a regular file, an executable, a symlink and a subdirectory.
+    fn write_modes(path: &Path) {
+        fs::write(path.join("file"), "file content").unwrap();
+        fs::write(path.join("script"), "#!/bin/sh\necho hello\n").unwrap();
+        fs::set_permissions(path.join("script"), fs::Permissions::from_mode(0o755)).unwrap();
+        symlink("dir/subfile", path.join("link")).unwrap();
+        fs::create_dir(path.join("dir")).unwrap();
+        fs::write(path.join("dir/subfile"), "subfile content").unwrap();
+    }
+
     #[test]
     fn test_tree_from_directory() {
         let dir = tempdir().unwrap();
@@ -326,7 +550,7 @@ mod tests {
         let subfile_path = subdir_path.join("subfile");
         fs::write(&subfile_path, "subfile content").unwrap();
 
-        let tree = Tree::from_directory(path).unwrap();
+        let tree = Tree::from_directory(path, ObjectFormat::Sha1).unwrap();
         assert_eq!(tree.entries.len(), 2);
     }
 
@@ -334,7 +558,7 @@ mod tests {
     fn test_tree_from_rgit_objects() {
         let dir = tempdir().unwrap();
         let path = dir.path();
-        let rgit_dir = init_rgit_dir(path).unwrap();
+        let db = ObjectDatabase::open(&init_rgit_dir(path).unwrap()).unwrap();
 
         let file_path = path.join("file");
         fs::write(&file_path, "file content").unwrap();
@@ -344,10 +568,10 @@ mod tests {
         let subfile_path = subdir_path.join("subfile");
         fs::write(&subfile_path, "subfile content").unwrap();
 
-        let tree = Tree::from_directory(path).unwrap();
-        tree.write_to_rgit_objects(rgit_dir.as_path()).unwrap();
+        let tree = Tree::from_directory(path, ObjectFormat::Sha1).unwrap();
+        tree.write_to_rgit_objects(&db).unwrap();
 
-        let tree = Tree::from_rgit_objects(rgit_dir.as_path(), tree.hash()).unwrap();
+        let tree = Tree::from_rgit_objects(&db, tree.hash()).unwrap();
         assert_eq!(tree.entries.len(), 2);
     }
 
@@ -365,8 +589,337 @@ mod tests {
         let subfile_path = subdir_path.join("subfile");
         fs::write(&subfile_path, "subfile content").unwrap();
 
-        let tree = Tree::from_directory(path).unwrap();
+        let tree = Tree::from_directory(path, ObjectFormat::Sha1).unwrap();
+        let mut buffer = Vec::new();
+        tree.print(&mut buffer).unwrap();
+    }
+
+    #[test]
+    fn test_tree_modes() {
+        // the tree of a work tree with every kind of entry must match `git write-tree`
+        let dir = tempdir().unwrap();
+        let work_dir = dir.path().join("work");
+        fs::create_dir(&work_dir).unwrap();
+        init_rgit_dir(&work_dir).unwrap();
+        write_modes(&work_dir);
+        for (name, mode) in [("group-exec", 0o654), ("other-exec", 0o645)] {
+            fs::write(work_dir.join(name), name).unwrap();
+            fs::set_permissions(work_dir.join(name), fs::Permissions::from_mode(mode)).unwrap();
+        }
+
+        git(dir.path(), &["init", "-q"]);
+        git(dir.path(), &["add", "-A", "--", ".", ":!.rgit"]);
+        let git_hash = git(dir.path(), &["write-tree"]);
+
+        let tree = Tree::from_directory(&work_dir, ObjectFormat::Sha1).unwrap();
+        assert_eq!(tree.hash().to_string(), git_hash.trim());
+        assert_eq!(entry(&tree, b"script").entry_type, EntryType::Executable);
+        assert_eq!(entry(&tree, b"link").entry_type, EntryType::Symlink);
+        assert_eq!(entry(&tree, b"group-exec").entry_type, EntryType::Regular);
+        assert_eq!(entry(&tree, b"other-exec").entry_type, EntryType::Regular);
+
+        let mut buffer = Vec::new();
+        tree.print(&mut buffer).unwrap();
+        let output = String::from_utf8(buffer).unwrap();
+        assert!(output.starts_with("040000 tree "));
+        assert!(output.contains("120000 blob "));
+        assert!(output.contains("100755 blob "));
+    }
+
+    #[test]
+    fn test_tree_modes_round_trip() {
+        let dir = tempdir().unwrap();
+        let path = dir.path();
+        let db = ObjectDatabase::open(&init_rgit_dir(path).unwrap()).unwrap();
+        write_modes(path);
+
+        let tree = Tree::from_directory(path, ObjectFormat::Sha1).unwrap();
+        tree.write_to_rgit_objects(&db).unwrap();
+        let tree = Tree::from_rgit_objects(&db, tree.hash()).unwrap();
+
+        let target = path.join("target");
+        fs::create_dir(&target).unwrap();
+        tree.write_to_directory(&target).unwrap();
+        let mode = fs::metadata(target.join("script"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o111, 0o111);
+        let mode = fs::metadata(target.join("file"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o111, 0);
+        assert_eq!(
+            fs::read_link(target.join("link")).unwrap(),
+            Path::new("dir/subfile")
+        );
+        assert_eq!(
+            Tree::from_directory(&target, ObjectFormat::Sha1)
+                .unwrap()
+                .hash(),
+            tree.hash()
+        );
+
+        // trees written with a zero-padded subtree mode are still readable
+        let content = b"040000 dir\0".to_vec();
+        let entries = parse_tree_entries(
+            &[content, entry(&tree, b"dir").hash.as_bytes().to_vec()].concat(),
+            ObjectFormat::Sha1,
+        )
+        .unwrap();
+        assert_eq!(entries[0].0, EntryType::Tree);
+    }
+
+    #[test]
+    fn test_tree_gitlink() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("work");
+        fs::create_dir(&path).unwrap();
+        let db = ObjectDatabase::open(&init_rgit_dir(&path).unwrap()).unwrap();
+        fs::write(path.join("file"), "file content").unwrap();
+
+        // a nested repository with a commit checked out
+        let nested = path.join("nested");
+        fs::create_dir(&nested).unwrap();
+        let nested_db = ObjectDatabase::open(&init_rgit_dir(&nested).unwrap()).unwrap();
+        fs::write(nested.join("subfile"), "subfile content").unwrap();
+        let nested_tree = Tree::from_directory(&nested, ObjectFormat::Sha1).unwrap();
+        nested_tree.write_to_rgit_objects(&nested_db).unwrap();
+        let commit = Commit::new(*nested_tree.hash(), Vec::new(), "commit".to_string()).unwrap();
+        let commit_hash = commit.write_to_rgit_objects(&nested_db).unwrap();
+        fs::write(nested.join(".rgit/HEAD"), hex::encode(commit_hash)).unwrap();
+
+        let tree = Tree::from_directory(&path, ObjectFormat::Sha1).unwrap();
+        assert_eq!(entry(&tree, b"nested").entry_type, EntryType::Gitlink);
+        assert_eq!(entry(&tree, b"nested").hash, commit_hash);
+        let mut buffer = Vec::new();
+        tree.print(&mut buffer).unwrap();
+        assert!(String::from_utf8(buffer)
+            .unwrap()
+            .contains(&format!("160000 commit {}\tnested\n", commit_hash)));
+
+        // git records the same tree for a submodule at that commit
+        git(dir.path(), &["init", "-q"]);
+        let blob = Blob::from_file(&path.join("file"), ObjectFormat::Sha1).unwrap();
+        git(
+            dir.path(),
+            &["hash-object", "-w", path.join("file").to_str().unwrap()],
+        );
+        for (mode, hash, name) in [
+            ("100644", blob.hash(), "file"),
+            ("160000", &commit_hash, "nested"),
+        ] {
+            let cacheinfo = format!("{},{},{}", mode, hash, name);
+            git(
+                dir.path(),
+                &["update-index", "--add", "--cacheinfo", &cacheinfo],
+            );
+        }
+        let git_hash = git(dir.path(), &["write-tree"]);
+        assert_eq!(tree.hash().to_string(), git_hash.trim());
+
+        // the nested commit is not copied nor followed, and checks out as an empty directory
+        tree.write_to_rgit_objects(&db).unwrap();
+        assert!(!db.contains(&commit_hash).unwrap());
+        let reachable = find_reachable_objects(&db, &[*tree.hash()]).unwrap();
+        assert_eq!(reachable.len(), 2);
+        let tree = Tree::from_rgit_objects(&db, tree.hash()).unwrap();
+        let target = dir.path().join("target");
+        fs::create_dir(&target).unwrap();
+        tree.write_to_directory(&target).unwrap();
+        assert!(target.join("nested").read_dir().unwrap().next().is_none());
+        assert_eq!(
+            fs::read_to_string(target.join("file")).unwrap(),
+            "file content"
+        );
+
+        // a nested repository without a commit can't be recorded
+        fs::remove_file(nested.join(".rgit/HEAD")).unwrap();
+        let result = Tree::from_directory(&path, ObjectFormat::Sha1);
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .contains("does not have a commit checked out"));
+    }
+
+    #[test]
+    fn test_tree_non_utf8_names() {
+        // Latin-1 and control characters in names are stored as is and quoted like git does
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("work");
+        fs::create_dir(&path).unwrap();
+        let db = ObjectDatabase::open(&init_rgit_dir(&path).unwrap()).unwrap();
+        let latin1 = OsStr::from_bytes(b"caf\xe9");
+        fs::write(path.join(latin1), "latin-1 content").unwrap();
+        fs::write(path.join("tab\tname"), "tab content").unwrap();
+        fs::create_dir(path.join("r\u{e9}sum\u{e9}")).unwrap();
+        fs::write(path.join("r\u{e9}sum\u{e9}").join(latin1), "nested").unwrap();
+
+        let tree = Tree::from_directory(&path, ObjectFormat::Sha1).unwrap();
+        assert_eq!(entry(&tree, b"caf\xe9").entry_type, EntryType::Regular);
+        tree.write_to_rgit_objects(&db).unwrap();
+        let mut buffer = Vec::new();
+        tree.print(&mut buffer).unwrap();
+
+        git(dir.path(), &["init", "-q"]);
+        git(dir.path(), &["add", "-A", "--", ".", ":!.rgit"]);
+        let git_hash = git(dir.path(), &["write-tree"]);
+        assert_eq!(tree.hash().to_string(), git_hash.trim());
+        assert_eq!(
+            String::from_utf8(buffer).unwrap(),
+            git(dir.path(), &["cat-file", "-p", git_hash.trim()])
+        );
+
+        let tree = Tree::from_rgit_objects(&db, tree.hash()).unwrap();
+        let target = dir.path().join("target");
+        fs::create_dir(&target).unwrap();
+        tree.write_to_directory(&target).unwrap();
+        assert_eq!(
+            fs::read_to_string(target.join("r\u{e9}sum\u{e9}").join(latin1)).unwrap(),
+            "nested"
+        );
+        assert_eq!(
+            fs::read_to_string(target.join("tab\tname")).unwrap(),
+            "tab content"
+        );
+    }
+
+    #[test]
+    fn test_tree_canonical_order() {
+        // subtrees sort as if their name ended with `/`, after `foo.c` and `foo-bar` but before
+        // `foo0`
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("work");
+        fs::create_dir(&path).unwrap();
+        let db = ObjectDatabase::open(&init_rgit_dir(&path).unwrap()).unwrap();
+        for name in ["foo.c", "foo-bar", "foo0", "bar.c", "baz"] {
+            fs::write(path.join(name), name).unwrap();
+        }
+        for name in ["foo", "bar", "baz.d"] {
+            fs::create_dir(path.join(name)).unwrap();
+            fs::write(path.join(name).join("file"), name).unwrap();
+        }
+
+        let tree = Tree::from_directory(&path, ObjectFormat::Sha1).unwrap();
+        let names = tree
+            .entries
+            .iter()
+            .map(|entry| String::from_utf8(entry.name.clone()).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            ["bar.c", "bar", "baz", "baz.d", "foo-bar", "foo.c", "foo", "foo0"]
+        );
+
+        git(dir.path(), &["init", "-q"]);
+        git(dir.path(), &["add", "-A", "--", ".", ":!.rgit"]);
+        let git_hash = git(dir.path(), &["write-tree"]);
+        assert_eq!(tree.hash().to_string(), git_hash.trim());
+
+        // a tree stored out of order keeps its order, and so its hash, when loaded
+        tree.write_to_rgit_objects(&db).unwrap();
+        let mut entries =
+            parse_tree_entries(&serialize_entries(&tree.entries), ObjectFormat::Sha1).unwrap();
+        entries.sort_by(|a, b| a.1.cmp(&b.1));
+        let content = entries
+            .iter()
+            .flat_map(|(entry_type, name, hash)| {
+                [
+                    format!("{} ", entry_type).into_bytes(),
+                    name.clone(),
+                    vec![0],
+                    hash.as_bytes().to_vec(),
+                ]
+                .concat()
+            })
+            .collect::<Vec<_>>();
+        let header = RGitObjectHeader::new(RGitObjectType::Tree, content.len());
+        let hash = header.hash(content.as_slice(), ObjectFormat::Sha1).unwrap();
+        assert_ne!(hash, *tree.hash());
+        let raw = RawObject {
+            object_type: RGitObjectType::Tree,
+            content,
+        };
+        db.write(&hash, &raw).unwrap();
+        assert_eq!(Tree::from_rgit_objects(&db, &hash).unwrap().hash(), &hash);
+    }
+
+    #[test]
+    fn test_tree_lazy_walk() {
+        let dir = tempdir().unwrap();
+        let path = dir.path();
+        let rgit_dir = init_rgit_dir(path).unwrap();
+        let db = ObjectDatabase::open(&rgit_dir).unwrap();
+        fs::write(path.join("file"), "file content").unwrap();
+        fs::create_dir_all(path.join("a")).unwrap();
+        fs::write(path.join("a/file"), "a content").unwrap();
+        fs::create_dir_all(path.join("b/c")).unwrap();
+        fs::write(path.join("b/c/file"), "c content").unwrap();
+        fs::write(path.join("b/c.txt"), "c.txt content").unwrap();
+        let tree = Tree::from_directory(path, ObjectFormat::Sha1).unwrap();
+        tree.write_to_rgit_objects(&db).unwrap();
+
+        // subtrees are only read when walked into, so a missing one goes unnoticed until then
+        let store = LooseStore::new(&rgit_dir.join("objects"));
+        fs::remove_file(store.object_path(&entry(&tree, b"a").hash)).unwrap();
+        let tree = Tree::from_rgit_objects(&db, tree.hash()).unwrap();
         let mut buffer = Vec::new();
         tree.print(&mut buffer).unwrap();
+        assert_eq!(String::from_utf8(buffer).unwrap().lines().count(), 3);
+
+        let walk = |paths: &[&[u8]]| {
+            let mut visited = Vec::new();
+            tree.walk(paths, &mut |entry| {
+                visited.push(String::from_utf8(entry.path().to_vec()).unwrap());
+                Ok(true)
+            })
+            .map(|_| visited)
+        };
+        assert_eq!(walk(&[b"b/c"]).unwrap(), ["b/c", "b/c/file"]);
+        assert_eq!(
+            walk(&[b"b", b"file"]).unwrap(),
+            ["b", "b/c.txt", "b/c", "b/c/file", "file"]
+        );
+        assert!(walk(&[b"b/c.txt/x"]).unwrap().is_empty());
+        assert!(walk(&[]).is_err());
+
+        // the visitor can skip subtrees
+        let mut visited = Vec::new();
+        tree.walk(&[], &mut |entry| {
+            visited.push(String::from_utf8(entry.path().to_vec()).unwrap());
+            Ok(entry.entry_type() != EntryType::Tree)
+        })
+        .unwrap();
+        assert_eq!(visited, ["a", "b", "file"]);
+    }
+
+    #[test]
+    fn test_tree_from_directory_threads() {
+        // the tree must not depend on how the work is spread across threads
+        let dir = tempdir().unwrap();
+        let path = dir.path();
+        init_rgit_dir(path).unwrap();
+        for i in 0..20 {
+            let subdir = path
+                .join(format!("dir{}", i % 4))
+                .join(format!("sub{}", i % 3));
+            fs::create_dir_all(&subdir).unwrap();
+            fs::write(subdir.join(format!("file{}", i)), "x".repeat(i * 10_000)).unwrap();
+            fs::write(path.join(format!("file{}", i)), i.to_string()).unwrap();
+        }
+
+        let serial = Tree::from_directory_with_threads(path, ObjectFormat::Sha1, 1).unwrap();
+        for threads in [0, 2, 8] {
+            let parallel =
+                Tree::from_directory_with_threads(path, ObjectFormat::Sha1, threads).unwrap();
+            assert_eq!(parallel.hash(), serial.hash());
+        }
+        assert_eq!(
+            Tree::from_directory(path, ObjectFormat::Sha1)
+                .unwrap()
+                .hash(),
+            serial.hash()
+        );
     }
 }
@@ -0,0 +1,193 @@
+use crate::hash::ObjectId;
+use crate::objects::{Commit, EntryType, ObjectDatabase, RGitObjectType, Tag, Tree};
+use anyhow::Result;
+use std::collections::HashSet;
+
+#[derive(Debug, PartialEq)]
+pub struct ReachableObject {
+    pub hash: ObjectId,
+    pub object_type: RGitObjectType,
+    /// Path the object was first reached through, empty for commits and root trees
+    pub path: Vec<u8>,
+}
+
+/// Collects every object reachable from `roots` by following tag targets, commit parents, commit
+/// trees and tree entries. Each object is reported once, before any of the objects it refers to.
+pub fn find_reachable_objects(
+    db: &ObjectDatabase,
+    roots: &[ObjectId],
+) -> Result<Vec<ReachableObject>> {
+    let mut seen = HashSet::new();
+    let mut objects = Vec::new();
+    let mut stack = Vec::new();
+
+    for root in roots.iter().rev() {
+        let header = db.read_header(root)?;
+        stack.push((*root, header.object_type, Vec::new()));
+    }
+
+    while let Some((hash, object_type, path)) = stack.pop() {
+        if !seen.insert(hash) {
+            continue;
+        }
+        objects.push(ReachableObject {
+            hash,
+            object_type,
+            path,
+        });
+
+        match object_type {
+            RGitObjectType::Commit => {
+                let commit = Commit::from_rgit_objects(db, &hash)?;
+                for parent in commit.parents().iter().rev() {
+                    stack.push((*parent, RGitObjectType::Commit, Vec::new()));
+                }
+                stack.push((*commit.tree(), RGitObjectType::Tree, Vec::new()));
+            }
+            RGitObjectType::Tree => {
+                Tree::from_rgit_objects(db, &hash)?.walk(&[], &mut |entry| {
+                    // the commits of nested repositories are not in the database
+                    if entry.entry_type() == EntryType::Gitlink || !seen.insert(*entry.hash()) {
+                        return Ok(false);
+                    }
+                    objects.push(ReachableObject {
+                        hash: *entry.hash(),
+                        object_type: entry.entry_type().object_type(),
+                        path: entry.path().to_vec(),
+                    });
+                    Ok(true)
+                })?;
+            }
+            RGitObjectType::Blob => {}
+            RGitObjectType::Tag => {
+                let tag = Tag::from_rgit_objects(db, &hash)?;
+                stack.push((*tag.object(), tag.target_type(), Vec::new()));
+            }
+        }
+    }
+
+    Ok(objects)
+}
+
+/// Returns true when the commit `ancestor` is `commit` or can be reached from it through parents.
+pub fn is_ancestor(db: &ObjectDatabase, ancestor: &ObjectId, commit: &ObjectId) -> Result<bool> {
+    let mut seen = HashSet::new();
+    let mut stack = vec![*commit];
+    while let Some(hash) = stack.pop() {
+        if hash == *ancestor {
+            return Ok(true);
+        }
+        if seen.insert(hash) {
+            stack.extend_from_slice(Commit::from_rgit_objects(db, &hash)?.parents());
+        }
+    }
+    Ok(false)
+}
+
+/// Returns the best common ancestors of the commits `a` and `b`: the commits reachable from both
+/// that are not an ancestor of another one of them. Most histories have a single one, criss-cross
+/// merges can have several.
+pub fn merge_bases(db: &ObjectDatabase, a: &ObjectId, b: &ObjectId) -> Result<Vec<ObjectId>> {
+    let mut from_a = HashSet::new();
+    let mut stack = vec![*a];
+    while let Some(hash) = stack.pop() {
+        if from_a.insert(hash) {
+            stack.extend_from_slice(Commit::from_rgit_objects(db, &hash)?.parents());
+        }
+    }
+
+    // the walk from b stops at the first common commits of each line of history
+    let mut common = Vec::new();
+    let mut seen = HashSet::new();
+    let mut stack = vec![*b];
+    while let Some(hash) = stack.pop() {
+        if !seen.insert(hash) {
+            continue;
+        }
+        if from_a.contains(&hash) {
+            common.push(hash);
+        } else {
+            stack.extend_from_slice(Commit::from_rgit_objects(db, &hash)?.parents());
+        }
+    }
+
+    let mut bases = Vec::new();
+    for candidate in common.iter() {
+        let mut is_best = true;
+        for other in common.iter().filter(|other| *other != candidate) {
+            if is_ancestor(db, candidate, other)? {
+                is_best = false;
+                break;
+            }
+        }
+        if is_best {
+            bases.push(*candidate);
+        }
+    }
+    bases.sort();
+    Ok(bases)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::ObjectFormat;
+    use crate::objects::{Blob, Tree};
+    use crate::utils::init_rgit_dir;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_find_reachable_objects() {
+        let dir = tempdir().unwrap();
+        let path = dir.path();
+        let db = ObjectDatabase::open(&init_rgit_dir(path).unwrap()).unwrap();
+
+        fs::write(path.join("file"), "file content").unwrap();
+        fs::create_dir(path.join("dir")).unwrap();
+        fs::write(path.join("dir/subfile"), "subfile content").unwrap();
+        let tree = Tree::from_directory(path, ObjectFormat::Sha1).unwrap();
+        tree.write_to_rgit_objects(&db).unwrap();
+        let first = Commit::new(*tree.hash(), Vec::new(), "first".to_string()).unwrap();
+        let first = first.write_to_rgit_objects(&db).unwrap();
+
+        // an identical file only shows up once
+        fs::write(path.join("dir/file"), "file content").unwrap();
+        let tree = Tree::from_directory(path, ObjectFormat::Sha1).unwrap();
+        tree.write_to_rgit_objects(&db).unwrap();
+        let second = Commit::new(*tree.hash(), vec![first], "second".to_string()).unwrap();
+        let second = second.write_to_rgit_objects(&db).unwrap();
+
+        let objects = find_reachable_objects(&db, &[second]).unwrap();
+        // 2 commits, 2 root trees, 2 dir trees, 2 blobs
+        assert_eq!(objects.len(), 8);
+        assert_eq!(objects[0].hash, second);
+        assert_eq!(objects[0].object_type, RGitObjectType::Commit);
+
+        let subfile = Blob::from_file(&path.join("dir/subfile"), ObjectFormat::Sha1).unwrap();
+        let subfile = objects
+            .iter()
+            .find(|object| object.hash == *subfile.hash())
+            .unwrap();
+        assert_eq!(subfile.object_type, RGitObjectType::Blob);
+        assert_eq!(subfile.path, b"dir/subfile");
+
+        let objects = find_reachable_objects(&db, &[first]).unwrap();
+        assert_eq!(objects.len(), 5);
+
+        assert!(is_ancestor(&db, &first, &second).unwrap());
+        assert!(is_ancestor(&db, &second, &second).unwrap());
+        assert!(!is_ancestor(&db, &second, &first).unwrap());
+
+        // two branches forked from second, and a merge of both
+        let left = Commit::new(*tree.hash(), vec![second], "left".to_string()).unwrap();
+        let left = left.write_to_rgit_objects(&db).unwrap();
+        let right = Commit::new(*tree.hash(), vec![second], "right".to_string()).unwrap();
+        let right = right.write_to_rgit_objects(&db).unwrap();
+        let merge = Commit::new(*tree.hash(), vec![left, right], "merge".to_string()).unwrap();
+        let merge = merge.write_to_rgit_objects(&db).unwrap();
+        assert_eq!(merge_bases(&db, &left, &right).unwrap(), vec![second]);
+        assert_eq!(merge_bases(&db, &merge, &right).unwrap(), vec![right]);
+        assert_eq!(merge_bases(&db, &first, &merge).unwrap(), vec![first]);
+    }
+}
@@ -0,0 +1,138 @@
+use super::remove_empty_dirs;
+use crate::error::RGitError;
+use anyhow::Result;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// An exclusive lock on a file, held through the `<path>.lock` file that receives its new
+/// content. The content replaces the file on [`LockFile::commit`]; dropping the lock without
+/// committing it leaves the file untouched.
+#[derive(Debug)]
+pub struct LockFile {
+    path: PathBuf,
+    lock_path: PathBuf,
+    file: Option<File>,
+    /// The deepest directory that existed before the lock was taken, the ones below it were
+    /// created for the lock and are removed with it when left empty
+    existing_dir: PathBuf,
+}
+
+impl LockFile {
+    /// Takes the lock on `path` by creating `<path>.lock`, which fails when another process holds
+    /// it.
+    pub fn acquire(path: &Path) -> Result<Self> {
+        let mut lock_path = path.as_os_str().to_owned();
+        lock_path.push(".lock");
+        let lock_path = PathBuf::from(lock_path);
+        let parent = lock_path.parent().unwrap();
+        let existing_dir = parent
+            .ancestors()
+            .find(|dir| dir.is_dir())
+            .unwrap_or(parent)
+            .to_path_buf();
+        fs::create_dir_all(parent)?;
+
+        let file = match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&lock_path)
+        {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                return Err(RGitError::new(
+                    format!(
+                        "fatal: Unable to create '{}': File exists.\n\n\
+                         Another rgit process seems to be running in this repository.\n\
+                         If it still fails, a rgit process may have crashed in this\n\
+                         repository earlier: remove the file manually to continue.",
+                        lock_path.display()
+                    ),
+                    128,
+                ));
+            }
+            Err(err) => return Err(RGitError::from(err).into()),
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            lock_path,
+            file: Some(file),
+            existing_dir,
+        })
+    }
+
+    /// Writes `content` to the lock file, to become the content of the locked file.
+    pub fn write(&mut self, content: &[u8]) -> Result<()> {
+        self.file.as_mut().unwrap().write_all(content)?;
+        Ok(())
+    }
+
+    /// Flushes the lock file to disk and renames it over the locked file, releasing the lock.
+    pub fn commit(mut self) -> Result<()> {
+        self.file.as_ref().unwrap().sync_all()?;
+        fs::rename(&self.lock_path, &self.path)?;
+        self.file = None;
+        Ok(())
+    }
+
+    /// Deletes the locked file and releases the lock.
+    pub fn delete(self) -> Result<()> {
+        match fs::remove_file(&self.path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Removes the lock file, unless it has been renamed over the locked file, along with the
+    /// directories created for it that are left empty.
+    fn release(&mut self) {
+        if self.file.take().is_some() {
+            let _ = fs::remove_file(&self.lock_path);
+            let _ = remove_empty_dirs(&self.lock_path, &self.existing_dir);
+        }
+    }
+}
+
+impl Drop for LockFile {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_lock_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("refs/heads/main");
+        let lock_path = dir.path().join("refs/heads/main.lock");
+
+        let mut lock = LockFile::acquire(&path).unwrap();
+        assert!(lock_path.is_file());
+        let err = LockFile::acquire(&path).unwrap_err();
+        assert!(err.to_string().contains("File exists"));
+        lock.write(b"content\n").unwrap();
+        lock.commit().unwrap();
+        assert!(!lock_path.exists());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "content\n");
+
+        // dropping the lock leaves the file as it was
+        let mut lock = LockFile::acquire(&path).unwrap();
+        lock.write(b"discarded\n").unwrap();
+        drop(lock);
+        assert!(!lock_path.exists());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "content\n");
+
+        LockFile::acquire(&path).unwrap().delete().unwrap();
+        assert!(!path.exists());
+        assert!(!lock_path.exists());
+
+        // the directories created for a lock go away with it
+        drop(LockFile::acquire(&dir.path().join("refs/heads/a/b/c")).unwrap());
+        assert!(!dir.path().join("refs/heads/a").exists());
+        assert!(dir.path().join("refs/heads").is_dir());
+    }
+}
@@ -0,0 +1,461 @@
+mod lock;
+mod packed;
+mod transaction;
+
+use crate::config::Config;
+use crate::hash::ObjectId;
+use crate::objects::Signature;
+use anyhow::Result;
+use packed::find_packed_ref;
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+pub use lock::LockFile;
+pub use packed::{pack_refs, read_packed_refs, PackedRef};
+pub use transaction::RefTransaction;
+
+/// The branch `HEAD` points to in a new repository.
+pub const DEFAULT_BRANCH: &str = "main";
+
+/// Symbolic refs pointing to symbolic refs are followed this many times at most.
+const MAX_SYMREF_DEPTH: usize = 5;
+
+/// The content of a ref: an object ID, or the name of another ref for a symbolic ref such as a
+/// `HEAD` that is on a branch.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RefTarget {
+    Direct(ObjectId),
+    Symbolic(String),
+}
+
+fn parse_ref(content: &str) -> Result<RefTarget> {
+    match content.strip_prefix("ref:") {
+        Some(target) => Ok(RefTarget::Symbolic(target.trim().to_string())),
+        None => Ok(RefTarget::Direct(ObjectId::from_hex(content.trim())?)),
+    }
+}
+
+/// Reads the ref `name`, such as `HEAD` or `refs/heads/main`, without following symbolic refs. A
+/// ref without a file of its own is looked up in `packed-refs`.
+pub fn read_ref_target(rgit_dir: &Path, name: &str) -> Result<Option<RefTarget>> {
+    let path = rgit_dir.join(name);
+    if path.is_file() {
+        match fs::read_to_string(path) {
+            Ok(content) => return Ok(Some(parse_ref(&content)?)),
+            // deleted meanwhile, possibly after being packed
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+    if !name.starts_with("refs/") {
+        return Ok(None);
+    }
+    Ok(find_packed_ref(&read_packed_refs(rgit_dir)?, name)
+        .map(|packed| RefTarget::Direct(packed.hash)))
+}
+
+/// Returns the object the ref `name` points to, following symbolic refs. `None` when the ref, or
+/// the branch a symbolic ref points to, does not exist yet.
+pub fn read_ref(rgit_dir: &Path, name: &str) -> Result<Option<ObjectId>> {
+    let mut name = name.to_string();
+    for _ in 0..=MAX_SYMREF_DEPTH {
+        match read_ref_target(rgit_dir, &name)? {
+            Some(RefTarget::Direct(hash)) => return Ok(Some(hash)),
+            Some(RefTarget::Symbolic(target)) => name = target,
+            None => return Ok(None),
+        }
+    }
+    Err(anyhow::anyhow!(
+        "Too many levels of symbolic refs: {}",
+        name
+    ))
+}
+
+/// Points the ref `name` to `hash`, replacing its content without recording it in any reflog.
+pub fn write_ref(rgit_dir: &Path, name: &str, hash: &ObjectId) -> Result<()> {
+    let mut transaction = RefTransaction::new(rgit_dir);
+    transaction.write(name, &RefTarget::Direct(*hash), None);
+    transaction.commit()
+}
+
+/// Makes `name` a symbolic ref to the ref `target`.
+pub fn write_symbolic_ref(rgit_dir: &Path, name: &str, target: &str) -> Result<()> {
+    let mut transaction = RefTransaction::new(rgit_dir);
+    transaction.write(name, &RefTarget::Symbolic(target.to_string()), None);
+    transaction.commit()
+}
+
+/// Removes the directories `path` leaves empty, up to but excluding `top`.
+fn remove_empty_dirs(path: &Path, top: &Path) -> Result<()> {
+    let mut dir = path.parent();
+    while let Some(parent) = dir.filter(|dir| dir.starts_with(top) && *dir != top) {
+        match fs::read_dir(parent).map(|mut entries| entries.next().is_some()) {
+            Ok(true) => break,
+            Ok(false) => fs::remove_dir(parent)?,
+            // already removed along with the lock that created it
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err.into()),
+        }
+        dir = parent.parent();
+    }
+    Ok(())
+}
+
+/// Returns `name` followed by the refs its chain of symbolic refs goes through, the last one being
+/// the ref that holds an object ID, or does not exist yet.
+fn symref_chain(rgit_dir: &Path, name: &str) -> Result<Vec<String>> {
+    let mut names = vec![name.to_string()];
+    while let Some(RefTarget::Symbolic(target)) = read_ref_target(rgit_dir, names.last().unwrap())?
+    {
+        if names.len() > MAX_SYMREF_DEPTH {
+            return Err(anyhow::anyhow!(
+                "Too many levels of symbolic refs: {}",
+                name
+            ));
+        }
+        names.push(target);
+    }
+    Ok(names)
+}
+
+/// Deletes the ref `name` and its reflog, along with the directories under `refs/` and `logs/`
+/// they leave empty.
+pub fn delete_ref(rgit_dir: &Path, name: &str) -> Result<()> {
+    let mut transaction = RefTransaction::new(rgit_dir);
+    transaction.no_deref().delete(name, None);
+    transaction.commit()
+}
+
+/// Points the ref `name` to `hash`, following symbolic refs, and records the change with
+/// `message` in the reflogs of the ref that is written and of the symbolic refs leading to it.
+pub fn update_ref(rgit_dir: &Path, name: &str, hash: &ObjectId, message: &str) -> Result<()> {
+    let mut transaction = RefTransaction::new(rgit_dir);
+    transaction.update(name, hash, None, message);
+    transaction.commit()
+}
+
+/// Replaces the content of the ref `name` itself, without following it, and records the change of
+/// the object it resolves to with `message` in its reflog. This is how `HEAD` moves to another
+/// branch or gets detached.
+pub fn replace_ref(rgit_dir: &Path, name: &str, target: &RefTarget, message: &str) -> Result<()> {
+    let mut transaction = RefTransaction::new(rgit_dir);
+    transaction.replace(name, target, None, message);
+    transaction.commit()
+}
+
+/// Renames the ref `old` to `new` along with its reflog, where the rename is recorded with
+/// `message`.
+pub fn rename_ref(rgit_dir: &Path, old: &str, new: &str, message: &str) -> Result<()> {
+    let hash = read_ref(rgit_dir, old)?
+        .ok_or_else(|| anyhow::anyhow!("Cannot rename {}, it does not exist", old))?;
+    // the reflog goes away with the old ref, its entries are carried over to the new one
+    let old_log = reflog_path(rgit_dir, old);
+    let log = old_log.is_file().then(|| fs::read(&old_log)).transpose()?;
+
+    // the old ref is deleted first so that `a` can be renamed to `a/b`, and comes back when the
+    // new one cannot be written
+    let null = ObjectId::null(hash.format());
+    let mut transaction = RefTransaction::new(rgit_dir);
+    transaction.no_deref().delete(old, Some(&hash));
+    transaction.commit()?;
+    let mut transaction = RefTransaction::new(rgit_dir);
+    transaction
+        .no_deref()
+        .write(new, &RefTarget::Direct(hash), Some(&null));
+    if let Err(err) = transaction.commit() {
+        let mut transaction = RefTransaction::new(rgit_dir);
+        transaction
+            .no_deref()
+            .write(old, &RefTarget::Direct(hash), Some(&null));
+        transaction.commit()?;
+        if let Some(log) = log {
+            fs::create_dir_all(old_log.parent().unwrap())?;
+            fs::write(old_log, log)?;
+        }
+        return Err(err);
+    }
+
+    if let Some(log) = log {
+        let new_log = reflog_path(rgit_dir, new);
+        fs::create_dir_all(new_log.parent().unwrap())?;
+        fs::write(new_log, log)?;
+    }
+    append_reflog(rgit_dir, new, Some(hash), Some(hash), message)
+}
+
+/// A change of a ref, as recorded in its reflog.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReflogEntry {
+    /// The object the ref pointed to before, null when it did not exist
+    pub old: ObjectId,
+    pub new: ObjectId,
+    pub signature: Signature,
+    /// Why the ref changed, such as `commit: <subject>` or `checkout: moving from main to topic`
+    pub message: String,
+}
+
+impl ReflogEntry {
+    fn parse(line: &str) -> Result<Self> {
+        let invalid = || anyhow::anyhow!("Invalid reflog entry: {:?}", line);
+        let (change, message) = line.split_once('\t').unwrap_or((line, ""));
+        let (old, rest) = change.split_once(' ').ok_or_else(invalid)?;
+        let (new, signature) = rest.split_once(' ').ok_or_else(invalid)?;
+        Ok(Self {
+            old: ObjectId::from_hex(old)?,
+            new: ObjectId::from_hex(new)?,
+            signature: Signature::parse(signature)?,
+            message: message.to_string(),
+        })
+    }
+}
+
+/// Only `HEAD` and branches have a reflog, as with git's default `core.logAllRefUpdates` in a
+/// repository with a work tree.
+fn has_reflog(name: &str) -> bool {
+    name == "HEAD" || name.starts_with("refs/heads/")
+}
+
+fn reflog_path(rgit_dir: &Path, name: &str) -> PathBuf {
+    rgit_dir.join("logs").join(name)
+}
+
+/// Appends the change of the ref `name` from `old` to `new` to its reflog, `None` standing for a
+/// ref that does not exist.
+fn append_reflog(
+    rgit_dir: &Path,
+    name: &str,
+    old: Option<ObjectId>,
+    new: Option<ObjectId>,
+    message: &str,
+) -> Result<()> {
+    let Some(format) = new.or(old).map(|hash| hash.format()) else {
+        return Ok(());
+    };
+    if !has_reflog(name) {
+        return Ok(());
+    }
+
+    // the reflog must not stop the ref from moving, unlike commits and tags that record who made
+    // them
+    let (user_name, user_email) = Config::load(rgit_dir)?
+        .identity()
+        .unwrap_or_else(|_| ("unknown".to_string(), "unknown".to_string()));
+    let line = format!(
+        "{} {} {}\t{}\n",
+        old.unwrap_or(ObjectId::null(format)),
+        new.unwrap_or(ObjectId::null(format)),
+        Signature::now(&user_name, &user_email)?,
+        message.split_whitespace().collect::<Vec<_>>().join(" ")
+    );
+
+    let path = reflog_path(rgit_dir, name);
+    fs::create_dir_all(path.parent().unwrap())?;
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(line.as_bytes())?;
+    Ok(())
+}
+
+/// Returns the reflog of the ref `name`, oldest change first. Refs without a reflog have an empty
+/// one.
+pub fn read_reflog(rgit_dir: &Path, name: &str) -> Result<Vec<ReflogEntry>> {
+    let path = reflog_path(rgit_dir, name);
+    if !path.is_file() {
+        return Ok(Vec::new());
+    }
+    fs::read_to_string(path)?
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(ReflogEntry::parse)
+        .collect()
+}
+
+fn collect_reflog_ids(dir: &Path, ids: &mut Vec<ObjectId>) -> Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            collect_reflog_ids(&entry.path(), ids)?;
+            continue;
+        }
+        for line in fs::read_to_string(entry.path())?.lines() {
+            let entry = ReflogEntry::parse(line)?;
+            ids.extend(
+                [entry.old, entry.new]
+                    .into_iter()
+                    .filter(|id| !id.is_null()),
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Returns every object ID recorded in the reflogs. The objects they name are kept by `prune` and
+/// `repack` so that the previous positions of refs can be recovered.
+pub fn list_reflog_ids(rgit_dir: &Path) -> Result<Vec<ObjectId>> {
+    let mut ids = Vec::new();
+    let logs_dir = rgit_dir.join("logs");
+    if logs_dir.is_dir() {
+        collect_reflog_ids(&logs_dir, &mut ids)?;
+    }
+    ids.sort();
+    ids.dedup();
+    Ok(ids)
+}
+
+fn collect_loose_refs(dir: &Path, name: &str, refs: &mut Vec<(String, RefTarget)>) -> Result<()> {
+    let mut entries = fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let entry_name = format!("{}/{}", name, entry.file_name().to_string_lossy());
+        if entry_name.ends_with(".lock") {
+            // a ref being updated by another process
+            continue;
+        }
+        if entry.file_type()?.is_dir() {
+            collect_loose_refs(&entry.path(), &entry_name, refs)?;
+            continue;
+        }
+        match fs::read_to_string(entry.path()) {
+            Ok(content) => refs.push((entry_name, parse_ref(&content)?)),
+            // packed and deleted meanwhile
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Ok(())
+}
+
+/// Returns the refs under `refs/` that have a file of their own, as opposed to packed refs.
+fn list_loose_refs(rgit_dir: &Path) -> Result<Vec<(String, RefTarget)>> {
+    let mut refs = Vec::new();
+    let refs_dir = rgit_dir.join("refs");
+    if refs_dir.is_dir() {
+        collect_loose_refs(&refs_dir, "refs", &mut refs)?;
+    }
+    Ok(refs)
+}
+
+/// Returns `HEAD` (when it points to a commit) followed by every ref under `refs/`, loose or
+/// packed, sorted by name. Symbolic refs are listed with the object they resolve to.
+pub fn list_refs(rgit_dir: &Path) -> Result<Vec<(String, ObjectId)>> {
+    let mut refs = Vec::new();
+
+    if let Some(hash) = read_ref(rgit_dir, "HEAD")? {
+        refs.push(("HEAD".to_string(), hash));
+    }
+
+    // loose refs are listed first: a ref packed meanwhile is then found in packed-refs
+    let loose = list_loose_refs(rgit_dir)?;
+    let mut by_name = read_packed_refs(rgit_dir)?
+        .into_iter()
+        .map(|packed| (packed.name, packed.hash))
+        .collect::<BTreeMap<_, _>>();
+    for (name, target) in loose {
+        let hash = match target {
+            RefTarget::Direct(hash) => Some(hash),
+            RefTarget::Symbolic(_) => read_ref(rgit_dir, &name)?,
+        };
+        match hash {
+            Some(hash) => by_name.insert(name, hash),
+            None => by_name.remove(&name),
+        };
+    }
+    refs.extend(by_name);
+
+    Ok(refs)
+}
+
+/// Checks a full ref name such as `refs/tags/v1` against the rules of `git check-ref-format`.
+pub fn check_ref_name(name: &str) -> bool {
+    let valid_component = |component: &str| {
+        !component.is_empty() && !component.starts_with('.') && !component.ends_with(".lock")
+    };
+    name.split('/').all(valid_component)
+        && !name.ends_with('.')
+        && !name.contains("..")
+        && !name.contains("@{")
+        && name != "@"
+        && !name
+            .chars()
+            .any(|c| c.is_ascii_control() || " ~^:?*[\\".contains(c))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::init_rgit_dir;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_list_refs() {
+        let dir = tempdir().unwrap();
+        let rgit_dir = init_rgit_dir(dir.path()).unwrap();
+        assert!(list_refs(&rgit_dir).unwrap().is_empty());
+
+        let head = ObjectId::from_bytes(&[1u8; 20]).unwrap();
+        let tag = ObjectId::from_bytes(&[2u8; 20]).unwrap();
+        fs::write(rgit_dir.join("HEAD"), hex::encode(head)).unwrap();
+        fs::create_dir_all(rgit_dir.join("refs/tags")).unwrap();
+        fs::write(rgit_dir.join("refs/tags/v1"), hex::encode(tag) + "\n").unwrap();
+
+        let refs = list_refs(&rgit_dir).unwrap();
+        assert_eq!(
+            refs,
+            vec![
+                ("HEAD".to_string(), head),
+                ("refs/tags/v1".to_string(), tag)
+            ]
+        );
+
+        // a symbolic HEAD is listed with the commit of its branch, once the branch exists
+        write_symbolic_ref(&rgit_dir, "HEAD", "refs/heads/main").unwrap();
+        assert_eq!(read_ref(&rgit_dir, "HEAD").unwrap(), None);
+        write_ref(&rgit_dir, "refs/heads/main", &head).unwrap();
+        assert_eq!(
+            read_ref_target(&rgit_dir, "HEAD").unwrap(),
+            Some(RefTarget::Symbolic("refs/heads/main".to_string()))
+        );
+        assert_eq!(read_ref(&rgit_dir, "HEAD").unwrap(), Some(head));
+        let refs = list_refs(&rgit_dir).unwrap();
+        assert_eq!(refs[0], ("HEAD".to_string(), head));
+        assert_eq!(refs[1], ("refs/heads/main".to_string(), head));
+
+        delete_ref(&rgit_dir, "refs/heads/main").unwrap();
+        assert!(!rgit_dir.join("refs/heads").exists());
+        assert!(rgit_dir.join("refs").is_dir());
+        assert_eq!(list_refs(&rgit_dir).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_check_ref_name() {
+        for name in [
+            "refs/tags/v1",
+            "refs/tags/v1.0-rc1",
+            "refs/heads/feature/x",
+            "HEAD",
+        ] {
+            assert!(check_ref_name(name), "{}", name);
+        }
+        for name in [
+            "refs/tags/",
+            "refs//tags",
+            "refs/tags/.v1",
+            "refs/tags/v1.lock",
+            "refs/tags/v1.",
+            "refs/tags/v..1",
+            "refs/tags/v@{1}",
+            "refs/tags/v 1",
+            "refs/tags/v~1",
+            "refs/tags/v1^",
+            "refs/tags/v:1",
+            "refs/tags/v*",
+            "refs/tags/v\\1",
+            "refs/tags/v\t1",
+            "@",
+        ] {
+            assert!(!check_ref_name(name), "{}", name);
+        }
+    }
+}
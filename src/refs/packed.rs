@@ -0,0 +1,228 @@
+use super::lock::LockFile;
+use super::{list_loose_refs, read_ref_target, remove_empty_dirs, RefTarget};
+use crate::hash::ObjectId;
+use crate::objects::{ObjectDatabase, RGitObjectType};
+use crate::revision::peel_tags;
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// The file, in the `.rgit` directory, holding the refs that have no file of their own.
+pub(super) const PACKED_REFS: &str = "packed-refs";
+
+/// Every annotated tag is followed by the object it peels to, and the refs are sorted by name.
+const HEADER: &str = "# pack-refs with: peeled fully-peeled sorted \n";
+
+/// A ref stored in `.rgit/packed-refs`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PackedRef {
+    pub name: String,
+    pub hash: ObjectId,
+    /// The object an annotated tag peels to, from the `^<id>` line following the ref
+    pub peeled: Option<ObjectId>,
+}
+
+fn parse_packed_refs(content: &str) -> Result<Vec<PackedRef>> {
+    let mut refs: Vec<PackedRef> = Vec::new();
+    for line in content.lines() {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let invalid = || anyhow::anyhow!("Invalid packed-refs line: {:?}", line);
+        if let Some(peeled) = line.strip_prefix('^') {
+            refs.last_mut().ok_or_else(invalid)?.peeled = Some(ObjectId::from_hex(peeled)?);
+            continue;
+        }
+        let (hash, name) = line.split_once(' ').ok_or_else(invalid)?;
+        refs.push(PackedRef {
+            name: name.to_string(),
+            hash: ObjectId::from_hex(hash)?,
+            peeled: None,
+        });
+    }
+    // files written by older tools may not be sorted
+    refs.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(refs)
+}
+
+/// Returns the refs of `.rgit/packed-refs`, sorted by name. A ref that also has a file of its own
+/// is at the value of that file instead, see [`super::read_ref_target`].
+pub fn read_packed_refs(rgit_dir: &Path) -> Result<Vec<PackedRef>> {
+    match fs::read_to_string(rgit_dir.join(PACKED_REFS)) {
+        Ok(content) => parse_packed_refs(&content),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Looks up the ref `name` in `refs`, sorted by name.
+pub(super) fn find_packed_ref<'a>(refs: &'a [PackedRef], name: &str) -> Option<&'a PackedRef> {
+    refs.binary_search_by(|packed| packed.name.as_str().cmp(name))
+        .ok()
+        .map(|index| &refs[index])
+}
+
+/// Replaces the content of `packed-refs`, locked by `lock`, with `refs`.
+fn write_packed_refs(mut lock: LockFile, refs: &[PackedRef]) -> Result<()> {
+    let mut content = HEADER.to_string();
+    for packed in refs {
+        content.push_str(&format!("{} {}\n", packed.hash, packed.name));
+        if let Some(peeled) = packed.peeled {
+            content.push_str(&format!("^{}\n", peeled));
+        }
+    }
+    lock.write(content.as_bytes())?;
+    lock.commit()
+}
+
+/// Removes the refs `names` from `packed-refs`, locked by `lock`.
+pub(super) fn delete_packed_refs(rgit_dir: &Path, lock: LockFile, names: &[&str]) -> Result<()> {
+    let refs = read_packed_refs(rgit_dir)?;
+    let kept = refs
+        .iter()
+        .filter(|packed| !names.contains(&packed.name.as_str()))
+        .cloned()
+        .collect::<Vec<_>>();
+    if kept.len() == refs.len() {
+        // nothing to rewrite, the lock is released unchanged
+        return Ok(());
+    }
+    write_packed_refs(lock, &kept)
+}
+
+/// Moves the tags, or every ref under `refs/` when `all` is set, into `packed-refs`. Symbolic refs
+/// stay in their own file.
+///
+/// The loose files are deleted once `packed-refs` is written, each under its lock and only when
+/// it still holds the packed value; with `prune` unset they are all kept.
+pub fn pack_refs(rgit_dir: &Path, db: &ObjectDatabase, all: bool, prune: bool) -> Result<()> {
+    let lock = LockFile::acquire(&rgit_dir.join(PACKED_REFS))?;
+    let mut refs = read_packed_refs(rgit_dir)?
+        .into_iter()
+        .map(|packed| (packed.name.clone(), packed))
+        .collect::<BTreeMap<_, _>>();
+
+    let mut packed_loose = Vec::new();
+    for (name, target) in list_loose_refs(rgit_dir)? {
+        let RefTarget::Direct(hash) = target else {
+            continue;
+        };
+        if !all && !name.starts_with("refs/tags/") {
+            continue;
+        }
+        let is_tag =
+            db.contains(&hash)? && db.read_header(&hash)?.object_type == RGitObjectType::Tag;
+        let peeled = if is_tag {
+            Some(peel_tags(db, hash)?)
+        } else {
+            None
+        };
+        refs.insert(
+            name.clone(),
+            PackedRef {
+                name: name.clone(),
+                hash,
+                peeled,
+            },
+        );
+        packed_loose.push((name, hash));
+    }
+    write_packed_refs(lock, &refs.into_values().collect::<Vec<_>>())?;
+
+    if prune {
+        for (name, hash) in packed_loose {
+            let path = rgit_dir.join(&name);
+            // a ref that is being updated, or was updated since it was read, keeps its file
+            let Ok(lock) = LockFile::acquire(&path) else {
+                continue;
+            };
+            if read_ref_target(rgit_dir, &name)? == Some(RefTarget::Direct(hash)) {
+                lock.delete()?;
+                remove_empty_dirs(&path, &rgit_dir.join("refs"))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::objects::{Signature, Tag};
+    use crate::refs::{delete_ref, list_refs, read_ref, write_ref, RefTransaction};
+    use crate::Repository;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_pack_refs() {
+        let dir = tempdir().unwrap();
+        let repo = Repository::init(dir.path(), None).unwrap();
+        let (rgit_dir, db) = (repo.rgit_dir(), repo.db());
+        let commit = repo.commit("first").unwrap();
+        let other = repo.commit("second").unwrap();
+        let tag = Tag::new(
+            commit,
+            RGitObjectType::Commit,
+            "v2".to_string(),
+            Some(Signature::parse("A U Thor <author@example.com> 0 +0000").unwrap()),
+            "v2\n".to_string(),
+        )
+        .write_to_rgit_objects(db)
+        .unwrap();
+
+        write_ref(rgit_dir, "refs/heads/main", &commit).unwrap();
+        write_ref(rgit_dir, "refs/tags/v1", &commit).unwrap();
+        write_ref(rgit_dir, "refs/tags/v2", &tag).unwrap();
+        let refs = list_refs(rgit_dir).unwrap();
+
+        pack_refs(rgit_dir, db, false, true).unwrap();
+        assert_eq!(
+            fs::read_to_string(rgit_dir.join("packed-refs")).unwrap(),
+            format!(
+                "# pack-refs with: peeled fully-peeled sorted \n\
+                 {} refs/tags/v1\n\
+                 {} refs/tags/v2\n\
+                 ^{}\n",
+                commit, tag, commit
+            )
+        );
+        assert!(!rgit_dir.join("refs/tags").exists());
+        assert!(rgit_dir.join("refs/heads/main").is_file());
+        assert_eq!(list_refs(rgit_dir).unwrap(), refs);
+
+        pack_refs(rgit_dir, db, true, true).unwrap();
+        assert!(!rgit_dir.join("refs/heads").exists());
+        assert_eq!(list_refs(rgit_dir).unwrap(), refs);
+        assert_eq!(read_ref(rgit_dir, "HEAD").unwrap(), Some(commit));
+        let packed = read_packed_refs(rgit_dir).unwrap();
+        assert_eq!(packed.len(), 3);
+        assert_eq!(packed[2].peeled, Some(commit));
+
+        // a loose file takes precedence over the packed value
+        let mut transaction = RefTransaction::new(rgit_dir);
+        transaction.update("refs/tags/v1", &other, Some(&commit), "");
+        transaction.commit().unwrap();
+        assert_eq!(read_ref(rgit_dir, "refs/tags/v1").unwrap(), Some(other));
+
+        // deleting a ref removes it from both places
+        delete_ref(rgit_dir, "refs/tags/v1").unwrap();
+        assert_eq!(read_ref(rgit_dir, "refs/tags/v1").unwrap(), None);
+        assert_eq!(read_packed_refs(rgit_dir).unwrap().len(), 2);
+        let mut transaction = RefTransaction::new(rgit_dir);
+        transaction.delete("refs/heads/main", Some(&commit));
+        transaction.commit().unwrap();
+        assert_eq!(read_ref(rgit_dir, "HEAD").unwrap(), None);
+        assert_eq!(
+            list_refs(rgit_dir).unwrap(),
+            vec![("refs/tags/v2".to_string(), tag)]
+        );
+
+        // packed refs are in the way of refs below them
+        let mut transaction = RefTransaction::new(rgit_dir);
+        transaction.update("refs/tags/v2/x", &commit, None, "");
+        let err = transaction.commit().unwrap_err();
+        assert!(err.to_string().contains("'refs/tags/v2' exists"));
+    }
+}
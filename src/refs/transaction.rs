@@ -0,0 +1,449 @@
+use super::lock::LockFile;
+use super::packed::{
+    delete_packed_refs, find_packed_ref, read_packed_refs, PackedRef, PACKED_REFS,
+};
+use super::{
+    append_reflog, check_ref_name, read_ref, reflog_path, remove_empty_dirs, symref_chain,
+    RefTarget,
+};
+use crate::error::RGitError;
+use crate::hash::ObjectId;
+use anyhow::Result;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+enum Change {
+    Set(RefTarget),
+    Delete,
+    /// Only checks the old value
+    Verify,
+}
+
+#[derive(Debug)]
+struct RefUpdate {
+    name: String,
+    change: Change,
+    /// The object the ref must point to, null when it must not exist. Not checked when `None`.
+    old: Option<ObjectId>,
+    /// Whether a symbolic ref is followed to the ref it points to, which is the one changed
+    deref: bool,
+    /// The reflog message, `None` for a change that is not recorded
+    message: Option<String>,
+}
+
+/// A set of ref changes that are applied together, or not at all.
+///
+/// Committing the transaction takes the `<ref>.lock` file of every ref it changes, checks that
+/// each one still points to its expected old object, and only then moves them all. Two processes
+/// updating the same ref from the same old value can't both succeed: the second one either finds
+/// the ref locked or finds it at another object.
+#[derive(Debug)]
+pub struct RefTransaction {
+    rgit_dir: PathBuf,
+    updates: Vec<RefUpdate>,
+    deref: bool,
+}
+
+/// Removes the directory `dir` if it only holds empty directories, and returns whether it did.
+fn remove_empty_tree(dir: &Path) -> Result<bool> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() || !remove_empty_tree(&entry.path())? {
+            return Ok(false);
+        }
+    }
+    fs::remove_dir(dir)?;
+    Ok(true)
+}
+
+/// Checks that the ref `name` can be written: it must neither be a directory of other refs, nor
+/// be below an existing ref, loose or among the `packed` ones. Empty directories in the way, left
+/// by refs that were deleted, are removed.
+fn check_conflicts(rgit_dir: &Path, packed: &[PackedRef], name: &str) -> Result<()> {
+    let cannot_lock = |reason: String| {
+        RGitError::new(
+            format!("fatal: cannot lock ref '{}': {}", name, reason),
+            128,
+        )
+    };
+    if rgit_dir.join(name).is_dir() && !remove_empty_tree(&rgit_dir.join(name))? {
+        return Err(cannot_lock(format!(
+            "there is a non-empty directory '{}' blocking reference '{}'",
+            rgit_dir.join(name).display(),
+            name
+        )));
+    }
+    let below = format!("{}/", name);
+    let index = packed.partition_point(|packed| packed.name < below);
+    if let Some(packed) = packed
+        .get(index)
+        .filter(|packed| packed.name.starts_with(&below))
+    {
+        return Err(cannot_lock(format!(
+            "'{}' exists; cannot create '{}'",
+            packed.name, name
+        )));
+    }
+    let mut prefix = Path::new(name).parent();
+    while let Some(dir) = prefix.filter(|dir| !dir.as_os_str().is_empty()) {
+        let exists = rgit_dir.join(dir).is_file()
+            || find_packed_ref(packed, &dir.to_string_lossy()).is_some();
+        if exists {
+            return Err(cannot_lock(format!(
+                "'{}' exists; cannot create '{}'",
+                dir.display(),
+                name
+            )));
+        }
+        prefix = dir.parent();
+    }
+    Ok(())
+}
+
+impl RefTransaction {
+    pub fn new(rgit_dir: &Path) -> Self {
+        Self {
+            rgit_dir: rgit_dir.to_path_buf(),
+            updates: Vec::new(),
+            deref: true,
+        }
+    }
+
+    /// Makes [`RefTransaction::update`], [`RefTransaction::delete`] and
+    /// [`RefTransaction::verify`] act on symbolic refs themselves rather than on the refs they
+    /// point to.
+    pub fn no_deref(&mut self) -> &mut Self {
+        self.deref = false;
+        self
+    }
+
+    fn push(
+        &mut self,
+        name: &str,
+        change: Change,
+        old: Option<&ObjectId>,
+        deref: bool,
+        message: Option<&str>,
+    ) -> &mut Self {
+        self.updates.push(RefUpdate {
+            name: name.to_string(),
+            change,
+            old: old.copied(),
+            deref,
+            message: message.map(str::to_string),
+        });
+        self
+    }
+
+    /// Points the ref `name` to `new`, following symbolic refs, provided it points to `old`. A
+    /// null `old` requires the ref not to exist, `None` accepts any value.
+    ///
+    /// The change is recorded with `message` in the reflogs of the ref and of the symbolic refs
+    /// leading to it.
+    pub fn update(
+        &mut self,
+        name: &str,
+        new: &ObjectId,
+        old: Option<&ObjectId>,
+        message: &str,
+    ) -> &mut Self {
+        self.push(
+            name,
+            Change::Set(RefTarget::Direct(*new)),
+            old,
+            true,
+            Some(message),
+        )
+    }
+
+    /// Replaces the content of the ref `name` itself, without following it, provided it resolves
+    /// to `old`. This is how `HEAD` moves to another branch or gets detached.
+    ///
+    /// The change of the object `name` resolves to is recorded with `message` in its reflog.
+    pub fn replace(
+        &mut self,
+        name: &str,
+        new: &RefTarget,
+        old: Option<&ObjectId>,
+        message: &str,
+    ) -> &mut Self {
+        self.push(name, Change::Set(new.clone()), old, false, Some(message))
+    }
+
+    /// Writes the ref `name` like [`RefTransaction::replace`], without recording it in any reflog.
+    pub(super) fn write(
+        &mut self,
+        name: &str,
+        new: &RefTarget,
+        old: Option<&ObjectId>,
+    ) -> &mut Self {
+        self.push(name, Change::Set(new.clone()), old, false, None)
+    }
+
+    /// Deletes the ref `name` and its reflog, following symbolic refs, provided it points to `old`.
+    pub fn delete(&mut self, name: &str, old: Option<&ObjectId>) -> &mut Self {
+        self.push(name, Change::Delete, old, true, None)
+    }
+
+    /// Checks that the ref `name` points to `old` without changing it.
+    pub fn verify(&mut self, name: &str, old: Option<&ObjectId>) -> &mut Self {
+        self.push(name, Change::Verify, old, true, None)
+    }
+
+    /// Applies every change, or none of them when a ref is locked by another process or is not at
+    /// its expected old value.
+    pub fn commit(self) -> Result<()> {
+        let rgit_dir = &self.rgit_dir;
+
+        // the refs to lock: the one named, or the ref its chain of symbolic refs ends at
+        let mut updates = Vec::with_capacity(self.updates.len());
+        for update in self.updates {
+            if update.name != "HEAD" && !update.name.starts_with("refs/")
+                || !check_ref_name(&update.name)
+            {
+                return Err(RGitError::new(
+                    format!(
+                        "fatal: refusing to update ref with bad name '{}'",
+                        update.name
+                    ),
+                    128,
+                ));
+            }
+            let names = if update.deref && self.deref {
+                symref_chain(rgit_dir, &update.name)?
+            } else {
+                vec![update.name.clone()]
+            };
+            updates.push((update, names));
+        }
+        // always locking in the same order keeps two transactions from waiting for each other
+        updates.sort_by(|(_, a), (_, b)| a.last().cmp(&b.last()));
+        for pair in updates.windows(2) {
+            if pair[0].1.last() == pair[1].1.last() {
+                return Err(RGitError::new(
+                    format!(
+                        "fatal: multiple updates for ref '{}' not allowed",
+                        pair[0].1.last().unwrap()
+                    ),
+                    128,
+                ));
+            }
+        }
+
+        let packed = read_packed_refs(rgit_dir)?;
+        let mut locked = Vec::with_capacity(updates.len());
+        for (update, names) in updates {
+            let target = names.last().unwrap();
+            if let Change::Set(_) = update.change {
+                check_conflicts(rgit_dir, &packed, target)?;
+            }
+            let lock = LockFile::acquire(&rgit_dir.join(target))?;
+            let current = read_ref(rgit_dir, target)?;
+            let cannot_lock = |reason: String| {
+                RGitError::new(
+                    format!("fatal: cannot lock ref '{}': {}", update.name, reason),
+                    128,
+                )
+            };
+            match (update.old, current) {
+                (Some(old), Some(_)) if old.is_null() => {
+                    return Err(cannot_lock("reference already exists".to_string()));
+                }
+                (Some(old), Some(current)) if old != current => {
+                    return Err(cannot_lock(format!(
+                        "is at {} but expected {}",
+                        current, old
+                    )));
+                }
+                (Some(old), None) if !old.is_null() => {
+                    return Err(cannot_lock(format!(
+                        "unable to resolve reference '{}'",
+                        target
+                    )));
+                }
+                _ => {}
+            }
+            locked.push((update, names, lock, current));
+        }
+
+        // deleted refs must not survive in packed-refs, which is locked last and rewritten before
+        // their loose files are removed. It is locked even when missing, so that a concurrent
+        // pack-refs can't pack a ref being deleted, and read again under the lock.
+        let deleted = locked
+            .iter()
+            .filter(|(update, ..)| matches!(update.change, Change::Delete))
+            .map(|(_, names, ..)| names.last().unwrap().as_str())
+            .collect::<Vec<_>>();
+        let packed_lock = if !deleted.is_empty() {
+            Some(LockFile::acquire(&rgit_dir.join(PACKED_REFS))?)
+        } else {
+            None
+        };
+
+        // every check passed, the refs can be moved
+        if let Some(packed_lock) = packed_lock {
+            delete_packed_refs(rgit_dir, packed_lock, &deleted)?;
+        }
+        for (update, names, mut lock, current) in locked {
+            let target = names.last().unwrap();
+            match &update.change {
+                Change::Set(new) => {
+                    let content = match new {
+                        RefTarget::Direct(hash) => format!("{}\n", hash),
+                        RefTarget::Symbolic(name) => format!("ref: {}\n", name),
+                    };
+                    lock.write(content.as_bytes())?;
+                    lock.commit()?;
+                    // moving to a branch without any commit yet is not recorded, like in git
+                    if let (Some(message), Some(new)) =
+                        (&update.message, read_ref(rgit_dir, target)?)
+                    {
+                        for name in names.iter() {
+                            append_reflog(rgit_dir, name, current, Some(new), message)?;
+                        }
+                    }
+                }
+                Change::Delete => {
+                    let path = rgit_dir.join(target);
+                    lock.delete()?;
+                    remove_empty_dirs(&path, &rgit_dir.join("refs"))?;
+                    let log_path = reflog_path(rgit_dir, target);
+                    if log_path.is_file() {
+                        fs::remove_file(&log_path)?;
+                        remove_empty_dirs(&log_path, &rgit_dir.join("logs"))?;
+                    }
+                }
+                Change::Verify => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::refs::{read_ref_target, read_reflog, write_symbolic_ref};
+    use crate::utils::init_rgit_dir;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_transaction() {
+        let dir = tempdir().unwrap();
+        let rgit_dir = init_rgit_dir(dir.path()).unwrap();
+        let first = ObjectId::from_bytes(&[1u8; 20]).unwrap();
+        let second = ObjectId::from_bytes(&[2u8; 20]).unwrap();
+        let null = ObjectId::null(first.format());
+
+        // HEAD is followed to its branch, and both reflogs record the change
+        let mut transaction = RefTransaction::new(&rgit_dir);
+        transaction
+            .update("HEAD", &first, Some(&null), "first")
+            .update("refs/tags/v1", &first, None, "tag");
+        transaction.commit().unwrap();
+        assert_eq!(read_ref(&rgit_dir, "refs/heads/main").unwrap(), Some(first));
+        assert_eq!(read_ref(&rgit_dir, "refs/tags/v1").unwrap(), Some(first));
+        assert_eq!(read_reflog(&rgit_dir, "HEAD").unwrap().len(), 1);
+        assert_eq!(read_reflog(&rgit_dir, "refs/heads/main").unwrap().len(), 1);
+        assert!(read_reflog(&rgit_dir, "refs/tags/v1").unwrap().is_empty());
+
+        // a stale old value fails the whole transaction
+        let mut transaction = RefTransaction::new(&rgit_dir);
+        transaction
+            .update("refs/tags/v1", &second, None, "tag")
+            .update("HEAD", &second, Some(&null), "second");
+        let err = transaction.commit().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "fatal: cannot lock ref 'HEAD': reference already exists"
+        );
+        let mut transaction = RefTransaction::new(&rgit_dir);
+        transaction.update("HEAD", &second, Some(&second), "second");
+        let err = transaction.commit().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "fatal: cannot lock ref 'HEAD': is at {} but expected {}",
+                first, second
+            )
+        );
+        assert_eq!(read_ref(&rgit_dir, "refs/tags/v1").unwrap(), Some(first));
+        assert!(!rgit_dir.join("refs/tags/v1.lock").exists());
+        assert!(!rgit_dir.join("refs/heads/main.lock").exists());
+
+        // a ref locked by another process can't be updated
+        let lock = LockFile::acquire(&rgit_dir.join("refs/heads/main")).unwrap();
+        let mut transaction = RefTransaction::new(&rgit_dir);
+        transaction.update("HEAD", &second, Some(&first), "second");
+        assert!(transaction.commit().is_err());
+        drop(lock);
+        let mut transaction = RefTransaction::new(&rgit_dir);
+        transaction.update("HEAD", &second, Some(&first), "second");
+        transaction.commit().unwrap();
+        assert_eq!(read_ref(&rgit_dir, "HEAD").unwrap(), Some(second));
+
+        let mut transaction = RefTransaction::new(&rgit_dir);
+        transaction
+            .update("HEAD", &first, None, "first")
+            .verify("refs/heads/main", None);
+        let err = transaction.commit().unwrap_err();
+        assert!(err.to_string().contains("multiple updates"));
+        let mut transaction = RefTransaction::new(&rgit_dir);
+        transaction.update("refs/heads/main/x", &first, None, "x");
+        let err = transaction.commit().unwrap_err();
+        assert!(err.to_string().contains("'refs/heads/main' exists"));
+        let mut transaction = RefTransaction::new(&rgit_dir);
+        transaction.update("main", &first, None, "x");
+        assert!(transaction.commit().is_err());
+
+        // a failed update leaves no directory behind, and empty ones don't block a ref
+        let mut transaction = RefTransaction::new(&rgit_dir);
+        transaction.update("refs/heads/x/y", &first, Some(&second), "x");
+        assert!(transaction.commit().is_err());
+        assert!(!rgit_dir.join("refs/heads/x").exists());
+        fs::create_dir_all(rgit_dir.join("refs/heads/x/y")).unwrap();
+        let mut transaction = RefTransaction::new(&rgit_dir);
+        transaction.update("refs/heads/x", &first, Some(&null), "x");
+        transaction.commit().unwrap();
+        let mut transaction = RefTransaction::new(&rgit_dir);
+        transaction.delete("refs/heads/x", Some(&first));
+        transaction.commit().unwrap();
+
+        // without dereferencing, HEAD itself is detached
+        let mut transaction = RefTransaction::new(&rgit_dir);
+        transaction
+            .no_deref()
+            .update("HEAD", &first, Some(&second), "detach")
+            .verify("refs/heads/main", Some(&second));
+        transaction.commit().unwrap();
+        assert_eq!(
+            read_ref_target(&rgit_dir, "HEAD").unwrap(),
+            Some(RefTarget::Direct(first))
+        );
+        assert_eq!(
+            read_ref(&rgit_dir, "refs/heads/main").unwrap(),
+            Some(second)
+        );
+
+        write_symbolic_ref(&rgit_dir, "HEAD", "refs/heads/main").unwrap();
+
+        // deletions wait for packed-refs, even before it exists
+        let lock = LockFile::acquire(&rgit_dir.join(PACKED_REFS)).unwrap();
+        let mut transaction = RefTransaction::new(&rgit_dir);
+        transaction.delete("refs/tags/v1", None);
+        assert!(transaction.commit().is_err());
+        assert_eq!(read_ref(&rgit_dir, "refs/tags/v1").unwrap(), Some(first));
+        drop(lock);
+
+        let mut transaction = RefTransaction::new(&rgit_dir);
+        transaction
+            .delete("HEAD", Some(&second))
+            .delete("refs/tags/v1", None);
+        transaction.commit().unwrap();
+        assert_eq!(read_ref(&rgit_dir, "HEAD").unwrap(), None);
+        assert!(!rgit_dir.join("refs/heads").exists());
+        assert!(!rgit_dir.join("logs/refs").exists());
+        assert!(rgit_dir.join("logs/HEAD").is_file());
+    }
+}
@@ -0,0 +1,314 @@
+use crate::checkout::checkout_tree;
+use crate::config::Config;
+use crate::error::RGitError;
+use crate::hash::{ObjectFormat, ObjectId};
+use crate::ignore::is_ignored;
+use crate::objects::{Commit, ObjectDatabase, RGitObjectHeader, RGitObjectType, RawObject, Tree};
+use crate::refs::{
+    read_ref, read_ref_target, replace_ref, write_symbolic_ref, RefTarget, RefTransaction,
+    DEFAULT_BRANCH,
+};
+use crate::revision::resolve_revision;
+use crate::utils::{get_rgit_dir, init_rgit_dir};
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+
+/// A work tree together with its `.rgit` directory and object database.
+#[derive(Debug, Clone)]
+pub struct Repository {
+    work_dir: PathBuf,
+    rgit_dir: PathBuf,
+    db: ObjectDatabase,
+}
+
+impl Repository {
+    /// Creates a repository in `dir` using `format`, SHA-1 by default, or reopens the one already
+    /// there. The object format of an existing repository can't be changed.
+    pub fn init(dir: &Path, format: Option<ObjectFormat>) -> Result<Self> {
+        let exists = dir.join(".rgit").is_dir();
+        let rgit_dir = init_rgit_dir(dir)?;
+        let mut config = Config::load(&rgit_dir)?;
+        if exists {
+            if format.is_some_and(|format| format != config.object_format().unwrap_or(format)) {
+                return Err(RGitError::new(
+                    "fatal: attempt to reinitialize repository with different hash".to_string(),
+                    128,
+                ));
+            }
+        } else {
+            match format.unwrap_or(ObjectFormat::Sha1) {
+                ObjectFormat::Sha1 => config.set("core.repositoryformatversion", "0")?,
+                ObjectFormat::Sha256 => {
+                    config.set("core.repositoryformatversion", "1")?;
+                    config.set("extensions.objectformat", &ObjectFormat::Sha256.to_string())?;
+                }
+            }
+            config.save()?;
+        }
+        Self::open(dir)
+    }
+
+    /// Opens the repository whose work tree is `dir`.
+    pub fn open(dir: &Path) -> Result<Self> {
+        let rgit_dir = dir.join(".rgit");
+        if !rgit_dir.is_dir() {
+            return Err(RGitError::NotARepository {
+                path: Some(dir.to_path_buf()),
+            }
+            .into());
+        }
+        Ok(Self {
+            work_dir: dir.to_path_buf(),
+            db: ObjectDatabase::open(&rgit_dir)?,
+            rgit_dir,
+        })
+    }
+
+    /// Opens the repository containing `path`, looking in `path` and then in its parents.
+    pub fn discover(path: &Path) -> Result<Self> {
+        let rgit_dir = get_rgit_dir(path)?;
+        Self::open(rgit_dir.parent().unwrap())
+    }
+
+    pub fn work_dir(&self) -> &Path {
+        &self.work_dir
+    }
+
+    pub fn rgit_dir(&self) -> &Path {
+        &self.rgit_dir
+    }
+
+    pub fn db(&self) -> &ObjectDatabase {
+        &self.db
+    }
+
+    pub fn format(&self) -> ObjectFormat {
+        self.db.format()
+    }
+
+    pub fn config(&self) -> Result<Config> {
+        Config::load(&self.rgit_dir)
+    }
+
+    /// Resolves an object name, see [`resolve_revision`].
+    pub fn resolve(&self, name: &str) -> Result<ObjectId> {
+        resolve_revision(&self.db, &self.rgit_dir, name)
+    }
+
+    pub fn read_object(&self, hash: &ObjectId) -> Result<RawObject> {
+        RawObject::from_rgit_objects(&self.db, hash)
+    }
+
+    /// Writes an object of type `object_type` with the given content and returns its ID.
+    pub fn write_object(&self, object_type: RGitObjectType, content: &[u8]) -> Result<ObjectId> {
+        let hash =
+            RGitObjectHeader::new(object_type, content.len()).hash(content, self.format())?;
+        let object = RawObject {
+            object_type,
+            content: content.to_vec(),
+        };
+        self.db.write(&hash, &object)?;
+        Ok(hash)
+    }
+
+    /// Returns true when `path`, in the work tree, is ignored by a `.rgitignore`.
+    pub fn is_ignored(&self, path: &Path) -> Result<bool> {
+        Ok(is_ignored(&self.work_dir.join(path))?.is_ignored)
+    }
+
+    /// Builds the tree of the work tree without writing it, on `index.threads` threads.
+    pub fn build_tree(&self) -> Result<Tree> {
+        let threads = self.config()?.threads()?;
+        Tree::from_directory_with_threads(&self.work_dir, self.format(), threads)
+    }
+
+    /// Writes the tree of the work tree and every file in it, and returns the ID of the tree.
+    pub fn write_tree(&self) -> Result<ObjectId> {
+        let tree = self.build_tree()?;
+        tree.write_to_rgit_objects(&self.db)?;
+        Ok(*tree.hash())
+    }
+
+    /// The commit `HEAD` points to, `None` before the first commit on the current branch.
+    pub fn head(&self) -> Result<Option<ObjectId>> {
+        read_ref(&self.rgit_dir, "HEAD")
+    }
+
+    /// The full name of the branch `HEAD` is on, such as `refs/heads/main`, `None` when `HEAD` is
+    /// detached.
+    pub fn head_branch(&self) -> Result<Option<String>> {
+        match read_ref_target(&self.rgit_dir, "HEAD")? {
+            Some(RefTarget::Symbolic(name)) => Ok(Some(name)),
+            Some(RefTarget::Direct(_)) => Ok(None),
+            // repositories made before branches existed had no HEAD until their first commit
+            None => Ok(Some(format!("refs/heads/{}", DEFAULT_BRANCH))),
+        }
+    }
+
+    /// Points `HEAD` to a branch, given by its full name such as `refs/heads/main`, or detaches it
+    /// at a commit. The move is recorded in the reflog of `HEAD` with `message`.
+    pub fn set_head(&self, target: &RefTarget, message: &str) -> Result<()> {
+        replace_ref(&self.rgit_dir, "HEAD", target, message)
+    }
+
+    /// Updates the work tree from the commit `HEAD` points to to the commit `commit`, without
+    /// moving `HEAD`. Only the files that differ between the two commits are written or removed.
+    ///
+    /// Fails without changing anything when one of these files has local changes or an untracked
+    /// file is in the way, unless `force` is set: then every file of `HEAD` is reset to its
+    /// content in `commit`.
+    pub fn checkout(&self, commit: &ObjectId, force: bool) -> Result<()> {
+        let from = match self.head()? {
+            Some(head) => Some(Tree::from_rgit_objects(
+                &self.db,
+                Commit::from_rgit_objects(&self.db, &head)?.tree(),
+            )?),
+            None => None,
+        };
+        let to = Tree::from_rgit_objects(
+            &self.db,
+            Commit::from_rgit_objects(&self.db, commit)?.tree(),
+        )?;
+        checkout_tree(
+            &self.db,
+            &self.work_dir,
+            &self.build_tree()?,
+            from.as_ref(),
+            &to,
+            force,
+        )
+    }
+
+    /// Records the work tree in a new commit on top of `HEAD`, moves the current branch to it and
+    /// returns its ID. Fails when `HEAD` is locked or moves in the meantime, rather than dropping
+    /// the other commit.
+    pub fn commit(&self, message: &str) -> Result<ObjectId> {
+        let tree = self.write_tree()?;
+        let parents = self.head()?.into_iter().collect::<Vec<_>>();
+        let reason = if parents.is_empty() {
+            "commit (initial)"
+        } else {
+            "commit"
+        };
+        let commit = Commit::new(tree, parents.clone(), message.to_string())?;
+        let hash = commit.write_to_rgit_objects(&self.db)?;
+
+        if read_ref_target(&self.rgit_dir, "HEAD")?.is_none() {
+            let branch = format!("refs/heads/{}", DEFAULT_BRANCH);
+            write_symbolic_ref(&self.rgit_dir, "HEAD", &branch)?;
+        }
+        // a commit made by another process since the parents were read is not overwritten
+        let old = parents
+            .first()
+            .copied()
+            .unwrap_or_else(|| ObjectId::null(hash.format()));
+        let subject = message.lines().next().unwrap_or_default();
+        let mut transaction = RefTransaction::new(&self.rgit_dir);
+        transaction.update(
+            "HEAD",
+            &hash,
+            Some(&old),
+            &format!("{}: {}", reason, subject),
+        );
+        transaction.commit()?;
+        Ok(hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_repository() {
+        let dir = tempdir().unwrap();
+        assert!(Repository::open(dir.path()).is_err());
+        assert!(Repository::discover(dir.path()).is_err());
+
+        let repo = Repository::init(dir.path(), Some(ObjectFormat::Sha256)).unwrap();
+        assert_eq!(repo.format(), ObjectFormat::Sha256);
+        assert_eq!(repo.head().unwrap(), None);
+        let result = Repository::init(dir.path(), Some(ObjectFormat::Sha1));
+        assert!(result.unwrap_err().to_string().contains("different hash"));
+
+        fs::create_dir(dir.path().join("dir")).unwrap();
+        fs::write(dir.path().join("dir/file"), "file content").unwrap();
+        fs::write(dir.path().join(".rgitignore"), "*.log\n").unwrap();
+        fs::write(dir.path().join("debug.log"), "log").unwrap();
+        let repo = Repository::discover(&dir.path().join("dir")).unwrap();
+        assert_eq!(repo.work_dir(), dir.path());
+        assert!(repo.is_ignored(Path::new("debug.log")).unwrap());
+        assert!(!repo.is_ignored(Path::new("dir/file")).unwrap());
+
+        let blob = repo
+            .write_object(RGitObjectType::Blob, b"file content")
+            .unwrap();
+        assert_eq!(repo.read_object(&blob).unwrap().content, b"file content");
+
+        let first = repo.commit("first").unwrap();
+        assert_eq!(repo.head().unwrap(), Some(first));
+        assert_eq!(repo.resolve("HEAD").unwrap(), first);
+        fs::write(dir.path().join("dir/file"), "new content").unwrap();
+        let second = repo.commit("second").unwrap();
+        let commit = Commit::from_rgit_objects(repo.db(), &second).unwrap();
+        assert_eq!(commit.parents(), [first]);
+        assert_eq!(commit.commit_message, "second");
+        assert_eq!(commit.tree(), repo.build_tree().unwrap().hash());
+
+        // a branch locked by a concurrent update is left alone
+        let lock_path = repo.rgit_dir().join("refs/heads/main.lock");
+        fs::write(&lock_path, "").unwrap();
+        assert!(repo.commit("third").is_err());
+        assert_eq!(repo.head().unwrap(), Some(second));
+        fs::remove_file(&lock_path).unwrap();
+        repo.commit("third").unwrap();
+    }
+
+    #[test]
+    fn test_repository_errors() {
+        let dir = tempdir().unwrap();
+        let err = Repository::open(dir.path()).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(RGitError::NotARepository { path: Some(path) }) if path == dir.path()
+        ));
+        let err = Repository::discover(dir.path()).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(RGitError::NotARepository { path: None })
+        ));
+
+        let repo = Repository::init(dir.path(), None).unwrap();
+        let err = repo.resolve("nope").unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(RGitError::InvalidRef { name }) if name == "nope"
+        ));
+        assert_eq!(err.to_string(), "fatal: Not a valid object name nope");
+
+        let missing = RGitObjectHeader::new(RGitObjectType::Blob, 0)
+            .hash(&b""[..], repo.format())
+            .unwrap();
+        let err = repo.read_object(&missing).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(RGitError::ObjectNotFound { id }) if *id == missing
+        ));
+
+        let tree = repo
+            .write_object(RGitObjectType::Tree, b"not a tree")
+            .unwrap();
+        let err = Tree::from_rgit_objects(repo.db(), &tree).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref(),
+            Some(RGitError::CorruptObject { id, .. }) if *id == tree
+        ));
+        assert_eq!(
+            err.to_string(),
+            format!("fatal: object {} is corrupt: Invalid tree entry", tree)
+        );
+        assert_eq!(err.downcast_ref::<RGitError>().unwrap().exit_code(), 128);
+    }
+}
@@ -0,0 +1,654 @@
+use crate::error::RGitError;
+use crate::hash::{ObjectId, ObjectIdPrefix};
+use crate::objects::{merge_bases, read_tree_entries, Commit, ObjectDatabase, RGitObjectType, Tag};
+use crate::refs::{check_ref_name, list_refs, read_ref, read_ref_target, read_reflog, RefTarget};
+use anyhow::Result;
+use regex::Regex;
+use std::collections::{BinaryHeap, HashSet};
+use std::path::Path;
+
+/// The shortest abbreviated object name that is accepted.
+pub const MIN_ABBREV: usize = 4;
+
+/// The shortest abbreviated object name that is printed.
+pub const DEFAULT_ABBREV: usize = 7;
+
+fn not_a_valid_object_name(name: &str) -> anyhow::Error {
+    RGitError::InvalidRef {
+        name: name.to_string(),
+    }
+    .into()
+}
+
+/// Resolves a ref name the way git does, trying `<name>`, `refs/<name>`, `refs/tags/<name>` and
+/// `refs/heads/<name>` in turn, and returns the full name of the ref along with its object.
+pub fn resolve_ref(rgit_dir: &Path, name: &str) -> Result<Option<(String, ObjectId)>> {
+    for candidate in [
+        name.to_string(),
+        format!("refs/{}", name),
+        format!("refs/tags/{}", name),
+        format!("refs/heads/{}", name),
+    ] {
+        // only `HEAD` and refs are looked up, not the other files of the repository
+        if candidate != "HEAD" && !(candidate.starts_with("refs/") && check_ref_name(&candidate)) {
+            continue;
+        }
+        if let Some(hash) = read_ref(rgit_dir, &candidate)? {
+            return Ok(Some((candidate, hash)));
+        }
+    }
+    Ok(None)
+}
+
+/// Resolves `<name>@{<n>}`: the object the ref `name` pointed to `n` changes ago according to its
+/// reflog, 0 being its current value. An empty `name` stands for the current branch.
+fn resolve_reflog(rgit_dir: &Path, name: &str, n: usize) -> Result<ObjectId> {
+    let (ref_name, display_name) = if name.is_empty() {
+        match read_ref_target(rgit_dir, "HEAD")? {
+            Some(RefTarget::Symbolic(branch)) => {
+                let short = branch.trim_start_matches("refs/heads/").to_string();
+                (branch, short)
+            }
+            _ => ("HEAD".to_string(), "HEAD".to_string()),
+        }
+    } else {
+        match resolve_ref(rgit_dir, name)? {
+            Some((ref_name, _)) => (ref_name, name.to_string()),
+            None => return Err(not_a_valid_object_name(name)),
+        }
+    };
+
+    let reflog = read_reflog(rgit_dir, &ref_name)?;
+    match reflog.iter().rev().nth(n) {
+        Some(entry) => Ok(entry.new),
+        None if reflog.is_empty() => Err(RGitError::new(
+            format!("fatal: log for '{}' is empty", display_name),
+            128,
+        )),
+        None => Err(RGitError::new(
+            format!(
+                "fatal: log for '{}' only has {} entries",
+                display_name,
+                reflog.len()
+            ),
+            128,
+        )),
+    }
+}
+
+/// Returns the only object whose name starts with the hex digits `prefix`.
+fn resolve_prefix(db: &ObjectDatabase, prefix: &str) -> Result<ObjectId> {
+    let prefix = prefix.to_lowercase();
+    let candidates = db.find_prefix(&ObjectIdPrefix::from_hex(&prefix, db.format())?)?;
+
+    match candidates.as_slice() {
+        [] => Err(not_a_valid_object_name(&prefix)),
+        [hash] => Ok(*hash),
+        _ => Err(RGitError::AmbiguousName {
+            candidates: candidates
+                .iter()
+                .map(|hash| Ok((*hash, db.read_header(hash)?.object_type)))
+                .collect::<Result<_>>()?,
+            name: prefix,
+        }
+        .into()),
+    }
+}
+
+/// Dereferences tags until the object `hash` is not a tag anymore.
+pub fn peel_tags(db: &ObjectDatabase, mut hash: ObjectId) -> Result<ObjectId> {
+    while db.read_header(&hash)?.object_type == RGitObjectType::Tag {
+        hash = *Tag::from_rgit_objects(db, &hash)?.object();
+    }
+    Ok(hash)
+}
+
+/// Dereferences the object `hash`, named `name`, until it is of type `object_type`. Tags peel to
+/// the object they refer to and commits to their tree.
+pub fn peel(
+    db: &ObjectDatabase,
+    name: &str,
+    mut hash: ObjectId,
+    object_type: RGitObjectType,
+) -> Result<ObjectId> {
+    loop {
+        let actual_type = db.read_header(&hash)?.object_type;
+        match (actual_type, object_type) {
+            (actual_type, object_type) if actual_type == object_type => return Ok(hash),
+            (RGitObjectType::Tag, _) => hash = *Tag::from_rgit_objects(db, &hash)?.object(),
+            (RGitObjectType::Commit, RGitObjectType::Tree) => {
+                return Ok(*Commit::from_rgit_objects(db, &hash)?.tree())
+            }
+            _ => {
+                return Err(RGitError::new(
+                    format!(
+                        "fatal: {}: expected {} type, but the object dereferences to {} type",
+                        name, object_type, actual_type
+                    ),
+                    128,
+                ))
+            }
+        }
+    }
+}
+
+/// Returns the name of the branch, or the ID of the commit, that `HEAD` was on before the `n`-th
+/// last checkout according to its reflog, which is what `@{-<n>}` stands for.
+pub fn previous_checkout(rgit_dir: &Path, n: usize) -> Result<Option<String>> {
+    if n == 0 {
+        return Ok(None);
+    }
+    Ok(read_reflog(rgit_dir, "HEAD")?
+        .iter()
+        .rev()
+        .filter_map(|entry| {
+            let moves = entry.message.strip_prefix("checkout: moving from ")?;
+            Some(moves.split_once(" to ")?.0.to_string())
+        })
+        .nth(n - 1))
+}
+
+/// Resolves a name without any `^` or `~` suffix: an object ID or prefix, a ref, `@` for `HEAD`,
+/// `<ref>@{<n>}` or `@{-<n>}`.
+fn resolve_name(db: &ObjectDatabase, rgit_dir: &Path, name: &str) -> Result<ObjectId> {
+    if name == "@" {
+        return resolve_name(db, rgit_dir, "HEAD");
+    }
+    if let Some((base, n)) = name
+        .strip_suffix('}')
+        .and_then(|rest| rest.rsplit_once("@{"))
+    {
+        if let Some(n) = n.strip_prefix('-').and_then(|n| n.parse::<usize>().ok()) {
+            return match previous_checkout(rgit_dir, n)? {
+                Some(previous) if base.is_empty() => resolve_name(db, rgit_dir, &previous),
+                _ => Err(not_a_valid_object_name(name)),
+            };
+        }
+        if let Ok(n) = n.parse::<usize>() {
+            return resolve_reflog(rgit_dir, base, n);
+        }
+    }
+
+    let is_hex = name.chars().all(|c| c.is_ascii_hexdigit());
+    if is_hex && name.len() == db.format().raw_len() * 2 {
+        return ObjectId::from_hex(name);
+    }
+    if let Some((_, hash)) = resolve_ref(rgit_dir, name)? {
+        return Ok(hash);
+    }
+    if is_hex && name.len() >= MIN_ABBREV && name.len() < db.format().raw_len() * 2 {
+        return resolve_prefix(db, name);
+    }
+    Err(not_a_valid_object_name(name))
+}
+
+/// Resolves `:/<pattern>`: the most recent commit reachable from any ref whose message matches the
+/// regular expression `pattern`.
+fn resolve_message(
+    db: &ObjectDatabase,
+    rgit_dir: &Path,
+    name: &str,
+    pattern: &str,
+) -> Result<ObjectId> {
+    let regex = Regex::new(pattern).map_err(|err| {
+        RGitError::new(format!("fatal: invalid regex '{}': {}", pattern, err), 128)
+    })?;
+
+    let mut queue = BinaryHeap::new();
+    let mut seen = HashSet::new();
+    let mut push = |queue: &mut BinaryHeap<_>, hash: ObjectId| -> Result<()> {
+        if seen.insert(hash) {
+            let time = Commit::from_rgit_objects(db, &hash)?.time().timestamp();
+            queue.push((time, hash));
+        }
+        Ok(())
+    };
+    for (_, hash) in list_refs(rgit_dir)? {
+        let hash = peel_tags(db, hash)?;
+        if db.read_header(&hash)?.object_type == RGitObjectType::Commit {
+            push(&mut queue, hash)?;
+        }
+    }
+    // newest commit first, like git
+    while let Some((_, hash)) = queue.pop() {
+        let commit = Commit::from_rgit_objects(db, &hash)?;
+        if regex.is_match(&commit.commit_message) {
+            return Ok(hash);
+        }
+        for parent in commit.parents() {
+            push(&mut queue, *parent)?;
+        }
+    }
+    Err(not_a_valid_object_name(name))
+}
+
+/// Resolves `<rev>:<path>`: the object at `path` in the tree `tree`.
+fn resolve_path(db: &ObjectDatabase, rev: &str, tree: ObjectId, path: &str) -> Result<ObjectId> {
+    let mut hash = tree;
+    for component in path.split('/').filter(|component| !component.is_empty()) {
+        let entries = match db.read_header(&hash)?.object_type {
+            RGitObjectType::Tree => read_tree_entries(db, &hash)?,
+            _ => Vec::new(),
+        };
+        hash = entries
+            .into_iter()
+            .find(|(_, name, _)| name == component.as_bytes())
+            .map(|(_, _, hash)| hash)
+            .ok_or_else(|| {
+                RGitError::new(
+                    format!("fatal: path '{}' does not exist in '{}'", path, rev),
+                    128,
+                )
+            })?;
+    }
+    Ok(hash)
+}
+
+/// Resolves an object name to the ID of the object it names.
+///
+/// `name` is a full object ID, an unambiguous prefix of at least [`MIN_ABBREV`] hex digits, `HEAD`
+/// (or `@`) or the name of a ref. `<ref>@{<n>}` names the `n`-th previous value of a ref in its
+/// reflog, `@{<n>}` that of the current branch, and `@{-<n>}` the branch or commit checked out
+/// before the `n`-th last checkout.
+///
+/// Any number of suffixes can follow: `~<n>` goes back `n` generations through first parents,
+/// `^<n>` takes the `n`-th parent (`^0` being the commit itself), `~` and `^` alone count as 1.
+/// `^{<type>}` peels the object to that type and `^{}` peels tags until a non-tag object.
+///
+/// `<rev>:<path>` names the object at `path` in the tree of `<rev>`, and `:/<regex>` the most
+/// recent commit reachable from a ref whose message matches `regex`.
+pub fn resolve_revision(db: &ObjectDatabase, rgit_dir: &Path, name: &str) -> Result<ObjectId> {
+    if let Some(pattern) = name.strip_prefix(":/") {
+        return resolve_message(db, rgit_dir, name, pattern);
+    }
+    if let Some((rev, path)) = name.split_once(':') {
+        if rev.is_empty() {
+            // `:<path>` names a file of the index, which rgit does not have
+            return Err(not_a_valid_object_name(name));
+        }
+        let hash = resolve_revision(db, rgit_dir, rev)?;
+        let tree = peel(db, rev, hash, RGitObjectType::Tree)?;
+        return resolve_path(db, rev, tree, path);
+    }
+
+    // ref names can't contain `^` or `~`, the suffixes start at the first one
+    let (base, mut suffixes) = name.split_at(name.find(['^', '~']).unwrap_or(name.len()));
+    let mut hash = resolve_name(db, rgit_dir, base)?;
+    while !suffixes.is_empty() {
+        if let Some(rest) = suffixes.strip_prefix("^{") {
+            let end = rest
+                .find('}')
+                .ok_or_else(|| not_a_valid_object_name(name))?;
+            suffixes = &rest[end + 1..];
+            let resolved = &name[..name.len() - suffixes.len()];
+            hash = match &rest[..end] {
+                "" => peel_tags(db, hash)?,
+                object_type => {
+                    let object_type = object_type
+                        .parse::<RGitObjectType>()
+                        .map_err(|_| not_a_valid_object_name(name))?;
+                    peel(db, resolved, hash, object_type)?
+                }
+            };
+            continue;
+        }
+
+        let operator = match suffixes.chars().next() {
+            Some(operator @ ('^' | '~')) => operator,
+            _ => return Err(not_a_valid_object_name(name)),
+        };
+        let rest = &suffixes[1..];
+        let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+        let n = match &rest[..digits] {
+            "" => 1,
+            n => n
+                .parse::<usize>()
+                .map_err(|_| not_a_valid_object_name(name))?,
+        };
+        suffixes = &rest[digits..];
+        let resolved = &name[..name.len() - suffixes.len()];
+
+        hash = peel(db, resolved, hash, RGitObjectType::Commit)?;
+        if operator == '^' {
+            if n > 0 {
+                let commit = Commit::from_rgit_objects(db, &hash)?;
+                hash = *commit
+                    .parents()
+                    .get(n - 1)
+                    .ok_or_else(|| not_a_valid_object_name(name))?;
+            }
+        } else {
+            for _ in 0..n {
+                let commit = Commit::from_rgit_objects(db, &hash)?;
+                hash = *commit
+                    .parents()
+                    .first()
+                    .ok_or_else(|| not_a_valid_object_name(name))?;
+            }
+        }
+    }
+    Ok(hash)
+}
+
+/// An object given on the command line, `excluded` when it names commits to leave out such as
+/// `^<rev>` or the `A` of `A..B`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RevisionArg {
+    pub hash: ObjectId,
+    pub excluded: bool,
+}
+
+/// Resolves a command line argument that can be a revision, `^<rev>` or a range: `A..B` stands
+/// for `B ^A`, and `A...B` for `B A` and the exclusion of their merge bases. A missing end of a
+/// range defaults to `HEAD`.
+pub fn resolve_revision_arg(
+    db: &ObjectDatabase,
+    rgit_dir: &Path,
+    arg: &str,
+) -> Result<Vec<RevisionArg>> {
+    let included = |hash| RevisionArg {
+        hash,
+        excluded: false,
+    };
+    let excluded = |hash| RevisionArg {
+        hash,
+        excluded: true,
+    };
+    // paths and messages may contain dots
+    if arg.starts_with(":/") || arg.contains(':') {
+        return Ok(vec![included(resolve_revision(db, rgit_dir, arg)?)]);
+    }
+    let side = |name: &str| match name {
+        "" => resolve_revision(db, rgit_dir, "HEAD"),
+        name => resolve_revision(db, rgit_dir, name),
+    };
+
+    if let Some((a, b)) = arg.split_once("...") {
+        let (a_hash, b_hash) = (side(a)?, side(b)?);
+        let mut args = vec![included(b_hash), included(a_hash)];
+        let a_commit = peel(db, a, a_hash, RGitObjectType::Commit)?;
+        let b_commit = peel(db, b, b_hash, RGitObjectType::Commit)?;
+        args.extend(
+            merge_bases(db, &a_commit, &b_commit)?
+                .into_iter()
+                .map(excluded),
+        );
+        return Ok(args);
+    }
+    if let Some((a, b)) = arg.split_once("..") {
+        return Ok(vec![included(side(b)?), excluded(side(a)?)]);
+    }
+    match arg.strip_prefix('^') {
+        Some(rev) => Ok(vec![excluded(resolve_revision(db, rgit_dir, rev)?)]),
+        None => Ok(vec![included(resolve_revision(db, rgit_dir, arg)?)]),
+    }
+}
+
+/// Returns the shortest prefix of at least [`DEFAULT_ABBREV`] hex digits that names only `hash`.
+pub fn abbreviate(db: &ObjectDatabase, hash: &ObjectId) -> Result<String> {
+    abbreviate_to(db, hash, DEFAULT_ABBREV)
+}
+
+/// Returns the shortest prefix of at least `min_len` hex digits, and no less than [`MIN_ABBREV`],
+/// that names only `hash`.
+pub fn abbreviate_to(db: &ObjectDatabase, hash: &ObjectId, min_len: usize) -> Result<String> {
+    let mut len = min_len.max(MIN_ABBREV);
+    // only the objects sharing the shortest acceptable prefix can make it longer
+    for other in db.find_prefix(&ObjectIdPrefix::of(hash, len))? {
+        if other != *hash {
+            len = len.max(common_hex_digits(hash, &other) + 1);
+        }
+    }
+    Ok(ObjectIdPrefix::of(hash, len).to_string())
+}
+
+/// The number of hex digits `a` and `b` start with in common.
+fn common_hex_digits(a: &ObjectId, b: &ObjectId) -> usize {
+    let bytes = a
+        .as_bytes()
+        .iter()
+        .zip(b.as_bytes())
+        .take_while(|(a, b)| a == b)
+        .count();
+    match (a.as_bytes().get(bytes), b.as_bytes().get(bytes)) {
+        (Some(a), Some(b)) if a >> 4 == b >> 4 => bytes * 2 + 1,
+        _ => bytes * 2,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hash::ObjectFormat;
+    use crate::objects::{Blob, Tree};
+    use crate::refs::write_ref;
+    use crate::utils::init_rgit_dir;
+    use crate::Repository;
+    use std::fs;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_resolve_revision() {
+        let dir = tempdir().unwrap();
+        let rgit_dir = init_rgit_dir(dir.path()).unwrap();
+        let db = ObjectDatabase::open(&rgit_dir).unwrap();
+        let result = resolve_revision(&db, &rgit_dir, "HEAD");
+        assert!(result.unwrap_err().to_string().contains("HEAD"));
+
+        fs::write(dir.path().join("file"), "file content").unwrap();
+        let tree = Tree::from_directory(dir.path(), ObjectFormat::Sha1).unwrap();
+        tree.write_to_rgit_objects(&db).unwrap();
+        let commit = Commit::new(*tree.hash(), Vec::new(), "commit".to_string()).unwrap();
+        let commit_hash = commit.write_to_rgit_objects(&db).unwrap();
+        fs::write(rgit_dir.join("HEAD"), hex::encode(commit_hash)).unwrap();
+        fs::create_dir_all(rgit_dir.join("refs/tags")).unwrap();
+        fs::write(rgit_dir.join("refs/tags/v1"), hex::encode(tree.hash())).unwrap();
+
+        let resolve = |name: &str| resolve_revision(&db, &rgit_dir, name);
+        assert_eq!(resolve("HEAD").unwrap(), commit_hash);
+        assert_eq!(resolve("v1").unwrap(), *tree.hash());
+        assert_eq!(resolve("tags/v1").unwrap(), *tree.hash());
+        assert_eq!(resolve("refs/tags/v1").unwrap(), *tree.hash());
+        assert_eq!(resolve(&commit_hash.to_string()).unwrap(), commit_hash);
+        assert_eq!(resolve(&commit_hash.to_string()[..4]).unwrap(), commit_hash);
+        assert_eq!(
+            resolve(&commit_hash.to_string()[..7].to_uppercase()).unwrap(),
+            commit_hash
+        );
+        assert!(resolve(&commit_hash.to_string()[..3]).is_err());
+        assert!(resolve("v2").is_err());
+        // other files of the repository are not refs
+        assert_eq!(resolve_ref(&rgit_dir, "config").unwrap(), None);
+        assert_eq!(resolve_ref(&rgit_dir, "tags/../../HEAD").unwrap(), None);
+        assert_eq!(
+            resolve_ref(&rgit_dir, "v1").unwrap(),
+            Some(("refs/tags/v1".to_string(), *tree.hash()))
+        );
+
+        assert_eq!(resolve("HEAD^{tree}").unwrap(), *tree.hash());
+        assert_eq!(resolve("HEAD^{commit}^{tree}").unwrap(), *tree.hash());
+        assert_eq!(resolve("HEAD^{}").unwrap(), commit_hash);
+        assert_eq!(resolve("v1^{tree}").unwrap(), *tree.hash());
+        let result = resolve("v1^{commit}");
+        assert!(result.unwrap_err().to_string().contains(
+            "v1^{commit}: expected commit type, but the object dereferences to tree type"
+        ));
+        assert!(resolve("HEAD^{tag}").is_err());
+    }
+
+    #[test]
+    fn test_revision_suffixes() {
+        let dir = tempdir().unwrap();
+        let path = dir.path();
+        let repo = Repository::init(path, None).unwrap();
+        let (db, rgit_dir) = (repo.db(), repo.rgit_dir());
+        fs::create_dir(path.join("dir")).unwrap();
+        fs::write(path.join("dir/file"), "first").unwrap();
+        let first = repo.commit("first: add dir/file").unwrap();
+        fs::write(path.join("dir/file"), "second").unwrap();
+        let second = repo.commit("second").unwrap();
+
+        // a merge of `second` and a commit on top of `first`
+        let tree = *Commit::from_rgit_objects(db, &second).unwrap().tree();
+        let side = Commit::new(tree, vec![first], "side".to_string())
+            .unwrap()
+            .write_to_rgit_objects(db)
+            .unwrap();
+        let merge = Commit::new(tree, vec![second, side], "merge".to_string())
+            .unwrap()
+            .write_to_rgit_objects(db)
+            .unwrap();
+        write_ref(rgit_dir, "refs/heads/main", &merge).unwrap();
+
+        let resolve = |name: &str| resolve_revision(db, rgit_dir, name);
+        assert_eq!(resolve("@").unwrap(), merge);
+        assert_eq!(resolve("HEAD^0").unwrap(), merge);
+        assert_eq!(resolve("HEAD^").unwrap(), second);
+        assert_eq!(resolve("HEAD^1").unwrap(), second);
+        assert_eq!(resolve("HEAD^2").unwrap(), side);
+        assert_eq!(resolve("HEAD~").unwrap(), second);
+        assert_eq!(resolve("HEAD~2").unwrap(), first);
+        assert_eq!(resolve("HEAD^^").unwrap(), first);
+        assert_eq!(resolve("main^2~1").unwrap(), first);
+        assert_eq!(resolve("HEAD~1^{tree}").unwrap(), tree);
+        assert_eq!(
+            resolve(&format!("{}~1", &merge.to_string()[..7])).unwrap(),
+            second
+        );
+        assert!(matches!(
+            resolve("HEAD^3").unwrap_err().downcast_ref(),
+            Some(RGitError::InvalidRef { name }) if name == "HEAD^3"
+        ));
+        assert!(resolve("HEAD~3").is_err());
+        assert!(resolve("HEAD^{tree}~1").is_err());
+        assert!(resolve("HEAD^{}é").is_err());
+        assert!(matches!(
+            resolve("HEAD^{commit}x").unwrap_err().downcast_ref(),
+            Some(RGitError::InvalidRef { name }) if name == "HEAD^{commit}x"
+        ));
+
+        let blob = resolve("HEAD~2:dir/file").unwrap();
+        assert_eq!(repo.read_object(&blob).unwrap().content, b"first");
+        assert_eq!(resolve("HEAD:").unwrap(), tree);
+        assert_eq!(
+            resolve("HEAD:dir").unwrap(),
+            read_tree_entries(db, &tree).unwrap()[0].2
+        );
+        let err = resolve("HEAD:nope").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "fatal: path 'nope' does not exist in 'HEAD'"
+        );
+        assert!(resolve("HEAD:dir/file/nope").is_err());
+
+        assert_eq!(resolve(":/^side").unwrap(), side);
+        assert_eq!(resolve(":/dir/file").unwrap(), first);
+        // the youngest match wins
+        assert_eq!(resolve(":/e").unwrap(), merge);
+        assert!(resolve(":/nope").is_err());
+        assert!(resolve(":/(").is_err());
+
+        let args = resolve_revision_arg(db, rgit_dir, "HEAD~2..").unwrap();
+        assert_eq!(
+            args,
+            [
+                RevisionArg {
+                    hash: merge,
+                    excluded: false
+                },
+                RevisionArg {
+                    hash: first,
+                    excluded: true
+                },
+            ]
+        );
+        let args = resolve_revision_arg(db, rgit_dir, "HEAD^1...HEAD^2").unwrap();
+        assert_eq!(
+            args,
+            [
+                RevisionArg {
+                    hash: side,
+                    excluded: false
+                },
+                RevisionArg {
+                    hash: second,
+                    excluded: false
+                },
+                RevisionArg {
+                    hash: first,
+                    excluded: true
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_previous_checkout() {
+        let dir = tempdir().unwrap();
+        let repo = Repository::init(dir.path(), None).unwrap();
+        let commit = repo.commit("first").unwrap();
+        assert_eq!(previous_checkout(repo.rgit_dir(), 1).unwrap(), None);
+        assert!(resolve_revision(repo.db(), repo.rgit_dir(), "@{-1}").is_err());
+
+        let topic = RefTarget::Symbolic("refs/heads/topic".to_string());
+        write_ref(repo.rgit_dir(), "refs/heads/topic", &commit).unwrap();
+        repo.set_head(&topic, "checkout: moving from main to topic")
+            .unwrap();
+        repo.set_head(
+            &RefTarget::Direct(commit),
+            &format!("checkout: moving from topic to {}", commit),
+        )
+        .unwrap();
+        let previous = |n| previous_checkout(repo.rgit_dir(), n).unwrap();
+        assert_eq!(previous(0), None);
+        assert_eq!(previous(1), Some("topic".to_string()));
+        assert_eq!(previous(2), Some("main".to_string()));
+        assert_eq!(previous(3), None);
+        assert_eq!(
+            resolve_revision(repo.db(), repo.rgit_dir(), "@{-2}~0").unwrap(),
+            commit
+        );
+    }
+
+    #[test]
+    fn test_ambiguous_prefix() {
+        let dir = tempdir().unwrap();
+        let rgit_dir = init_rgit_dir(dir.path()).unwrap();
+        let db = ObjectDatabase::open(&rgit_dir).unwrap();
+
+        // write blobs until two of them share their first 4 hex digits
+        let mut blobs = Vec::<Blob>::new();
+        let (first, second) = loop {
+            fs::write(dir.path().join("file"), blobs.len().to_string()).unwrap();
+            let blob = Blob::from_file(&dir.path().join("file"), ObjectFormat::Sha1).unwrap();
+            blob.write_to_rgit_objects(&db).unwrap();
+            let prefix = &hex::encode(blob.hash())[..4];
+            if let Some(other) = blobs
+                .iter()
+                .find(|other| hex::encode(other.hash()).starts_with(prefix))
+            {
+                break (*other.hash(), *blob.hash());
+            }
+            blobs.push(blob);
+        };
+
+        let prefix = &first.to_string()[..4];
+        let message = resolve_revision(&db, &rgit_dir, prefix)
+            .unwrap_err()
+            .to_string();
+        assert!(message.contains(&format!("short object ID {} is ambiguous", prefix)));
+        assert!(message.contains(&format!("hint:   {} blob", first)));
+        assert!(message.contains(&format!("hint:   {} blob", second)));
+
+        let abbrev = abbreviate(&db, &first).unwrap();
+        assert!(abbrev.len() >= DEFAULT_ABBREV);
+        assert_eq!(resolve_revision(&db, &rgit_dir, &abbrev).unwrap(), first);
+        let common = first
+            .to_string()
+            .chars()
+            .zip(second.to_string().chars())
+            .take_while(|(a, b)| a == b)
+            .count();
+        assert_eq!(abbrev.len(), DEFAULT_ABBREV.max(common + 1));
+    }
+}
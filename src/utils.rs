@@ -1,12 +1,15 @@
 use crate::error::RGitError;
+use crate::refs::{write_symbolic_ref, DEFAULT_BRANCH};
 use anyhow::Result;
 use std::fs;
 use std::path::{Path, PathBuf};
 
+/// Creates the `.rgit` directory of `root`, with a `HEAD` on the unborn [`DEFAULT_BRANCH`].
 pub fn init_rgit_dir(root: &Path) -> Result<PathBuf> {
     let rgit_dir = root.join(".rgit");
     if fs::metadata(&rgit_dir).is_err() {
         fs::create_dir(&rgit_dir)?;
+        write_symbolic_ref(&rgit_dir, "HEAD", &format!("refs/heads/{}", DEFAULT_BRANCH))?;
     }
     Ok(rgit_dir)
 }
@@ -23,43 +26,43 @@ pub fn get_rgit_dir(dir: &Path) -> Result<PathBuf> {
             return Ok(rgit_dir);
         }
         if !dir.pop() {
-            return Err(RGitError::new(
-                "fatal: not a rgit repository (or any of the parent directories): .rgit"
-                    .to_string(),
-                128,
-            ));
+            return Err(RGitError::NotARepository { path: None }.into());
         }
     }
 }
 
-pub fn get_rgit_object_path(
-    rgit_dir: &Path,
-    hash: &[u8; 20],
-    check_exists: bool,
-) -> Result<PathBuf> {
-    if fs::metadata(rgit_dir).is_err() || rgit_dir.is_file() {
-        return Err(RGitError::new(
-            "fatal: not a rgit repository (or any of the parent directories): .rgit".to_string(),
-            128,
-        ));
+/// Quotes a path like git does with `core.quotePath`: names with control characters, `"`, `\`
+/// or non-ASCII bytes are wrapped in double quotes with C-style escapes, the others are kept as
+/// is.
+pub fn quote_path(name: &[u8]) -> String {
+    let needs_quoting = |byte: u8| !(0x20..0x7f).contains(&byte) || byte == b'"' || byte == b'\\';
+    if !name.iter().any(|&byte| needs_quoting(byte)) {
+        return String::from_utf8_lossy(name).into_owned();
     }
 
-    let hash = hex::encode(hash);
-    let object_path = rgit_dir.join("objects").join(&hash[..2]).join(&hash[2..]);
-    if check_exists && fs::metadata(&object_path).is_err() {
-        return Err(RGitError::new(
-            format!("fatal: Not a valid object name {}", hash),
-            128,
-        ));
+    let mut quoted = String::from("\"");
+    for &byte in name {
+        match byte {
+            b'\x07' => quoted.push_str("\\a"),
+            b'\x08' => quoted.push_str("\\b"),
+            b'\t' => quoted.push_str("\\t"),
+            b'\n' => quoted.push_str("\\n"),
+            b'\x0b' => quoted.push_str("\\v"),
+            b'\x0c' => quoted.push_str("\\f"),
+            b'\r' => quoted.push_str("\\r"),
+            b'"' => quoted.push_str("\\\""),
+            b'\\' => quoted.push_str("\\\\"),
+            byte if needs_quoting(byte) => quoted.push_str(&format!("\\{:03o}", byte)),
+            byte => quoted.push(byte as char),
+        }
     }
-    Ok(object_path)
+    quoted.push('"');
+    quoted
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use rand::Rng;
-    use std::fs;
     use tempfile::tempdir;
 
     #[test]
@@ -82,52 +85,13 @@ mod tests {
     }
 
     #[test]
-    fn test_get_rgit_object_path() {
-        let temp_dir = tempdir().unwrap();
-        let rgit_dir = temp_dir.path().join(".rgit");
-
-        fs::write(temp_dir.path().join(".rgit"), "").unwrap();
-        let result = get_rgit_object_path(rgit_dir.as_path(), &[0u8; 20], false);
-        assert!(result.is_err());
-        assert!(result
-            .unwrap_err()
-            .to_string()
-            .contains("not a rgit repository"));
-        fs::remove_file(temp_dir.path().join(".rgit")).unwrap();
-
-        let result = get_rgit_object_path(rgit_dir.as_path(), &[0u8; 20], false);
-        assert!(result.is_err());
-        assert!(result
-            .unwrap_err()
-            .to_string()
-            .contains("not a rgit repository"));
-
-        let objects_dir = rgit_dir.join("objects");
-        fs::create_dir_all(&objects_dir).unwrap();
-
-        let mut rng = rand::thread_rng();
-        let hash: [u8; 20] = rng.gen();
-        let hash_str = hex::encode(&hash);
-        let object_path = objects_dir.join(&hash_str[..2]).join(&hash_str[2..]);
-
-        let result = get_rgit_object_path(rgit_dir.as_path(), &hash, false);
-        assert!(result.is_ok());
-        assert_eq!(result.unwrap(), object_path);
-
-        fs::create_dir_all(object_path.parent().unwrap()).unwrap();
-        fs::File::create(&object_path).unwrap();
-
-        let result = get_rgit_object_path(rgit_dir.as_path(), &hash, true);
-        assert!(result.is_ok());
-        assert_eq!(result.unwrap(), object_path);
-
-        let result = get_rgit_object_path(rgit_dir.as_path(), &[0u8; 20], true);
-        assert!(result.is_err());
-        assert!(result
-            .unwrap_err()
-            .to_string()
-            .contains("Not a valid object name"));
-
-        temp_dir.close().unwrap();
+    fn test_quote_path() {
+        assert_eq!(quote_path(b"dir/file name.txt"), "dir/file name.txt");
+        assert_eq!(quote_path("café".as_bytes()), "\"caf\\303\\251\"");
+        assert_eq!(quote_path(b"caf\xe9"), "\"caf\\351\"");
+        assert_eq!(
+            quote_path(b"a\tb\n\"c\"\\\x01\x7f"),
+            "\"a\\tb\\n\\\"c\\\"\\\\\\001\\177\""
+        );
     }
 }
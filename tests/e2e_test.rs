@@ -24,6 +24,8 @@ fn test_rgit_end_to_end() {
         .assert()
         .success();
     let hash = from_utf8(&result.get_output().stdout).unwrap().trim();
+    // same object ID as `git hash-object test.txt`
+    assert_eq!(hash, "b45ef6fec89518d314f546fd6c3025367b721684");
 
     let result = rgit_command()
         .current_dir(dir.path())
@@ -98,4 +100,176 @@ fn test_rgit_end_to_end() {
     let commit_content = from_utf8(&result.get_output().stdout).unwrap().trim();
     assert!(commit_content.starts_with("[commit"));
     assert!(commit_content.contains("Initial commit"));
+
+    // the abbreviated commit ID can be passed back
+    let commit_prefix = commit_content
+        .trim_start_matches("[commit ")
+        .split(']')
+        .next()
+        .unwrap();
+    let result = rgit_command()
+        .current_dir(dir.path())
+        .args(["cat-file", "-t", commit_prefix])
+        .assert()
+        .success();
+    assert_eq!(from_utf8(&result.get_output().stdout).unwrap(), "commit\n");
+
+    fs::remove_file(dir.path().join("test.txt")).unwrap();
+    rgit_command()
+        .current_dir(dir.path())
+        .args(["read-tree", "HEAD"])
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(dir.path().join("test.txt")).unwrap(),
+        "Hello, World!"
+    );
+
+    rgit_command()
+        .current_dir(dir.path())
+        .env("RGIT_COMMITTER_NAME", "A U Thor")
+        .env("RGIT_COMMITTER_EMAIL", "author@example.com")
+        .args(["tag", "-a", "v1.0", "-m", "Release 1.0"])
+        .assert()
+        .success();
+    let result = rgit_command()
+        .current_dir(dir.path())
+        .args(["cat-file", "-p", "v1.0"])
+        .assert()
+        .success();
+    let tag_content = from_utf8(&result.get_output().stdout).unwrap();
+    assert!(tag_content.starts_with("object "));
+    assert!(tag_content.contains("\ntype commit\ntag v1.0\ntagger A U Thor <author@example.com> "));
+    assert!(tag_content.ends_with("\n\nRelease 1.0\n"));
+    let result = rgit_command()
+        .current_dir(dir.path())
+        .args(["tag"])
+        .assert()
+        .success();
+    assert_eq!(from_utf8(&result.get_output().stdout).unwrap(), "v1.0\n");
+    rgit_command()
+        .current_dir(dir.path())
+        .args(["gc"])
+        .assert()
+        .success();
+    // only the tree of the first write-tree is left dangling
+    let result = rgit_command()
+        .current_dir(dir.path())
+        .args(["fsck"])
+        .assert()
+        .success();
+    let fsck_output = from_utf8(&result.get_output().stdout).unwrap();
+    assert!(fsck_output
+        .lines()
+        .all(|line| line.starts_with("dangling tree")));
+}
+
+#[test]
+fn test_rgit_sha256_repository() {
+    let dir = tempfile::tempdir().unwrap();
+    rgit_command()
+        .current_dir(dir.path())
+        .args(["init", "--object-format", "sha256"])
+        .assert()
+        .success();
+
+    fs::write(dir.path().join("test.txt"), "Hello, World!").unwrap();
+    let result = rgit_command()
+        .current_dir(dir.path())
+        .args(["hash-object", "-w", "test.txt"])
+        .assert()
+        .success();
+    let hash = from_utf8(&result.get_output().stdout).unwrap().trim();
+    // same object ID as `git hash-object test.txt` in a `--object-format=sha256` repository
+    assert_eq!(
+        hash,
+        "e118a058f018dda253bb692320c940091b15e4f19067e12fff110606a111f5da"
+    );
+
+    let result = rgit_command()
+        .current_dir(dir.path())
+        .args(["write-tree"])
+        .assert()
+        .success();
+    let tree_hash = from_utf8(&result.get_output().stdout).unwrap().trim();
+    assert_eq!(
+        tree_hash,
+        "9e08268e26d40b69a039b9be4e9852ff15af7ed6002247aee47075eecb0fd358"
+    );
+
+    rgit_command()
+        .current_dir(dir.path())
+        .args(["commit", "-m", "Initial commit"])
+        .assert()
+        .success();
+    rgit_command()
+        .current_dir(dir.path())
+        .args(["repack"])
+        .assert()
+        .success();
+    rgit_command()
+        .current_dir(dir.path())
+        .args(["fsck"])
+        .assert()
+        .success()
+        .stdout("");
+    let result = rgit_command()
+        .current_dir(dir.path())
+        .args(["cat-file", "-p", hash])
+        .assert()
+        .success();
+    assert_eq!(
+        from_utf8(&result.get_output().stdout).unwrap().trim(),
+        "Hello, World!"
+    );
+
+    // SHA-1 names are only prefixes of SHA-256 ones
+    let result = rgit_command()
+        .current_dir(dir.path())
+        .args(["cat-file", "-p", "b45ef6fec89518d314f546fd6c3025367b721684"])
+        .assert()
+        .code(128);
+    assert!(from_utf8(&result.get_output().stderr)
+        .unwrap()
+        .contains("Not a valid object name"));
+    rgit_command()
+        .current_dir(dir.path())
+        .args(["init", "--object-format", "sha1"])
+        .assert()
+        .code(128);
+
+    // I/O errors are fatal too
+    let result = rgit_command()
+        .current_dir(dir.path())
+        .args(["hash-object", "nonexistent"])
+        .assert()
+        .code(128);
+    assert!(from_utf8(&result.get_output().stderr)
+        .unwrap()
+        .starts_with("fatal: No such file or directory"));
+}
+
+#[test]
+fn test_rgit_library() {
+    // a repository written through the library is readable by the command line
+    let dir = tempfile::tempdir().unwrap();
+    let repo = rgit::Repository::init(dir.path(), None).unwrap();
+    fs::write(dir.path().join("test.txt"), "Hello, World!").unwrap();
+    let commit = repo.commit("Initial commit").unwrap();
+
+    let result = rgit_command()
+        .current_dir(dir.path())
+        .args(["cat-file", "-p", "HEAD^{tree}"])
+        .assert()
+        .success();
+    assert_eq!(
+        from_utf8(&result.get_output().stdout).unwrap(),
+        "100644 blob b45ef6fec89518d314f546fd6c3025367b721684\ttest.txt\n"
+    );
+    let result = rgit_command()
+        .current_dir(dir.path())
+        .args(["cat-file", "-t", &commit.to_string()])
+        .assert()
+        .success();
+    assert_eq!(from_utf8(&result.get_output().stdout).unwrap(), "commit\n");
 }